@@ -5,8 +5,7 @@ use rlox::compiler::parse::ast;
 use rlox::compiler::parse::parser::{parse_res, ParseError};
 
 fuzz_target!(|data: ast::FuzzStatements| {
-    let mut stderr = vec![];
-    match parse_res(&data.to_string(), &mut stderr) {
+    match parse_res(&data.to_string()) {
         Ok(_) => {},
         Err(ParseError::AssignmentDepth { .. }) => {},
         Err(e) => panic!("{e:?}"),
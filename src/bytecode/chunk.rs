@@ -2,7 +2,7 @@ use std::io::Write;
 
 use num_enum::{FromPrimitive, IntoPrimitive};
 
-use crate::common::ui::Span;
+use crate::common::ui::{SourceMap, Span};
 use crate::value::Value;
 use crate::{bytecode::interner::Interner, common::try_as::TryAs, value::function::ObjFunction};
 
@@ -17,11 +17,18 @@ pub enum OpCode {
     // 1 follow bytes ====
     Constant, // 1: a constant index
     Call,
+    TailCall,
+    CollectRest, // 1: the number of parameters before rest
+    GetModule,   // 1: a module index
+    GetProperty, // 1: a constant index for the name
     // 2 follow bytes ====
+    Module, // 1: a module index
     JumpRelIfFalse,
     JumpRelIfTrue,
+    JumpRelIfNotNil,
     JumpRel,
     Loop,
+    PushHandler, // 2: the offset to the catch block
     // variable-length
     Closure,
     // No follow bytes but data-dependent
@@ -31,8 +38,11 @@ pub enum OpCode {
     Print,
     Pop,
     CloseUpvalue,
+    PopHandler,
+    Throw,
     GetGlobal,
     DefineGlobal,
+    DefineConstGlobal,
     SetGlobal,
     GetLocal,
     SetLocal,
@@ -50,6 +60,13 @@ pub enum OpCode {
     Invalid,
 }
 
+/// An imported module, and the global slot behind each of its exports
+#[derive(Default, Debug, Clone)]
+pub struct ModuleInfo {
+    pub name: String,
+    pub exports: Vec<(String, u8)>,
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct Chunk {
     // INVARIANT: An OpCode must be followed by however many bytes are specified
@@ -60,6 +77,8 @@ pub struct Chunk {
     // Owned by this
     pub globals: Interner,
     pub native_globals: Vec<(u8, Value)>,
+    /// Every imported module, by index
+    pub modules: Vec<ModuleInfo>,
 }
 
 impl Drop for Chunk {
@@ -94,6 +113,15 @@ impl Chunk {
         index.try_into().expect("Too many constants")
     }
 
+    pub fn add_module(&mut self, name: &str) -> u8 {
+        self.modules.push(ModuleInfo {
+            name: name.to_owned(),
+            exports: vec![],
+        });
+        let index = self.modules.len() - 1;
+        index.try_into().expect("Too many modules")
+    }
+
     pub fn get_constant(&self, index: u8) -> Value {
         self.constants[index as usize]
     }
@@ -103,11 +131,11 @@ impl Chunk {
         self.spans.push(origin);
     }
 
-    pub fn disassemble(&self, name: &str, source: &str, mut stdout: impl Write) {
+    pub fn disassemble(&self, name: &str, sources: &SourceMap, mut stdout: impl Write) {
         writeln!(stdout, "==== {name} ====").unwrap();
         let mut i = 0;
         while i < self.instructions.len() {
-            i = self.disassemble_instruction(i, source, &mut stdout);
+            i = self.disassemble_instruction(i, sources, &mut stdout);
         }
     }
 
@@ -136,6 +164,13 @@ impl Chunk {
         *offset += 2;
     }
 
+    fn module_instruction(&self, name: &str, offset: &mut usize, mut stdout: impl Write) {
+        let index = self.instructions[*offset + 1];
+        let module = &self.modules[index as usize].name;
+        writeln!(stdout, "{name:<16} {index:>4} '{module}'").unwrap();
+        *offset += 2;
+    }
+
     fn jmp_instruction(&self, name: &str, offset: &mut usize, mut stdout: impl Write) {
         let value = &self.instructions[*offset + 1..][..2];
         let addr: u16 = bytemuck::pod_read_unaligned(value);
@@ -168,14 +203,14 @@ impl Chunk {
     pub fn disassemble_instruction(
         &self,
         mut offset: usize,
-        source: &str,
+        sources: &SourceMap,
         mut stdout: impl Write,
     ) -> usize {
         write!(stdout, "{:0>4} ", offset).unwrap();
         if offset > 0 && self.spans[offset] == self.spans[offset - 1] {
             write!(stdout, "{:<8}", "|").unwrap();
         } else {
-            let snippet = &source[self.spans[offset]];
+            let snippet = &sources[self.spans[offset]];
             let snippet = &snippet[snippet.len().saturating_sub(7)..];
             write!(stdout, "{:<8}", snippet).unwrap();
         }
@@ -202,7 +237,12 @@ impl Chunk {
             OpCode::Print => simple("PRINT"),
             OpCode::Pop => simple("POP"),
             OpCode::CloseUpvalue => simple("CLOSE_UPVALUE"),
+            OpCode::PopHandler => simple("POP_HANDLER"),
+            OpCode::Throw => simple("THROW"),
             OpCode::DefineGlobal => self.global_instruction("DEFINE_GLOBAL", &mut offset, stdout),
+            OpCode::DefineConstGlobal => {
+                self.global_instruction("DEFINE_CONST_GLOBAL", &mut offset, stdout)
+            }
             OpCode::GetGlobal => self.global_instruction("GET_GLOBAL", &mut offset, stdout),
             OpCode::SetGlobal => self.global_instruction("SET_GLOBAL", &mut offset, stdout),
             OpCode::SetLocal => self.byte_instruction("SET_LOCAL", &mut offset, stdout),
@@ -210,12 +250,21 @@ impl Chunk {
             OpCode::SetUpvalue => self.byte_instruction("SET_UPVALUE", &mut offset, stdout),
             OpCode::GetUpvalue => self.byte_instruction("GET_UPVALUE", &mut offset, stdout),
             OpCode::Call => self.byte_instruction("CALL", &mut offset, stdout),
+            OpCode::TailCall => self.byte_instruction("TAIL_CALL", &mut offset, stdout),
+            OpCode::CollectRest => self.byte_instruction("COLLECT_REST", &mut offset, stdout),
+            OpCode::GetModule => self.module_instruction("GET_MODULE", &mut offset, stdout),
+            OpCode::Module => self.module_instruction("MODULE", &mut offset, stdout),
+            OpCode::GetProperty => self.constant_instruction("GET_PROPERTY", &mut offset, stdout),
             OpCode::JumpRelIfFalse => {
                 self.jmp_instruction("JUMP_REL_IF_FALSE", &mut offset, stdout)
             }
             OpCode::JumpRelIfTrue => self.jmp_instruction("JUMP_REL_IF_TRUE", &mut offset, stdout),
+            OpCode::JumpRelIfNotNil => {
+                self.jmp_instruction("JUMP_REL_IF_NOT_NIL", &mut offset, stdout)
+            }
             OpCode::JumpRel => self.jmp_instruction("JUMP_REL", &mut offset, stdout),
             OpCode::Loop => self.jmp_instruction("LOOP", &mut offset, stdout),
+            OpCode::PushHandler => self.jmp_instruction("PUSH_HANDLER", &mut offset, stdout),
             OpCode::Invalid => {
                 writeln!(stdout, "INVALID OPCODE: {chunk}").unwrap();
                 offset += 1;
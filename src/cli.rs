@@ -0,0 +1,329 @@
+use std::{str::FromStr, time::Duration};
+
+use crate::vm::config::VMConfig;
+
+pub const USAGE: &str = "\
+Usage: rlox [options] <filename>
+       rlox --explain <code>
+       rlox lsp            Run a language server over stdin and stdout
+       rlox fmt [--check] <filename>...
+                           Format files in place, or with --check, list the ones that aren't
+
+Options:
+    --max-frames <n>    Maximum depth of nested function calls
+    --stack-size <n>    Number of values the stack can hold
+    --grow-stack        Grow the stack instead of overflowing it
+    --fuel <n>          Maximum number of instructions to execute
+    --timeout <ms>      Maximum number of milliseconds to run for
+    --max-memory <n>    Maximum number of bytes the heap can hold
+    --gc-growth-factor <f>
+                        How much the heap may grow between collections, at least 1
+    --gc-min-heap <n>   Number of bytes the heap may hold before collections start
+    --gc-stats          Print garbage collection statistics after running
+    --deny-warnings     Treat compile-time warnings as errors
+    --error-format <human|json>
+                        Draw errors for people, or print one JSON object per line
+
+Exits with 1 if the script didn't compile, 2 if it threw an error, or 3 if it ran out of
+fuel, time or memory. Every error and warning has a code like L0003, which --explain
+describes in more detail";
+
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub enum ErrorFormat {
+    #[default]
+    Human,
+    Json,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Args {
+    pub filename: String,
+    pub config: VMConfig,
+    pub gc_stats: bool,
+    pub error_format: ErrorFormat,
+}
+
+/// What the CLI was asked to do
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Run(Args),
+    /// Describe a diagnostic code
+    Explain(String),
+    /// Serve the Language Server Protocol over stdin and stdout
+    Lsp,
+    /// Format files in place, or only check whether they're formatted
+    Fmt {
+        filenames: Vec<String>,
+        check: bool,
+    },
+}
+
+fn number<T: FromStr>(flag: &str, value: Option<String>) -> Result<T, String> {
+    let Some(value) = value else {
+        return Err(format!("{flag} expects a value"));
+    };
+    value
+        .parse()
+        .map_err(|_| format!("{flag} expects a number, but got {value}"))
+}
+
+pub fn parse_command(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    let explain = match args.peek().map(String::as_str) {
+        Some("--explain") => {
+            args.next();
+            args.next()
+        }
+        Some(arg) if arg.starts_with("--explain=") => args.next().map(|arg| arg[10..].to_owned()),
+        Some("lsp") => {
+            args.next();
+            return match args.next() {
+                Some(arg) => Err(format!("Unexpected argument {arg}")),
+                None => Ok(Command::Lsp),
+            };
+        }
+        Some("fmt") => {
+            args.next();
+            return parse_fmt(args);
+        }
+        _ => return parse_args(args).map(Command::Run),
+    };
+    let Some(code) = explain else {
+        return Err("--explain expects a code".to_owned());
+    };
+    if let Some(arg) = args.next() {
+        return Err(format!("Unexpected argument {arg}"));
+    }
+    Ok(Command::Explain(code))
+}
+
+fn parse_fmt(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut filenames = vec![];
+    let mut check = false;
+    for arg in args {
+        match arg.as_str() {
+            "--check" => check = true,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {arg}")),
+            _ => filenames.push(arg),
+        }
+    }
+    if filenames.is_empty() {
+        return Err("Missing filename".to_owned());
+    }
+    Ok(Command::Fmt { filenames, check })
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Args, String> {
+    let mut args = args.into_iter();
+    let mut filename = None;
+    let mut config = VMConfig::default();
+    let mut gc_stats = false;
+    let mut error_format = ErrorFormat::default();
+    while let Some(arg) = args.next() {
+        // both --flag value and --flag=value are accepted
+        let (flag, mut value) = match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => {
+                (flag.to_owned(), Some(value.to_owned()))
+            }
+            _ => (arg, None),
+        };
+        let mut value = || value.take().or_else(|| args.next());
+        match flag.as_str() {
+            "--max-frames" => config.max_frames = number(&flag, value())?,
+            "--stack-size" => config.stack_size = number(&flag, value())?,
+            "--grow-stack" => config.growable_stack = true,
+            "--fuel" => config.fuel = Some(number(&flag, value())?),
+            "--max-memory" => config.max_memory = Some(number(&flag, value())?),
+            "--gc-growth-factor" => {
+                let factor = number(&flag, value())?;
+                if !VMConfig::valid_gc_growth_factor(factor) {
+                    return Err(format!(
+                        "{flag} expects a number of at least 1, but got {factor}"
+                    ));
+                }
+                config.gc_growth_factor = factor;
+            }
+            "--gc-min-heap" => config.gc_min_heap = number(&flag, value())?,
+            "--gc-stats" => gc_stats = true,
+            "--deny-warnings" => config.deny_warnings = true,
+            "--error-format" => {
+                error_format = match value().as_deref() {
+                    Some("human") => ErrorFormat::Human,
+                    Some("json") => ErrorFormat::Json,
+                    _ => return Err(format!("{flag} expects human or json")),
+                };
+            }
+            "--timeout" => {
+                config.timeout = Some(Duration::from_millis(number(&flag, value())?));
+            }
+            _ if flag.starts_with("--") => return Err(format!("Unknown option {flag}")),
+            _ if filename.is_some() => return Err(format!("Unexpected argument {flag}")),
+            _ => filename = Some(flag),
+        }
+    }
+    let Some(filename) = filename else {
+        return Err("Missing filename".to_owned());
+    };
+    Ok(Args {
+        filename,
+        config,
+        gc_stats,
+        error_format,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{parse_args, parse_command, Args, Command, ErrorFormat};
+    use crate::vm::config::VMConfig;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        parse_args(args.iter().map(|s| s.to_string()))
+    }
+
+    #[test]
+    fn filename_only() {
+        assert_eq!(
+            parse(&["foo.lox"]),
+            Ok(Args {
+                filename: "foo.lox".to_owned(),
+                config: VMConfig::default(),
+                gc_stats: false,
+                error_format: ErrorFormat::Human,
+            })
+        );
+    }
+
+    #[test]
+    fn limits() {
+        let args = parse(&[
+            "--max-frames",
+            "10",
+            "--stack-size=100",
+            "--grow-stack",
+            "a.lox",
+        ]);
+        assert_eq!(
+            args.unwrap().config,
+            VMConfig::default()
+                .max_frames(10)
+                .stack_size(100)
+                .growable_stack(true)
+        );
+    }
+
+    #[test]
+    fn execution_limits() {
+        let args = parse(&[
+            "--fuel=1000",
+            "--timeout",
+            "50",
+            "--max-memory",
+            "4096",
+            "a.lox",
+        ]);
+        assert_eq!(
+            args.unwrap().config,
+            VMConfig::default()
+                .fuel(1000)
+                .timeout(Duration::from_millis(50))
+                .max_memory(4096)
+        );
+    }
+
+    #[test]
+    fn gc() {
+        let args = parse(&[
+            "--gc-growth-factor=1.5",
+            "--gc-min-heap",
+            "0",
+            "--gc-stats",
+            "a.lox",
+        ]);
+        let args = args.unwrap();
+        assert!(args.gc_stats);
+        assert_eq!(
+            args.config,
+            VMConfig::default().gc_growth_factor(1.5).gc_min_heap(0)
+        );
+        for factor in ["0.5", "-2", "NaN", "inf"] {
+            let flag = format!("--gc-growth-factor={factor}");
+            assert!(parse(&[&flag, "a.lox"]).is_err(), "{factor}");
+        }
+    }
+
+    #[test]
+    fn deny_warnings() {
+        let args = parse(&["--deny-warnings", "a.lox"]);
+        assert_eq!(
+            args.unwrap().config,
+            VMConfig::default().deny_warnings(true)
+        );
+    }
+
+    #[test]
+    fn error_format() {
+        let args = parse(&["--error-format=json", "a.lox"]);
+        assert_eq!(args.unwrap().error_format, ErrorFormat::Json);
+        let args = parse(&["--error-format", "human", "a.lox"]);
+        assert_eq!(args.unwrap().error_format, ErrorFormat::Human);
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["--max-frames", "a.lox"]).is_err());
+        assert!(parse(&["--stack-size"]).is_err());
+        assert!(parse(&["--foo", "a.lox"]).is_err());
+        assert!(parse(&["a.lox", "b.lox"]).is_err());
+        assert!(parse(&["--error-format=xml", "a.lox"]).is_err());
+    }
+
+    #[test]
+    fn explain() {
+        let command = |args: &[&str]| parse_command(args.iter().map(|s| s.to_string()));
+        assert_eq!(
+            command(&["--explain", "L0003"]),
+            Ok(Command::Explain("L0003".to_owned()))
+        );
+        assert_eq!(
+            command(&["--explain=L0003"]),
+            Ok(Command::Explain("L0003".to_owned()))
+        );
+        assert_eq!(command(&["a.lox"]), parse(&["a.lox"]).map(Command::Run));
+        assert!(command(&["--explain"]).is_err());
+        assert!(command(&["--explain", "L0003", "a.lox"]).is_err());
+    }
+
+    #[test]
+    fn lsp() {
+        let command = |args: &[&str]| parse_command(args.iter().map(|s| s.to_string()));
+        assert_eq!(command(&["lsp"]), Ok(Command::Lsp));
+        assert!(command(&["lsp", "a.lox"]).is_err());
+        assert_eq!(command(&["lsp.lox"]), parse(&["lsp.lox"]).map(Command::Run));
+    }
+
+    #[test]
+    fn fmt() {
+        let command = |args: &[&str]| parse_command(args.iter().map(|s| s.to_string()));
+        assert_eq!(
+            command(&["fmt", "a.lox", "b.lox"]),
+            Ok(Command::Fmt {
+                filenames: vec!["a.lox".to_owned(), "b.lox".to_owned()],
+                check: false,
+            })
+        );
+        assert_eq!(
+            command(&["fmt", "--check", "a.lox"]),
+            Ok(Command::Fmt {
+                filenames: vec!["a.lox".to_owned()],
+                check: true,
+            })
+        );
+        assert!(command(&["fmt"]).is_err());
+        assert!(command(&["fmt", "--check"]).is_err());
+        assert!(command(&["fmt", "--fuel=1", "a.lox"]).is_err());
+    }
+}
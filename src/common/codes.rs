@@ -0,0 +1,379 @@
+//! The registry of diagnostic codes, which stay the same however a message is worded
+//!
+//! Codes are grouped by the stage that reports them: `L00xx` for parsing, `L01xx` for imports,
+//! `L02xx` for warnings, `L03xx` for codegen, `L04xx` for runtime errors and `L05xx` for limits.
+//! A code is never reused once it's been given out.
+
+/// Identifies a kind of diagnostic, like `L0003`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Code {
+    pub id: &'static str,
+    /// What `--explain` prints, which describes the problem and shows an example of it
+    pub explanation: &'static str,
+}
+
+macro_rules! codes {
+    ($($name:ident = $id:literal $explanation:literal)*) => {
+        $(pub const $name: Code = Code { id: $id, explanation: $explanation };)*
+
+        /// Every code, in order
+        pub const ALL: &[Code] = &[$($name),*];
+
+        #[cfg(test)]
+        const NAMES: &[&str] = &[$(stringify!($name)),*];
+    };
+}
+
+codes! {
+    INVALID_TOKEN = "L0001" r#"Something in the source isn't a token.
+
+Tokens are numbers, strings, identifiers, keywords and punctuation. Anything else,
+like a stray character or a string that's never closed, can't be lexed:
+
+    var price = 3 $ 4;
+    print "unterminated;
+
+Remove the character, or close the string with another `"`."#
+
+    EXPECTED_TOKEN = "L0002" r#"The parser found a different token than the grammar allows here.
+
+    var = 3;
+    fun (a) { return a; }
+
+The label says what was expected instead, like a variable name after `var`."#
+
+    INVALID_ASSIGNMENT = "L0003" r#"An assignment is nested inside a larger expression.
+
+Assignments bind more loosely than any operator, so this assigns to `b` in the
+middle of an addition, rather than to `a + b`:
+
+    a + b = 3;
+    print -x += 1;
+
+Put parentheses around the assignment if it's meant to be part of the expression:
+
+    var c = a + (b = 3);"#
+
+    MISSING_SEMICOLON = "L0004" r#"A statement wasn't terminated with `;`.
+
+    print 1
+    print 2;
+
+Every expression statement, `var` or `const` declaration, `print`, `return`,
+`throw` and `import` ends with a semicolon:
+
+    print 1;
+    print 2;"#
+
+    UNMATCHED_DELIMITER = "L0005" r#"A `(` or `{` is never closed.
+
+    print (1 + 2;
+    fun f() { return 1;
+
+The secondary label shows where the closing delimiter was expected. Add it:
+
+    print (1 + 2);
+    fun f() { return 1; }"#
+
+    TOO_MANY_ARGUMENTS = "L0006" r#"A call has more than 255 arguments.
+
+The argument count of a call is stored in a single byte, so calls like
+`f(a0, a1, ..., a255)` can't be compiled. Pass a smaller number of arguments,
+for example by grouping related values into a module."#
+
+    TOO_MANY_PARAMETERS = "L0007" r#"A function declares more than 255 parameters.
+
+    fun f(p0, p1, p2, ..., p255) {}
+
+Like arguments, parameters are counted in a single byte. Take fewer of them."#
+
+    CONST_WITHOUT_INITIALIZER = "L0008" r#"A `const` is declared without a value.
+
+    const limit;
+
+Constants can never be assigned to, so they have to be initialized where they're
+declared:
+
+    const limit = 10;"#
+
+    TRY_WITHOUT_HANDLER = "L0009" r#"A `try` block has neither a `catch` nor a `finally`.
+
+    try { risky(); }
+
+Handle the exception, or run cleanup whether or not one is thrown:
+
+    try { risky(); } catch (e) { print e; }
+    try { risky(); } finally { cleanup(); }"#
+
+    REQUIRED_AFTER_DEFAULT = "L0010" r#"A parameter without a default value comes after one with a default.
+
+    fun greet(greeting = "Hello", name) {}
+
+Arguments fill parameters from left to right, so `name` could never be left out
+while `greeting` is. Put required parameters first:
+
+    fun greet(name, greeting = "Hello") {}"#
+
+    IMPORT_NOT_FOUND = "L0101" r#"An imported file couldn't be read.
+
+    import "utils.lox" as utils;
+
+Paths are relative to the directory of the file that imports them, and scripts
+that aren't read from a file are relative to the working directory. The label
+includes the error from the operating system, like the file not existing."#
+
+    IMPORT_CYCLE = "L0102" r#"A module imports itself, directly or through other modules.
+
+    // a.lox
+    import "b.lox" as b;
+    // b.lox
+    import "a.lox" as a;
+
+Modules run once, before whatever imports them, so a cycle has no order they
+could run in. Move what both modules need into a third one that they both import."#
+
+    UNUSED_VARIABLE = "L0201" r#"A local variable or parameter is never read.
+
+    fun area(width, height) {
+        var unused = 0;
+        return width * width;
+    }
+
+This is often a typo, like using `width` where `height` was meant. Names that
+start with an underscore are never reported, for when something is unused on
+purpose:
+
+    fun callback(_event) { print "called"; }"#
+
+    SHADOWED_VARIABLE = "L0202" r#"A variable is declared twice in the same scope.
+
+    {
+        var total = 1;
+        var total = 2;
+    }
+
+The second declaration hides the first for the rest of the scope. Assign to the
+existing variable instead, or give the new one a different name."#
+
+    UNDEFINED_VARIABLE = "L0203" r#"A variable is used, but no declaration of it could be found.
+
+    fun f() { return cuont; }
+
+This is only a warning, since a global might be declared later on, or by code
+that isn't visible yet. If it isn't, reading it is a runtime error (L0402). If a
+variable in scope is spelled similarly, it's suggested as what was probably meant."#
+
+    UNREACHABLE_CODE = "L0204" r#"Code comes after a statement that always leaves the block.
+
+    fun f() {
+        return 1;
+        print "never printed";
+    }
+
+Anything after `return` or `throw` in the same block can't run. Remove it, or
+move it before the statement."#
+
+    JUMP_TOO_LONG = "L0301" r#"A branch or loop body compiles to more bytecode than a jump can cross.
+
+Jumps store their distance in two bytes, so the body of an `if`, `else`, `while`,
+`for`, `try` or a short-circuiting `and` or `or` can be at most 65535 bytes of
+bytecode. Move part of the body into a function and call it."#
+
+    CONST_ASSIGNMENT = "L0302" r#"A constant is assigned to after it's declared.
+
+    const answer = 42;
+    answer = 43;
+
+Declare it with `var` if it needs to change. This is reported when compiling
+whenever the declaration is visible, and otherwise when the assignment runs."#
+
+    MODULE_RETURN = "L0303" r#"A module returns from its top level.
+
+    // utils.lox
+    return;
+
+A module's top level always runs to the end, so that all of its exports exist.
+Returning is only allowed inside functions, or at the top level of the main script."#
+
+    NESTED_IMPORT = "L0304" r#"An `import` is inside a block or function.
+
+    fun load() {
+        import "utils.lox" as utils;
+    }
+
+Imports are resolved before anything runs, so they have to be at the top level
+of a file."#
+
+    TOO_MANY_EXPORTS = "L0305" r#"A module declares more than 255 top-level names.
+
+Every top-level variable, constant and function of a module is an export, and a
+module is built from at most 255 of them. Split the module in two."#
+
+    TYPE_MISMATCH = "L0401" r#"An operator was used on values of the wrong type.
+
+    print 1 + "a";
+    print -"a";
+
+Arithmetic and comparisons take numbers, `-` negates a number and `+` also
+concatenates two strings. Convert the values first if they should be combined."#
+
+    UNDEFINED_GLOBAL = "L0402" r#"A global variable was read or assigned before it was declared.
+
+    print later;
+    var later = 1;
+
+Globals only exist once their declaration has run. Declare the variable first.
+If a defined global is spelled similarly, it's suggested as what was probably meant."#
+
+    ARITY_MISMATCH = "L0403" r#"A function was called with the wrong number of arguments.
+
+    fun add(a, b) { return a + b; }
+    add(1);
+
+Parameters with default values may be left out, and a rest parameter accepts any
+number of extra arguments, but every other parameter needs an argument."#
+
+    ARGUMENT_TYPE = "L0404" r#"A built-in function got an argument of the wrong type.
+
+    len("four");
+
+The message says which argument it was, counting from 1, and what type it
+should have been."#
+
+    NOT_CALLABLE = "L0405" r#"Something that isn't a function was called.
+
+    var x = 3;
+    x();
+
+Only functions and built-in functions can be called."#
+
+    NOT_A_MODULE = "L0406" r#"A property was read from something that isn't a module.
+
+    var n = 3;
+    print n.value;
+
+Modules have properties, which are their exports. Userdata that the host gives a
+script can also have methods."#
+
+    MISSING_EXPORT = "L0407" r#"A module doesn't export the name that was read from it.
+
+    import "utils.lox" as utils;
+    utils.nonexistent();
+
+A module exports exactly the variables, constants and functions declared at its
+top level. Check the name, or declare it in the module."#
+
+    STACK_OVERFLOW = "L0408" r#"Too many function calls were running at once.
+
+    fun forever(n) { return 1 + forever(n + 1); }
+    forever(0);
+
+This usually means a recursive function never reaches its base case. Calls in
+tail position don't use any extra stack, so `return forever(n + 1);` would loop
+instead."#
+
+    UNCAUGHT_EXCEPTION = "L0409" r#"A value was thrown, and nothing caught it.
+
+    throw "something went wrong";
+
+Catch it with `try` and `catch` around the code that throws it:
+
+    try {
+        throw "something went wrong";
+    } catch (e) {
+        print e;
+    }"#
+
+    MISSING_METHOD = "L0410" r#"A method was read from userdata that doesn't have it.
+
+    var file = open("notes.txt");
+    file.nonexistent();
+
+Userdata comes from the host, which decides what methods each kind has. Check the
+name against what the host provides."#
+
+    OUT_OF_FUEL = "L0501" r#"The script ran more instructions than `--fuel` allows.
+
+This limit makes sure a script finishes, even if it loops forever. Raise the
+limit, or leave out `--fuel` to run without one."#
+
+    TIMEOUT = "L0502" r#"The script ran for longer than `--timeout` allows.
+
+Raise the limit, or leave out `--timeout` to run without one."#
+
+    OUT_OF_MEMORY = "L0503" r#"The script allocated more memory than `--max-memory` allows.
+
+This is only reported after collecting garbage, so everything counted was still
+reachable. Raise the limit, or keep fewer objects alive at once."#
+}
+
+/// Looks up a code by its id, like `L0003`, for `--explain`
+pub fn explain(id: &str) -> Option<Code> {
+    ALL.iter()
+        .copied()
+        .find(|code| code.id.eq_ignore_ascii_case(id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids_are_well_formed_and_unique() {
+        for (i, code) in ALL.iter().enumerate() {
+            let digits = code.id.strip_prefix('L').unwrap();
+            assert!(
+                digits.len() == 4 && digits.bytes().all(|b| b.is_ascii_digit()),
+                "{} isn't an L followed by four digits",
+                code.id
+            );
+            assert!(
+                ALL[..i].iter().all(|other| other.id != code.id),
+                "{} is used twice",
+                code.id
+            );
+        }
+    }
+
+    #[test]
+    fn every_code_is_explained() {
+        for code in ALL {
+            assert_eq!(explain(code.id), Some(*code));
+            assert_eq!(explain(&code.id.to_lowercase()), Some(*code));
+            let mut lines = code.explanation.lines();
+            let summary = lines.next().unwrap();
+            assert!(
+                summary.ends_with('.'),
+                "{} needs a one line summary",
+                code.id
+            );
+            // every explanation either shows an example or describes the limit it's about
+            assert!(
+                lines.count() >= 2,
+                "{} needs an example or a longer description",
+                code.id
+            );
+        }
+        assert_eq!(explain("L9999"), None);
+    }
+
+    /// Every code in the registry is reported somewhere, so none of them are stale
+    #[test]
+    fn every_code_is_used() {
+        let sources = [
+            include_str!("../compiler/parse/parser.rs"),
+            include_str!("../compiler/import.rs"),
+            include_str!("../compiler/resolve.rs"),
+            include_str!("../compiler/codegen.rs"),
+            include_str!("../vm.rs"),
+            include_str!("../vm/gc.rs"),
+        ];
+        for name in NAMES {
+            let path = format!("codes::{name}");
+            assert!(
+                sources.iter().any(|source| source.contains(&path)),
+                "{name} is never reported"
+            );
+        }
+    }
+}
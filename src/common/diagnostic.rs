@@ -0,0 +1,184 @@
+//! Errors and warnings about a script, which stay data until something renders them
+
+use std::io::{self, Write};
+
+use ariadne::{Color, ReportKind};
+use serde_json::{json, Value};
+
+use super::codes::Code;
+use super::ui::{LineCol, SourceMap, Span};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A function that was running when a runtime error happened
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StackFrame {
+    pub function: String,
+    /// Where the function was, which is a call for every frame but the innermost
+    pub span: Span,
+}
+
+/// Points at some code, optionally saying something about it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Label {
+    pub span: Span,
+    pub message: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Identifies the kind of problem, independently of how the message is worded
+    pub code: Code,
+    pub message: Option<String>,
+    /// Where the problem is, which is also where it's reported to be
+    pub primary: Label,
+    pub secondary: Vec<Label>,
+    pub notes: Vec<String>,
+    /// A suggestion for fixing the problem, like a name that was probably meant
+    pub help: Option<String>,
+    /// Innermost first, and only runtime errors have one
+    pub call_stack: Vec<StackFrame>,
+}
+
+impl Diagnostic {
+    fn new(severity: Severity, code: Code, span: Span) -> Self {
+        Self {
+            severity,
+            code,
+            message: None,
+            primary: Label {
+                span,
+                message: None,
+            },
+            secondary: vec![],
+            notes: vec![],
+            help: None,
+            call_stack: vec![],
+        }
+    }
+
+    pub fn error(code: Code, span: Span) -> Self {
+        Self::new(Severity::Error, code, span)
+    }
+
+    pub fn warning(code: Code, span: Span) -> Self {
+        Self::new(Severity::Warning, code, span)
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// Says something about the primary span
+    pub fn with_label(mut self, message: impl Into<String>) -> Self {
+        self.primary.message = Some(message.into());
+        self
+    }
+
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.secondary.push(Label {
+            span,
+            message: Some(message.into()),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    pub fn with_call_stack(mut self, call_stack: Vec<StackFrame>) -> Self {
+        self.call_stack = call_stack;
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Draws this with ariadne, which is how the CLI shows it
+    pub fn render(&self, sources: &SourceMap, out: impl Write) -> io::Result<()> {
+        let (kind, color) = match self.severity {
+            Severity::Error => (ReportKind::Error, Color::Red),
+            Severity::Warning => (ReportKind::Warning, Color::Yellow),
+        };
+        let mut report = sources
+            .report(kind, self.primary.span)
+            .with_code(self.code.id);
+        if let Some(message) = &self.message {
+            report = report.with_message(message);
+        }
+        for label in [&self.primary].into_iter().chain(self.secondary.iter()) {
+            let mut drawn = sources.label(label.span).with_color(color);
+            if let Some(message) = &label.message {
+                drawn = drawn.with_message(message);
+            }
+            report = report.with_label(drawn);
+        }
+        if !self.notes.is_empty() {
+            report = report.with_note(self.notes.join("\n"));
+        }
+        if let Some(help) = &self.help {
+            report = report.with_help(help);
+        }
+        report.finish().write(sources, out)
+    }
+
+    /// A single line of JSON, for tools that can't read what render draws
+    pub fn to_json(&self, sources: &SourceMap) -> String {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let labels: Vec<_> = [&self.primary]
+            .into_iter()
+            .chain(self.secondary.iter())
+            .map(|label| located(sources, label.span, json!({ "message": label.message })))
+            .collect();
+        let call_stack: Vec<_> = self
+            .call_stack
+            .iter()
+            .map(|frame| located(sources, frame.span, json!({ "function": frame.function })))
+            .collect();
+        let json = json!({
+            "severity": severity,
+            "code": self.code.id,
+            "message": self.message,
+            "labels": labels,
+            "notes": self.notes,
+            "help": self.help,
+            "call_stack": call_stack,
+        });
+        located(sources, self.primary.span, json).to_string()
+    }
+}
+
+/// Adds the fields that say where a span is, with a byte range and a line and column in characters
+fn located(sources: &SourceMap, span: Span, mut json: Value) -> Value {
+    let range = span.range();
+    let LineCol { line, column } = sources.line_col(span);
+    json["file"] = json!(sources.name(span.file()));
+    json["range"] = json!([range.start, range.end]);
+    json["line"] = json!(line);
+    json["column"] = json!(column);
+    json
+}
+
+/// Draws every diagnostic in order
+pub fn render_all(diagnostics: &[Diagnostic], sources: &SourceMap, mut out: impl Write) {
+    for diagnostic in diagnostics {
+        diagnostic.render(sources, &mut out).unwrap();
+    }
+}
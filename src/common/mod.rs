@@ -1,4 +1,7 @@
 pub mod alloc;
+pub mod codes;
+pub mod diagnostic;
+pub mod suggest;
 #[cfg(test)]
 pub mod test_util;
 pub mod try_as;
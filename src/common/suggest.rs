@@ -0,0 +1,86 @@
+//! Suggestions for names that were probably misspelled
+
+/// The number of characters that have to be inserted, removed, replaced or swapped with their
+/// neighbour to turn a into b
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // the distances from every prefix of a to every prefix of b, where a swap looks two rows back
+    let mut rows = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in rows.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in rows[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let replaced = rows[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            let mut distance = replaced.min(rows[i - 1][j] + 1).min(rows[i][j - 1] + 1);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distance = distance.min(rows[i - 2][j - 2] + 1);
+            }
+            rows[i][j] = distance;
+        }
+    }
+    rows[a.len()][b.len()]
+}
+
+/// The candidate closest to name, if one is close enough to plausibly be what was meant
+pub fn did_you_mean<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<&'a str> {
+    // short names are only one typo away from most other short names
+    let max_distance = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|&candidate| candidate != name)
+        .map(|candidate| (edit_distance(name, candidate), candidate))
+        .filter(|&(distance, _)| distance <= max_distance)
+        // ties are broken alphabetically, so the suggestion doesn't depend on iteration order
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// The help for an undefined name, if anything resembles it
+pub fn undefined_help<'a>(
+    name: &str,
+    candidates: impl IntoIterator<Item = &'a str>,
+) -> Option<String> {
+    did_you_mean(name, candidates).map(|candidate| format!("Did you mean '{candidate}'?"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{did_you_mean, edit_distance};
+
+    #[test]
+    fn distances() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("abc", ""), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        // swapping neighbours is a single typo
+        assert_eq!(edit_distance("count", "cuont"), 1);
+        assert_eq!(edit_distance("lenght", "length"), 1);
+        assert_eq!(edit_distance("ab", "ba"), 1);
+        assert_eq!(edit_distance("abc", "ca"), 3);
+        assert_eq!(edit_distance("💩x", "x"), 1);
+    }
+
+    #[test]
+    fn suggestions() {
+        let names = ["length", "clock", "counter", "len"];
+        assert_eq!(did_you_mean("lenght", names), Some("length"));
+        assert_eq!(did_you_mean("clokc", names), Some("clock"));
+        assert_eq!(did_you_mean("coutner", names), Some("counter"));
+        assert_eq!(did_you_mean("lne", names), Some("len"));
+        assert_eq!(did_you_mean("nel", names), None);
+        assert_eq!(did_you_mean("le", names), Some("len"));
+        assert_eq!(did_you_mean("something", names), None);
+        // a name is never suggested as a replacement for itself
+        assert_eq!(did_you_mean("len", ["len"]), None);
+        assert_eq!(did_you_mean("ab", ["ac", "aa"]), Some("aa"));
+    }
+}
@@ -22,10 +22,14 @@ pub use ::insta::assert_snapshot;
 pub use black_box as assert_snapshot;
 
 pub fn mock_interpret(source: &str) -> String {
+    mock_interpret_with(source, crate::vm::config::VMConfig::default())
+}
+
+pub fn mock_interpret_with(source: &str, config: crate::vm::config::VMConfig) -> String {
     setup_test();
     let mut stderr = vec![];
     let mut stdout = vec![];
-    let _ = crate::interpret(source, &mut stderr, &mut stdout);
+    let _ = crate::vm::interpret_with(source, config, &mut stderr, &mut stdout);
     let stderr = String::from_utf8(strip_ansi_escapes::strip(stderr).unwrap()).unwrap();
     let stdout = String::from_utf8(strip_ansi_escapes::strip(stdout).unwrap()).unwrap();
     format!("stdout:\n{stdout}\n\nstderr:\n{stderr}\n")
@@ -41,16 +45,29 @@ macro_rules! snap_interpret {
             ));
         }
     };
+    ($name:ident, $input:literal, $config:expr) => {
+        #[test]
+        fn $name() {
+            $crate::common::test_util::assert_snapshot!(
+                $crate::common::test_util::mock_interpret_with($input, $config)
+            );
+        }
+    };
 }
 
 pub fn mock_parse(source: &str) -> String {
-    let mut stderr = vec![];
-    let ast = crate::compiler::parse(source, &mut stderr);
-    let stderr = String::from_utf8(strip_ansi_escapes::strip(stderr).unwrap()).unwrap();
-    if let Some(ast) = ast {
-        format!("ast:\n{}\n\n{stderr}", ast)
-    } else {
-        format!("stderr:\n{stderr}\n")
+    use crate::common::diagnostic::render_all;
+    use crate::common::ui::{SourceMap, SCRIPT_NAME};
+    let mut sources = SourceMap::default();
+    let main = sources.add(SCRIPT_NAME, source);
+    match crate::compiler::parse::parse_file(&sources, main) {
+        Ok(ast) => format!("ast:\n{}\n\n", ast),
+        Err(diagnostics) => {
+            let mut stderr = vec![];
+            render_all(&diagnostics, &sources, &mut stderr);
+            let stderr = String::from_utf8(strip_ansi_escapes::strip(stderr).unwrap()).unwrap();
+            format!("stderr:\n{stderr}\n")
+        }
     }
 }
 
@@ -67,12 +84,17 @@ macro_rules! snap_parse {
 }
 
 pub fn mock_codegen(source: &str) -> String {
+    use crate::common::diagnostic::render_all;
+    use crate::common::ui::{SourceMap, SCRIPT_NAME};
     let mut stderr = vec![];
-    let chunk = crate::compiler::compile(source, &mut stderr);
+    let mut sources = SourceMap::default();
+    let main = sources.add(SCRIPT_NAME, source);
+    let (chunk, diagnostics) = crate::compiler::compile(&mut sources, main, None, &[], false);
+    render_all(&diagnostics, &sources, &mut stderr);
     let stderr = String::from_utf8(strip_ansi_escapes::strip(stderr).unwrap()).unwrap();
     if let Some(chunk) = chunk {
         let mut bytecode = vec![];
-        chunk.disassemble("test.lox", source, &mut bytecode);
+        chunk.disassemble("test.lox", &sources, &mut bytecode);
         let stdout = String::from_utf8(strip_ansi_escapes::strip(bytecode).unwrap()).unwrap();
         format!("bytecode:\n{stdout}\n\n{stderr}")
     } else {
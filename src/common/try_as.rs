@@ -15,6 +15,7 @@ impl<T> TryAs<T> for T {
 
 pub trait TryCast<From>: Sized {
     fn try_cast(value: From) -> Option<Self>;
+    #[allow(dead_code)]
     fn unwrap_cast(value: From) -> Self {
         Self::try_cast(value).unwrap()
     }
@@ -1,17 +1,28 @@
-use std::{fmt::Display, ops::Index};
+use std::{
+    fmt::{Debug, Display},
+    ops::{Index, Range},
+};
 
 use arbitrary::Arbitrary;
+use ariadne::{Cache, Label, Report, ReportBuilder, ReportKind, Source};
 use tracing::warn;
 
-#[derive(Copy, Clone, PartialEq, Eq, Debug, Arbitrary)]
+/// Which file in a SourceMap something came from
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default, Arbitrary)]
+pub struct FileId(u16);
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Arbitrary)]
 pub struct Span {
+    file: FileId,
     begin: u32,
     end: u32,
 }
 
+/// Spans made from a range are in the first file, see Span::in_file for the rest
 impl From<logos::Span> for Span {
     fn from(value: logos::Span) -> Self {
         Self {
+            file: FileId::default(),
             begin: value.start.try_into().unwrap(),
             end: value.end.try_into().unwrap(),
         }
@@ -26,8 +37,26 @@ impl Index<Span> for str {
 }
 
 impl Span {
+    pub fn in_file(self, file: FileId) -> Span {
+        Span { file, ..self }
+    }
+
+    pub fn file(self) -> FileId {
+        self.file
+    }
+
+    /// In bytes, relative to the start of the file
+    pub fn range(self) -> Range<usize> {
+        self.begin as usize..self.end as usize
+    }
+
     pub fn unite(self, other: Span) -> Span {
+        // a span can't cover two files, which happens next to codegen's own spans in a module
+        if self.file != other.file {
+            return self;
+        }
         Span {
+            file: self.file,
             begin: self.begin.min(other.begin),
             end: self.end.max(other.end),
         }
@@ -35,7 +64,7 @@ impl Span {
 
     pub fn unite_many(spans: &[Span]) -> Span {
         debug_assert!(!spans.is_empty());
-        if let Some(span) = spans.get(0) {
+        if let Some(span) = spans.first() {
             spans[1..].iter().fold(*span, |a, b| a.unite(*b))
         } else {
             warn!("Empty set of spans should never happen");
@@ -52,21 +81,37 @@ impl Span {
     }
 }
 
-impl ariadne::Span for Span {
-    type SourceId = ();
+/// Where a span is in characters rather than bytes, which is what ariadne counts in
+/// Made by SourceMap::label and SourceMap::report, so that no report can skip the conversion
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct CharSpan {
+    file: FileId,
+    start: usize,
+    end: usize,
+}
+
+impl ariadne::Span for CharSpan {
+    type SourceId = FileId;
     fn source(&self) -> &Self::SourceId {
-        &()
+        &self.file
     }
 
     fn start(&self) -> usize {
-        self.begin as usize
+        self.start
     }
 
     fn end(&self) -> usize {
-        self.end as usize
+        self.end
     }
 }
 
+/// Both start at 1, and columns are counted in characters
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Arbitrary)]
 pub struct Spanned<T> {
     pub data: T,
@@ -107,4 +152,122 @@ impl<T> WithSpanExt for T {
     }
 }
 
-pub const OFFSET: usize = 12;
+/// The name of a script that didn't come from a file
+pub const SCRIPT_NAME: &str = "<script>";
+
+struct SourceFile {
+    name: String,
+    text: String,
+    /// The text split into lines, for reports
+    lines: Source,
+}
+
+/// Every file that's been loaded, so that spans from any of them can be reported
+#[derive(Default)]
+pub struct SourceMap {
+    files: Vec<SourceFile>,
+}
+
+impl SourceMap {
+    pub fn add(&mut self, name: impl Into<String>, text: impl Into<String>) -> FileId {
+        let id = FileId(self.files.len().try_into().expect("Too many files"));
+        let text = text.into();
+        self.files.push(SourceFile {
+            name: name.into(),
+            lines: Source::from(text.as_str()),
+            text,
+        });
+        id
+    }
+
+    pub fn name(&self, file: FileId) -> &str {
+        &self.files[usize::from(file.0)].name
+    }
+
+    pub fn text(&self, file: FileId) -> &str {
+        &self.files[usize::from(file.0)].text
+    }
+
+    fn char_offset(&self, file: FileId, byte: u32) -> usize {
+        let text = self.text(file);
+        text.get(..byte as usize).unwrap_or(text).chars().count()
+    }
+
+    fn char_span(&self, span: Span) -> CharSpan {
+        CharSpan {
+            file: span.file,
+            start: self.char_offset(span.file, span.begin),
+            end: self.char_offset(span.file, span.end),
+        }
+    }
+
+    /// Where span starts, the same way reports show it
+    pub fn line_col(&self, span: Span) -> LineCol {
+        let offset = self.char_offset(span.file, span.begin);
+        let lines = &self.files[usize::from(span.file.0)].lines;
+        match lines.get_offset_line(offset) {
+            Some((_, line, column)) => LineCol {
+                line: line + 1,
+                column: column + 1,
+            },
+            // only an empty file has no lines
+            None => LineCol { line: 1, column: 1 },
+        }
+    }
+
+    pub fn label(&self, span: Span) -> Label<CharSpan> {
+        Label::new(self.char_span(span))
+    }
+
+    /// Starts a report located at the beginning of span
+    pub fn report(
+        &self,
+        kind: ReportKind<'static>,
+        span: Span,
+    ) -> ReportBuilder<'static, CharSpan> {
+        Report::build(kind, span.file, self.char_offset(span.file, span.begin))
+    }
+}
+
+impl Index<Span> for SourceMap {
+    type Output = str;
+    fn index(&self, index: Span) -> &Self::Output {
+        &self.text(index.file)[index]
+    }
+}
+
+impl Cache<FileId> for &SourceMap {
+    fn fetch(&mut self, id: &FileId) -> Result<&Source, Box<dyn Debug + '_>> {
+        match self.files.get(usize::from(id.0)) {
+            Some(file) => Ok(&file.lines),
+            None => Err(Box::new(format!("Unknown file {id:?}"))),
+        }
+    }
+
+    fn display<'a>(&self, id: &'a FileId) -> Option<Box<dyn Display + 'a>> {
+        let file = self.files.get(usize::from(id.0))?;
+        Some(Box::new(file.name.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LineCol, SourceMap, Span};
+
+    #[test]
+    fn line_col_counts_characters() {
+        let mut sources = SourceMap::default();
+        let text = "print \"💩\";\nprint \"갍갍\" + x;";
+        let file = sources.add("test.lox", text);
+        let x = text.rfind('x').unwrap();
+        let span = Span::from(x..x + 1).in_file(file);
+        assert_eq!(&sources[span], "x");
+        assert_eq!(
+            sources.line_col(span),
+            LineCol {
+                line: 2,
+                column: 14
+            }
+        );
+    }
+}
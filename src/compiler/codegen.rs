@@ -1,6 +1,8 @@
 mod scope;
 
-use std::io::Write;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::collections::HashSet;
 
 use std::slice::SliceIndex;
 use std::sync::OnceLock;
@@ -12,21 +14,34 @@ use crate::bytecode::interner::Interner;
 use crate::bytecode::chunk::Chunk;
 use crate::bytecode::chunk::OpCode;
 
+use crate::common::codes::{self, Code};
+use crate::common::diagnostic::Diagnostic;
+use crate::common::try_as::TryAs;
 use crate::common::ui;
 use crate::common::ui::*;
 use crate::value::function::ObjFunction;
+use crate::value::list::ObjList;
 use crate::value::native_function::CallError;
+use crate::value::native_function::NativeFn;
 use crate::value::native_function::NativeFunction;
 use crate::value::string::UnsafeString;
+use crate::value::valid::ValidPtr;
+use crate::value::weak::ObjWeakRef;
 use crate::value::Value;
 
 use self::scope::Scope;
 
+use super::import::Module;
+use super::import::Modules;
+
 use super::parse::BinaryExpr;
 use super::parse::BinaryKind;
 use super::parse::Call;
+use super::parse::Catch;
 use super::parse::Expression;
+use super::parse::Function;
 use super::parse::FunctionDeclaration;
+use super::parse::Identifier;
 use super::parse::Literal;
 use super::parse::Statement;
 use super::parse::Statements;
@@ -35,6 +50,9 @@ use super::parse::UnaryKind;
 struct StaticCallFrame {
     base_pointer: usize,
     upvalues: Vec<Upvalue>,
+    /// One entry per handler this function has pushed, innermost last
+    /// Each has the finally block that has to run when leaving it early, if any
+    handlers: Vec<Option<Spanned<Statements>>>,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -46,17 +64,34 @@ struct Upvalue {
 struct Local {
     depth: u8,
     captured: bool,
+    /// Where this was declared, if it was declared const
+    constant: Option<Span>,
+}
+
+/// The module whose top level is being compiled, which has its own globals
+struct ModuleScope {
+    prefix: String,
+    /// Names declared at the top level, which take precedence over natives
+    declared: HashSet<String>,
 }
 
 type LocalSymbol = InternedU8;
-struct Compiler<'src, StdErr: Write> {
+struct Compiler<'d> {
     chunk: Chunk,
-    source: &'src str,
-    stderr: StdErr,
+    diagnostics: &'d mut Vec<Diagnostic>,
     interned_locals: Interner,
     defined_locals: Vec<Local>,
     scope_size: Vec<usize>,
     static_call_stack: Vec<StaticCallFrame>,
+    /// Where each global that's currently const was declared
+    const_globals: HashMap<u8, Span>,
+    module: Option<ModuleScope>,
+    /// Which module each import refers to, by the span of its path
+    imports: HashMap<Span, usize>,
+    /// The constant holding each module's function, by index
+    module_functions: Vec<u8>,
+    /// Natives the host defines on top of the built in ones
+    natives: &'d [(&'static str, NativeFn)],
 }
 
 pub type CodegenResult<T> = Result<T, ()>;
@@ -102,33 +137,140 @@ impl Chunk {
     }
 }
 
-impl<'src, StdErr: Write> Compiler<'src, StdErr> {
-    fn new(source: &'src str, stderr: StdErr) -> Self {
+/// The natives that every program starts with, as globals
+pub const NATIVES: &[(&str, NativeFn)] = &[
+    ("clock", |_, values| {
+        if !values.is_empty() {
+            return Err(CallError::ArityMismatch(0));
+        }
+        // this is primarily for benchmarking anyways
+        static FIRST_TIME: OnceLock<Instant> = OnceLock::new();
+        let init = *FIRST_TIME.get_or_init(Instant::now);
+        let time = Instant::now().duration_since(init).as_secs_f64();
+        Ok(Value::Num(time))
+    }),
+    ("len", |_, values| {
+        let [list] = values else {
+            return Err(CallError::ArityMismatch(1));
+        };
+        let Some(list) = TryAs::<ObjList>::try_as(*list) else {
+            return Err(CallError::TypeMismatch(1, "list"));
+        };
+        Ok(Value::Num(list.items().len() as f64))
+    }),
+    ("get", |_, values| {
+        let [list, index] = values else {
+            return Err(CallError::ArityMismatch(2));
+        };
+        let Some(list) = TryAs::<ObjList>::try_as(*list) else {
+            return Err(CallError::TypeMismatch(1, "list"));
+        };
+        let Value::Num(index) = index else {
+            return Err(CallError::TypeMismatch(2, "number"));
+        };
+        // out of bounds and fractional indices are both just missing
+        let item = if index.fract() == 0.0 && *index >= 0.0 {
+            list.items().get(*index as usize).copied()
+        } else {
+            None
+        };
+        Ok(item.unwrap_or(Value::Nil))
+    }),
+    ("weakref", |heap, values| {
+        let [target] = values else {
+            return Err(CallError::ArityMismatch(1));
+        };
+        let Value::Object(target) = target else {
+            return Err(CallError::TypeMismatch(1, "object"));
+        };
+        let weak = heap.alloc(ObjWeakRef::new(*target).into())?;
+        Ok(Value::Object(weak))
+    }),
+    ("deref", |_, values| {
+        let [weak] = values else {
+            return Err(CallError::ArityMismatch(1));
+        };
+        let Some(weak) = TryAs::<ObjWeakRef>::try_as(*weak) else {
+            return Err(CallError::TypeMismatch(1, "weakref"));
+        };
+        Ok(weak.get().map_or(Value::Nil, Value::Object))
+    }),
+];
+
+impl<'d> Compiler<'d> {
+    fn new(diagnostics: &'d mut Vec<Diagnostic>, natives: &'d [(&'static str, NativeFn)]) -> Self {
         Self {
             chunk: Chunk::new(),
-            source,
-            stderr,
+            diagnostics,
             interned_locals: Interner::default(),
             defined_locals: Default::default(),
             scope_size: Default::default(),
             static_call_stack: vec![StaticCallFrame {
                 base_pointer: 0,
                 upvalues: vec![],
+                handlers: vec![],
             }],
+            const_globals: HashMap::new(),
+            module: None,
+            imports: HashMap::new(),
+            module_functions: vec![],
+            natives,
         }
     }
 
-    fn simple_error(&mut self, span: ui::Span, msg: &str) {
-        use ariadne::{Color, Label, Report, ReportKind, Source};
-        Report::build(ReportKind::Error, (), ui::OFFSET)
-            .with_label(Label::new(span).with_color(Color::Red).with_message(msg))
-            .finish()
-            .write(Source::from(self.source), &mut self.stderr)
-            .unwrap();
+    fn simple_error(&mut self, code: Code, span: ui::Span, msg: &str) {
+        self.diagnostics
+            .push(Diagnostic::error(code, span).with_label(msg));
+    }
+
+    fn const_assignment_error(&mut self, assignment: Span, declaration: Span, name: &str) {
+        self.diagnostics.push(
+            Diagnostic::error(codes::CONST_ASSIGNMENT, assignment)
+                .with_message(format!("Cannot assign to constant {name}"))
+                .with_label("This assignment isn't allowed")
+                .with_secondary(declaration, format!("{name} is declared const here")),
+        );
     }
 
     fn in_global_scope(&self) -> bool {
-        self.scope_size.is_empty()
+        // a module's top level is inside the function that runs it
+        self.scope_size.len() == usize::from(self.module.is_some())
+    }
+
+    /// Globals of a module are namespaced by it, so they can't clash with anything else
+    fn global_name<'a>(&self, name: &'a str) -> Cow<'a, str> {
+        match &self.module {
+            Some(module)
+                if module.declared.contains(name)
+                    || !NATIVES
+                        .iter()
+                        .chain(self.natives)
+                        .any(|(native, _)| *native == name) =>
+            {
+                Cow::Owned(format!("{}{name}", module.prefix))
+            }
+            _ => Cow::Borrowed(name),
+        }
+    }
+
+    fn global(&mut self, name: &str) -> u8 {
+        let name = self.global_name(name);
+        self.chunk.globals.add_or_get(&name)
+    }
+
+    /// Defines a global at the top level, or a local anywhere else
+    fn define_variable(&mut self, id: &Spanned<Identifier>) {
+        if self.in_global_scope() {
+            let nameid = self.global(&id.data.0);
+            self.const_globals.remove(&nameid);
+            emit_bytes!(self.chunk, id.span; OpCode::DefineGlobal, nameid);
+        } else {
+            self.add_local(&id.data.0);
+        }
+    }
+
+    fn in_function(&self) -> bool {
+        self.static_call_stack.len() > 1
     }
 
     fn begin_scope(&mut self) {
@@ -139,6 +281,7 @@ impl<'src, StdErr: Write> Compiler<'src, StdErr> {
         self.static_call_stack.push(StaticCallFrame {
             base_pointer: self.defined_locals.len(),
             upvalues: vec![],
+            handlers: vec![],
         });
 
         self.scope_size.push(0);
@@ -165,6 +308,14 @@ impl<'src, StdErr: Write> Compiler<'src, StdErr> {
         }
     }
 
+    /// Ends a scope without popping its locals, for when the next instruction consumes them
+    fn forget_scope(&mut self) {
+        let size = self.scope_size.pop().unwrap();
+        for _ in 0..size {
+            self.defined_locals.pop().unwrap();
+        }
+    }
+
     fn define_local(&mut self, name: &str) -> u8 {
         self.interned_locals.add_or_get(name)
     }
@@ -174,11 +325,30 @@ impl<'src, StdErr: Write> Compiler<'src, StdErr> {
         self.defined_locals.push(Local {
             depth: nameid,
             captured: false,
+            constant: None,
         });
         let size = self.scope_size.last_mut().unwrap();
         *size += 1;
     }
 
+    fn add_const_local(&mut self, name: &str, declaration: Span) {
+        self.add_local(name);
+        self.defined_locals.last_mut().unwrap().constant = Some(declaration);
+    }
+
+    /// Where the variable that name resolves to was declared, if it's const
+    /// Locals of enclosing functions are still on the stack, so this covers upvalues too
+    fn resolve_const(&self, name: &str) -> Option<Span> {
+        if let Some(nameid) = self.interned_locals.get(name) {
+            let local = self.defined_locals.iter().rev().find(|l| l.depth == nameid);
+            if let Some(local) = local {
+                return local.constant;
+            }
+        }
+        let nameid = self.chunk.globals.get(&self.global_name(name))?;
+        self.const_globals.get(&nameid).copied()
+    }
+
     fn resolve_local(
         &self,
         name: &str,
@@ -258,13 +428,13 @@ impl<'src, StdErr: Write> Compiler<'src, StdErr> {
         } else if let Some(pos) = self.resolve_upvalue(name) {
             (Scope::Upvalue, pos)
         } else {
-            (Scope::Global, self.chunk.globals.add_or_get(name))
+            (Scope::Global, self.global(name))
         }
     }
 
     fn patch_jump(&mut self, addr: usize, span: Span) -> CodegenResult<()> {
         let Ok(jump) = u16::try_from(self.chunk.instructions.len() - addr - 2) else {
-            self.simple_error(span, "The body of this branch is too long and would generate more instructions than is supported.");
+            self.simple_error(codes::JUMP_TOO_LONG, span, "The body of this branch is too long and would generate more instructions than is supported.");
             return Err(());
         };
         let offset = jump.to_ne_bytes();
@@ -276,7 +446,11 @@ impl<'src, StdErr: Write> Compiler<'src, StdErr> {
         self.chunk.emit_byte(OpCode::Loop, span);
         let offset = self.chunk.instructions.len() - start + 2;
         let Ok(offset) = u16::try_from(offset) else {
-            self.simple_error(span, "This loop would have a longer body than is supported");
+            self.simple_error(
+                codes::JUMP_TOO_LONG,
+                span,
+                "This loop would have a longer body than is supported",
+            );
             return Err(());
         };
         let offset = offset.to_ne_bytes();
@@ -325,15 +499,51 @@ impl<'src, StdErr: Write> Compiler<'src, StdErr> {
         Ok(())
     }
 
+    fn coalesce_expr(&mut self, expr: &BinaryExpr) -> CodegenResult<()> {
+        self.expression(&expr.lhs.data)?;
+        let end = self
+            .chunk
+            .emit_jump(OpCode::JumpRelIfNotNil, expr.kind.span);
+        self.chunk.emit_impl_byte(OpCode::Pop);
+
+        self.expression(&expr.rhs.data)?;
+        self.patch_jump(end, expr.kind.span)?;
+        Ok(())
+    }
+
+    fn ternary_expr(
+        &mut self,
+        cond: &Spanned<Expression>,
+        then_branch: &Spanned<Expression>,
+        else_branch: &Spanned<Expression>,
+    ) -> CodegenResult<()> {
+        self.expression(&cond.data)?;
+        let else_jump = self.chunk.emit_jump(OpCode::JumpRelIfFalse, cond.span);
+        self.chunk.emit_impl_byte(OpCode::Pop);
+        self.expression(&then_branch.data)?;
+
+        let end = self.chunk.emit_jump(OpCode::JumpRel, Chunk::impl_span());
+        self.patch_jump(else_jump, cond.span)?;
+        self.chunk.emit_impl_byte(OpCode::Pop);
+        self.expression(&else_branch.data)?;
+        self.patch_jump(end, Chunk::impl_span())?;
+        Ok(())
+    }
+
     fn binary_op(&mut self, expr: &BinaryExpr) -> CodegenResult<()> {
         self.expression(&expr.lhs.data)?;
         self.expression(&expr.rhs.data)?;
+        self.binary_operator(expr.kind.data, expr.kind.span)
+    }
+
+    /// Combines the top two values on the stack
+    fn binary_operator(&mut self, kind: BinaryKind, span: Span) -> CodegenResult<()> {
         macro_rules! emit {
             ($($opcode:expr),+) => {
-                emit_bytes!(self.chunk, expr.kind.span; $($opcode,)+)
+                emit_bytes!(self.chunk, span; $($opcode,)+)
             };
         }
-        match expr.kind.data {
+        match kind {
             BinaryKind::Minus => emit!(OpCode::Sub),
             BinaryKind::Plus => emit!(OpCode::Add),
             BinaryKind::Divide => emit!(OpCode::Div),
@@ -354,8 +564,14 @@ impl<'src, StdErr: Write> Compiler<'src, StdErr> {
             Expression::Binary(bin @ BinaryExpr { kind, .. }) => match kind.data {
                 BinaryKind::And => self.and_expr(bin)?,
                 BinaryKind::Or => self.or_expr(bin)?,
+                BinaryKind::Coalesce => self.coalesce_expr(bin)?,
                 _ => self.binary_op(bin)?,
             },
+            Expression::Ternary {
+                cond,
+                then_branch,
+                else_branch,
+            } => self.ternary_expr(cond, then_branch, else_branch)?,
             Expression::Unary { kind, val } => {
                 self.expression(&val.data)?;
                 let opcode = match kind.data {
@@ -366,17 +582,41 @@ impl<'src, StdErr: Write> Compiler<'src, StdErr> {
             }
             Expression::Literal(lit) => self.literal(lit)?,
             Expression::Assignment { id, rhs } => {
+                if let Some(declaration) = self.resolve_const(&id.data.0) {
+                    self.const_assignment_error(id.span, declaration, &id.data.0);
+                    return Err(());
+                }
                 let (scope, follow_byte) = self.resolve(&id.data.0);
                 let opcode = scope.set_opcode();
                 self.expression(&rhs.data)?;
                 emit_bytes!(self.chunk, id.span; opcode, follow_byte);
             }
+            Expression::CompoundAssignment { id, kind, rhs } => {
+                if let Some(declaration) = self.resolve_const(&id.data.0) {
+                    self.const_assignment_error(id.span, declaration, &id.data.0);
+                    return Err(());
+                }
+                let (scope, follow_byte) = self.resolve(&id.data.0);
+                emit_bytes!(self.chunk, id.span; scope.get_opcode(), follow_byte);
+                self.expression(&rhs.data)?;
+                self.binary_operator(BinaryKind::from(kind.data), kind.span)?;
+                emit_bytes!(self.chunk, id.span; scope.set_opcode(), follow_byte);
+            }
             Expression::Identifier(id) => {
                 let (scope, follow_byte) = self.resolve(&id.data.0);
                 let opcode = scope.get_opcode();
                 emit_bytes!(self.chunk, id.span; opcode, follow_byte);
             }
             Expression::Call(call) => self.function_call(call)?,
+            Expression::Get { object, name } => {
+                self.expression(&object.data)?;
+                let constant = self.chunk.add_constant(Value::from(name.data.0.as_str()));
+                emit_bytes!(self.chunk, name.span; OpCode::GetProperty, constant);
+            }
+            // not a valid identifier, so the body can't refer to itself
+            Expression::Lambda(function) => {
+                self.function("<lambda>", function.span, &function.data)?
+            }
         }
         Ok(())
     }
@@ -395,18 +635,33 @@ impl<'src, StdErr: Write> Compiler<'src, StdErr> {
         res
     }
 
-    fn function_call(&mut self, call: &Call) -> CodegenResult<()> {
+    fn emit_call(&mut self, call: &Call, opcode: OpCode) -> CodegenResult<()> {
         let Call { callee, args } = call;
         self.expression(&callee.data)?;
         for arg in args {
             self.expression(&arg.data)?;
         }
-        emit_bytes!(self.chunk, callee.span; OpCode::Call, args.len() as u8);
+        emit_bytes!(self.chunk, callee.span; opcode, args.len() as u8);
         Ok(())
     }
 
-    fn function_declaration(&mut self, declaration: &FunctionDeclaration) -> CodegenResult<()> {
-        let FunctionDeclaration { name, args, body } = declaration;
+    fn function_call(&mut self, call: &Call) -> CodegenResult<()> {
+        self.emit_call(call, OpCode::Call)
+    }
+
+    /// The return after this is only reached if the callee isn't a closure
+    fn tail_call(&mut self, call: &Call) -> CodegenResult<()> {
+        self.emit_call(call, OpCode::TailCall)
+    }
+
+    /// Leaves a closure of the function on the stack
+    fn function(&mut self, name: &str, span: Span, function: &Function) -> CodegenResult<()> {
+        let Function {
+            args,
+            defaults,
+            rest,
+            body,
+        } = function;
 
         // We aren't making separate chunks to keep everything in the same allocation
         // So skip the function
@@ -416,11 +671,24 @@ impl<'src, StdErr: Write> Compiler<'src, StdErr> {
         let function_start = self.chunk.instructions.len();
 
         // mark self so recursive calls work
-        self.add_local(&name.data.0);
+        self.add_local(name);
         // callee must initialize the args, this is just to make the offsets work
         for arg in args {
             self.add_local(&arg.data.0);
         }
+        // calls skip past the defaults that were passed, and the rest fall through to each other
+        // each one leaves its value on the stack, right where its local goes
+        let mut entries = vec![];
+        for (arg, default) in defaults {
+            entries.push(self.chunk.instructions.len());
+            self.expression(&default.data)?;
+            self.add_local(&arg.data.0);
+        }
+        entries.push(self.chunk.instructions.len());
+        if let Some(rest) = rest {
+            emit_bytes!(self.chunk, rest.span; OpCode::CollectRest, function.parameter_count() as u8);
+            self.add_local(&rest.data.0);
+        }
 
         self.block(&body.data)?;
         self.end_function_scope();
@@ -429,27 +697,29 @@ impl<'src, StdErr: Write> Compiler<'src, StdErr> {
 
         self.patch_jump(skip, Chunk::impl_span())?;
 
-        let function = ObjFunction {
-            arity: args.len().try_into().unwrap(),
+        let object = ObjFunction {
+            min_arity: args.len().try_into().unwrap(),
+            max_arity: function.parameter_count().try_into().unwrap(),
+            rest: rest.is_some(),
             upvalues: callframe.upvalues.len() as u8,
             addr: function_start,
-            name: UnsafeString::from(name.data.0.as_str()),
+            entries: ValidPtr::from(entries.into_boxed_slice()),
+            name: UnsafeString::from(name),
         };
 
-        let constant = self.chunk.add_constant(function.into());
-        emit_bytes!(self.chunk, name.span; OpCode::Closure, constant);
+        let constant = self.chunk.add_constant(object.into());
+        emit_bytes!(self.chunk, span; OpCode::Closure, constant);
 
         for upvalue in callframe.upvalues {
             emit_bytes!(self.chunk, Chunk::impl_span(); upvalue.local as u8, upvalue.index);
         }
+        Ok(())
+    }
 
-        if self.in_global_scope() {
-            let nameid = self.chunk.globals.add_or_get(&name.data.0);
-            emit_bytes!(self.chunk, name.span; OpCode::DefineGlobal, nameid);
-        } else {
-            self.add_local(&name.data.0);
-        }
-
+    fn function_declaration(&mut self, declaration: &FunctionDeclaration) -> CodegenResult<()> {
+        let FunctionDeclaration { name, function } = declaration;
+        self.function(&name.data.0, name.span, function)?;
+        self.define_variable(name);
         Ok(())
     }
 
@@ -469,11 +739,16 @@ impl<'src, StdErr: Write> Compiler<'src, StdErr> {
                 } else {
                     self.chunk.emit_constant(Value::Nil, id.span);
                 }
+                self.define_variable(id);
+            }
+            Statement::ConstDeclaration { id, rhs } => {
+                self.expression(&rhs.data)?;
                 if self.in_global_scope() {
-                    let nameid = self.chunk.globals.add_or_get(&id.data.0);
-                    emit_bytes!(self.chunk, id.span; OpCode::DefineGlobal, nameid);
+                    let nameid = self.global(&id.data.0);
+                    self.const_globals.insert(nameid, id.span);
+                    emit_bytes!(self.chunk, id.span; OpCode::DefineConstGlobal, nameid);
                 } else {
-                    self.add_local(&id.data.0);
+                    self.add_const_local(&id.data.0, id.span);
                 }
             }
             Statement::Block(statements) => self.scoped_block(&statements.data)?,
@@ -514,14 +789,61 @@ impl<'src, StdErr: Write> Compiler<'src, StdErr> {
                 self.patch_jump(exit, cond.span)?;
                 self.chunk.emit_impl_byte(OpCode::Pop);
             }
+            Statement::Return { span, .. }
+                if self.module.is_some() && self.static_call_stack.len() == 2 =>
+            {
+                self.simple_error(
+                    codes::MODULE_RETURN,
+                    *span,
+                    "Modules can't return from their top level",
+                );
+                return Err(());
+            }
             Statement::Return { span, value } => {
-                if let Some(value) = value {
-                    self.expression(&value.data)?;
+                let in_try = !self.static_call_stack.last().unwrap().handlers.is_empty();
+                match value.as_ref().map(|value| &value.data) {
+                    // top level returns have no callframe to reuse
+                    // and a handler has to outlive the call it's wrapped around
+                    Some(Expression::Call(call)) if self.in_function() && !in_try => {
+                        self.tail_call(call)?
+                    }
+                    Some(expr) => self.expression(expr)?,
+                    None => self.chunk.emit_impl_byte(OpCode::Nil),
+                }
+                self.leave_handlers()?;
+                if value.is_some() {
                     self.chunk.emit_byte(OpCode::Return, *span);
                 } else {
-                    self.chunk.emit_return();
+                    self.chunk.emit_impl_byte(OpCode::Return);
                 }
             }
+            Statement::Import { path, alias } => {
+                let Some(&index) = self.imports.get(&path.span) else {
+                    self.simple_error(
+                        codes::NESTED_IMPORT,
+                        path.span,
+                        "Imports are only allowed at the top level",
+                    );
+                    return Err(());
+                };
+                // modules only run the first time they're imported
+                emit_bytes!(self.chunk, path.span; OpCode::GetModule, index as u8);
+                let skip = self.chunk.emit_jump(OpCode::JumpRelIfNotNil, path.span);
+                self.chunk.emit_impl_byte(OpCode::Pop);
+                let function = self.module_functions[index];
+                emit_bytes!(self.chunk, path.span; OpCode::Closure, function, OpCode::Call, 0);
+                self.patch_jump(skip, path.span)?;
+                self.define_variable(alias);
+            }
+            Statement::Throw { span, value } => {
+                self.expression(&value.data)?;
+                self.chunk.emit_byte(OpCode::Throw, *span);
+            }
+            Statement::Try {
+                body,
+                catch,
+                finally,
+            } => self.try_statement(body, catch.as_ref(), finally.as_ref())?,
             Statement::FunctionDeclaration(declaration) => {
                 self.function_declaration(declaration)?
             }
@@ -529,17 +851,184 @@ impl<'src, StdErr: Write> Compiler<'src, StdErr> {
         Ok(())
     }
 
-    fn top(mut self, top: &Statements) -> CodegenResult<Chunk> {
-        self.define_native_function("clock", |values| {
-            if !values.is_empty() {
-                return Err(CallError::ArityMismatch(0));
+    /// Pops every handler in the current function and runs their finally blocks, innermost first
+    /// The value being returned is kept on the stack the whole time
+    fn leave_handlers(&mut self) -> CodegenResult<()> {
+        let handlers = self.static_call_stack.last().unwrap().handlers.clone();
+        if handlers.is_empty() {
+            return Ok(());
+        }
+        self.begin_scope();
+        self.add_local("<return>");
+        for (i, finally) in handlers.iter().enumerate().rev() {
+            self.chunk.emit_impl_byte(OpCode::PopHandler);
+            if let Some(finally) = finally {
+                // a return inside of finally only has to leave the handlers outside of it
+                self.static_call_stack.last_mut().unwrap().handlers = handlers[..i].to_vec();
+                let res = self.scoped_block(&finally.data);
+                self.static_call_stack.last_mut().unwrap().handlers = handlers.clone();
+                res?;
+            }
+        }
+        self.forget_scope();
+        Ok(())
+    }
+
+    /// Compiles `try`, which goes to catch on a throw and then to finally either way:
+    /// ```text
+    ///     PUSH_HANDLER rethrow  ; only with both, so finally also runs if catch throws
+    ///     PUSH_HANDLER catch    ; or rethrow, without a catch
+    ///     body
+    ///     POP_HANDLER
+    ///     JUMP_REL finally
+    /// catch:
+    ///     catch body
+    /// finally:
+    ///     POP_HANDLER           ; only with both
+    ///     finally body
+    ///     JUMP_REL end
+    /// rethrow:
+    ///     finally body
+    ///     THROW
+    /// end:
+    /// ```
+    fn try_statement(
+        &mut self,
+        body: &Spanned<Statements>,
+        catch: Option<&Catch>,
+        finally: Option<&Spanned<Statements>>,
+    ) -> CodegenResult<()> {
+        let outer = match (catch, finally) {
+            (Some(_), Some(finally)) => {
+                let outer = self.chunk.emit_jump(OpCode::PushHandler, finally.span);
+                let frame = self.static_call_stack.last_mut().unwrap();
+                frame.handlers.push(Some(finally.clone()));
+                Some(outer)
+            }
+            _ => None,
+        };
+        let handler = self.chunk.emit_jump(OpCode::PushHandler, body.span);
+        let inner_finally = if outer.is_some() { None } else { finally };
+        self.guarded_block(&body.data, inner_finally)?;
+        self.chunk.emit_impl_byte(OpCode::PopHandler);
+
+        if let Some(Catch { id, body }) = catch {
+            let skip_catch = self.chunk.emit_jump(OpCode::JumpRel, Chunk::impl_span());
+            self.patch_jump(handler, body.span)?;
+            // the VM leaves what was thrown right where this local goes
+            self.begin_scope();
+            self.add_local(&id.data.0);
+            let res = self.block(&body.data);
+            self.end_scope();
+            res?;
+            self.patch_jump(skip_catch, Chunk::impl_span())?;
+        }
+
+        let rethrow = match outer {
+            Some(outer) => {
+                self.static_call_stack.last_mut().unwrap().handlers.pop();
+                self.chunk.emit_impl_byte(OpCode::PopHandler);
+                outer
+            }
+            None => handler,
+        };
+        let Some(finally) = finally else {
+            return Ok(());
+        };
+        self.scoped_block(&finally.data)?;
+        let end = self.chunk.emit_jump(OpCode::JumpRel, Chunk::impl_span());
+        self.patch_jump(rethrow, finally.span)?;
+        // this time, what was thrown is on the stack underneath finally's locals
+        self.begin_scope();
+        self.add_local("<error>");
+        self.scoped_block(&finally.data)?;
+        self.forget_scope();
+        self.chunk.emit_byte(OpCode::Throw, finally.span);
+        self.patch_jump(end, Chunk::impl_span())
+    }
+
+    /// Compiles a block that has a handler around it, so returns know about it
+    fn guarded_block(
+        &mut self,
+        block: &Statements,
+        finally: Option<&Spanned<Statements>>,
+    ) -> CodegenResult<()> {
+        let frame = self.static_call_stack.last_mut().unwrap();
+        frame.handlers.push(finally.cloned());
+        let res = self.scoped_block(block);
+        self.static_call_stack.last_mut().unwrap().handlers.pop();
+        res
+    }
+
+    /// Compiles a module into a function that builds it, the first time it's called
+    fn module(&mut self, module: &Module) -> CodegenResult<()> {
+        let index = self.chunk.add_module(&module.name);
+        let mut exports = vec![];
+        for statement in module.ast.0.iter() {
+            let id = match &statement.data {
+                Statement::VarDeclaration { id, .. } | Statement::ConstDeclaration { id, .. } => id,
+                Statement::FunctionDeclaration(FunctionDeclaration { name, .. }) => name,
+                _ => continue,
+            };
+            if !exports.contains(&id.data.0) {
+                exports.push(id.data.0.clone());
             }
-            // this is primarily for benchmarking anyways
-            static FIRST_TIME: OnceLock<Instant> = OnceLock::new();
-            let init = *FIRST_TIME.get_or_init(Instant::now);
-            let time = Instant::now().duration_since(init).as_secs_f64();
-            Ok(Value::Num(time))
+        }
+        if u8::try_from(exports.len()).is_err() {
+            self.simple_error(
+                codes::TOO_MANY_EXPORTS,
+                module.ast.0[0].span,
+                "Modules can't have more than 255 top level declarations",
+            );
+            return Err(());
+        };
+
+        let skip = self.chunk.emit_jump(OpCode::JumpRel, Chunk::impl_span());
+        self.module = Some(ModuleScope {
+            prefix: format!("{}::", module.name),
+            declared: exports.iter().cloned().collect(),
         });
+        self.begin_function_scope();
+        let start = self.chunk.instructions.len();
+        self.add_local("<module>");
+        self.block(&module.ast)?;
+
+        self.chunk.modules[index as usize].exports = exports
+            .into_iter()
+            .map(|export| {
+                let nameid = self.global(&export);
+                (export, nameid)
+            })
+            .collect();
+        emit_bytes!(self.chunk, Chunk::impl_span(); OpCode::Module, index);
+        self.chunk.emit_impl_byte(OpCode::Return);
+        self.forget_scope();
+        self.static_call_stack.pop();
+        self.module = None;
+        self.patch_jump(skip, Chunk::impl_span())?;
+
+        let function = ObjFunction {
+            min_arity: 0,
+            max_arity: 0,
+            rest: false,
+            upvalues: 0,
+            addr: start,
+            entries: ValidPtr::from(vec![start].into_boxed_slice()),
+            name: UnsafeString::from(module.name.as_str()),
+        };
+        let constant = self.chunk.add_constant(function.into());
+        self.module_functions.push(constant);
+        Ok(())
+    }
+
+    fn top(mut self, top: &Statements, modules: &Modules) -> CodegenResult<Chunk> {
+        for (name, function) in NATIVES.iter().chain(self.natives) {
+            self.define_native_function(name, *function);
+        }
+        self.imports = modules.imports.clone();
+        for module in modules.modules.iter() {
+            self.module(module)?;
+        }
         for statement in top.0.iter() {
             self.statement(&statement.data)?
         }
@@ -547,11 +1036,7 @@ impl<'src, StdErr: Write> Compiler<'src, StdErr> {
         Ok(self.chunk)
     }
 
-    fn define_native_function(
-        &mut self,
-        name: &str,
-        function: fn(&[Value]) -> Result<Value, CallError>,
-    ) {
+    fn define_native_function(&mut self, name: &str, function: NativeFn) {
         let nameid = self.chunk.globals.add_or_get(name);
         self.chunk.add_native(
             nameid,
@@ -563,9 +1048,14 @@ impl<'src, StdErr: Write> Compiler<'src, StdErr> {
     }
 }
 
-pub fn generate(source: &str, stderr: impl Write, ast: &Statements) -> CodegenResult<Chunk> {
-    let compiler = Compiler::new(source, stderr);
-    compiler.top(ast)
+pub fn generate(
+    ast: &Statements,
+    modules: &Modules,
+    natives: &[(&'static str, NativeFn)],
+    diagnostics: &mut Vec<Diagnostic>,
+) -> CodegenResult<Chunk> {
+    let compiler = Compiler::new(diagnostics, natives);
+    compiler.top(ast, modules)
 }
 
 #[cfg(test)]
@@ -644,6 +1134,19 @@ mod tests {
         "
     }
 
+    snap_codegen! {
+        tail_call,
+        "
+        fun count(n) {
+            if n == 0 {
+                return clock();
+            }
+            return count(n - 1);
+        }
+        return count(1);
+        "
+    }
+
     snap_codegen! {
         escape_assignment,
         r#"
@@ -0,0 +1,932 @@
+//! Prints a file back out in a consistent style, keeping its comments
+//!
+//! The AST doesn't have comments, parentheses or blank lines, so those come from the source:
+//! a comment is printed before the first thing that comes after it, or at the end of its line
+//! if it was there already, and a run of blank lines becomes one. Comments before a block's {,
+//! or between its } and an else, catch or finally, stay at the end of that line.
+
+mod doc;
+
+use crate::common::diagnostic::Diagnostic;
+use crate::common::ui::{FileId, SourceMap, Span, Spanned};
+
+use self::doc::Doc;
+use super::parse::lex::{Lexer, Token};
+use super::parse::{
+    parse_file, BinaryExpr, Call, Catch, Expression, Function, FunctionDeclaration, Literal,
+    Precedence, Statement, Statements,
+};
+
+/// How many characters lines are kept within, where they can be broken
+const WIDTH: usize = 80;
+
+/// What an expression has to bind as tightly as to go without parentheses
+#[derive(Copy, Clone)]
+struct Context {
+    min: Precedence,
+    /// Whether binding exactly as tightly as min isn't enough, like on the right of a + b
+    strict: bool,
+    /// Whether an assignment can go here
+    assign: bool,
+}
+
+impl Context {
+    /// Anything, like a statement or the right hand side of a variable
+    const ASSIGNABLE: Self = Self {
+        min: Precedence::Assignment,
+        strict: false,
+        assign: true,
+    };
+    /// Anything but an assignment, like an argument or a condition
+    const EXPRESSION: Self = Self {
+        min: Precedence::Assignment,
+        strict: false,
+        assign: false,
+    };
+
+    fn operand(min: Precedence, strict: bool) -> Self {
+        Self {
+            min,
+            strict,
+            assign: false,
+        }
+    }
+}
+
+struct Formatter<'src> {
+    source: &'src str,
+    /// Every token but comments, in order
+    tokens: Vec<Spanned<Token>>,
+    comments: Vec<Span>,
+    /// How many of the comments have been printed
+    printed: usize,
+    /// Where the last statement or comment that was printed ends, for finding blank lines
+    last_end: usize,
+}
+
+fn begin(span: Span) -> usize {
+    span.range().start
+}
+
+fn end(span: Span) -> usize {
+    span.range().end
+}
+
+/// `(a, b)` on one line if it fits, and otherwise with each item on its own line
+fn list(items: Vec<Doc>) -> Doc {
+    if items.is_empty() {
+        return "()".into();
+    }
+    let mut inner = vec![Doc::SoftLine];
+    for (i, item) in items.into_iter().enumerate() {
+        if i > 0 {
+            inner.extend([",".into(), Doc::Line]);
+        }
+        inner.push(item);
+    }
+    Doc::Group(vec![
+        "(".into(),
+        Doc::Indent(inner),
+        Doc::SoftLine,
+        ")".into(),
+    ])
+}
+
+impl<'src> Formatter<'src> {
+    fn new(source: &'src str, file: FileId) -> Self {
+        let mut tokens = vec![];
+        let mut comments = vec![];
+        // the file already parsed, so every token is valid
+        for token in Lexer::with_comments(source, file).flatten() {
+            match token.data {
+                Token::Comment => comments.push(token.span),
+                _ => tokens.push(token),
+            }
+        }
+        Self {
+            source,
+            tokens,
+            comments,
+            printed: 0,
+            last_end: 0,
+        }
+    }
+
+    fn next_comment(&self) -> Option<Span> {
+        self.comments.get(self.printed).copied()
+    }
+
+    fn comment(&mut self, prefix: &str) -> Doc {
+        let comment = self.comments[self.printed];
+        self.printed += 1;
+        self.last_end = end(comment);
+        Doc::Comment(format!("{prefix}{}", self.source[comment].trim_end()))
+    }
+
+    /// Comments in the middle of an expression, which go on their own lines before it
+    fn comments_before(&mut self, offset: usize) -> Doc {
+        let mut docs = vec![];
+        while self.next_comment().is_some_and(|c| begin(c) < offset) {
+            docs.extend([self.comment(""), Doc::HardLine]);
+        }
+        Doc::Concat(docs)
+    }
+
+    /// Starts a new line in a list of statements, keeping a blank line if there was one
+    fn separate(&self, lines: &mut Vec<Doc>, next: usize) {
+        if lines.is_empty() {
+            return;
+        }
+        lines.push(Doc::HardLine);
+        let between = &self.source[self.last_end.min(next)..next];
+        if between.matches('\n').count() > 1 {
+            lines.push(Doc::HardLine);
+        }
+    }
+
+    /// Comments between statements, which each get a line of their own
+    fn comment_lines(&mut self, before: usize, lines: &mut Vec<Doc>) {
+        while let Some(comment) = self.next_comment().filter(|c| begin(*c) < before) {
+            self.separate(lines, begin(comment));
+            lines.push(self.comment(""));
+        }
+    }
+
+    /// Where a statement really ends, since the spans of some leave out their last few tokens,
+    /// like the ) of a call or the ; after an expression
+    fn statement_end(&self, statement: &Spanned<Statement>) -> usize {
+        match statement.data {
+            Statement::Expr(_)
+            | Statement::Print(_)
+            | Statement::VarDeclaration { .. }
+            | Statement::ConstDeclaration { .. }
+            | Statement::Return { .. }
+            | Statement::Throw { .. }
+            | Statement::Import { .. } => {
+                let after = self
+                    .tokens
+                    .partition_point(|token| begin(token.span) < end(statement.span));
+                let semicolon = self.tokens[after..]
+                    .iter()
+                    .find(|token| token.data == Token::Semicolon);
+                semicolon.map_or(end(statement.span), |token| end(token.span))
+            }
+            _ => end(statement.span),
+        }
+    }
+
+    /// Comments that weren't printed inside the statement, along with one after it on the same
+    /// line, which go at the end of its last line
+    fn trailing_comments(&mut self, statement_end: usize) -> Doc {
+        let mut docs = vec![];
+        while let Some(comment) = self.next_comment() {
+            let inside = begin(comment) < statement_end;
+            let same_line = || {
+                let between = &self.source[statement_end..begin(comment)];
+                between.chars().all(|c| c != '\n' && c.is_whitespace())
+            };
+            if !inside && !same_line() {
+                break;
+            }
+            if docs.is_empty() {
+                docs.push(self.comment(" "));
+            } else {
+                docs.extend([Doc::HardLine, self.comment("")]);
+            }
+        }
+        Doc::Concat(docs)
+    }
+
+    /// Comments before an offset that go at the end of the line they're put on, like ones
+    /// between an if's header and its {
+    fn trailing_until(&mut self, offset: usize) -> Doc {
+        let mut docs = vec![];
+        while self.next_comment().is_some_and(|c| begin(c) < offset) {
+            if docs.is_empty() {
+                docs.push(self.comment(" "));
+            } else {
+                docs.extend([Doc::HardLine, self.comment("")]);
+            }
+        }
+        Doc::Concat(docs)
+    }
+
+    /// The keyword after a block, like else, which the comments between them don't move past
+    fn after_block(&mut self, block: &Spanned<Statements>, keyword: &str) -> Doc {
+        let next = self
+            .tokens
+            .partition_point(|token| begin(token.span) < end(block.span));
+        let keyword_start = begin(self.tokens[next].span);
+        if self
+            .next_comment()
+            .is_none_or(|c| begin(c) >= keyword_start)
+        {
+            return Doc::text(format!(" {keyword} "));
+        }
+        let comments = self.trailing_until(keyword_start);
+        Doc::Concat(vec![
+            comments,
+            Doc::HardLine,
+            Doc::text(format!("{keyword} ")),
+        ])
+    }
+
+    /// The statements and the comments around them, up to the offset where they end
+    fn lines(&mut self, statements: &[Spanned<Statement>], until: usize) -> Vec<Doc> {
+        let mut lines = vec![];
+        for statement in statements {
+            self.comment_lines(begin(statement.span), &mut lines);
+            self.separate(&mut lines, begin(statement.span));
+            let doc = self.statement(statement);
+            let statement_end = self.statement_end(statement);
+            self.last_end = statement_end;
+            let trailing = self.trailing_comments(statement_end);
+            // a comment inside the statement can end before it does
+            self.last_end = self.last_end.max(statement_end);
+            lines.push(Doc::Concat(vec![doc, trailing]));
+        }
+        self.comment_lines(until, &mut lines);
+        lines
+    }
+
+    fn program(&mut self, ast: &Statements) -> Doc {
+        let mut lines = self.lines(&ast.0, self.source.len());
+        if !lines.is_empty() {
+            lines.push(Doc::HardLine);
+        }
+        Doc::Concat(lines)
+    }
+
+    /// `{ statements }`, where span is the block's, including its braces
+    fn block_of(&mut self, span: Span, statements: &[Spanned<Statement>]) -> Doc {
+        // comments left over from the header, or after the { on its line, stay on that line
+        let line_end = self.source[begin(span)..]
+            .find('\n')
+            .map_or(self.source.len(), |newline| begin(span) + newline);
+        let first = statements.first().map_or(end(span), |s| begin(s.span));
+        let printed = self.printed;
+        let header = self.trailing_until(line_end.min(first));
+        let lines = self.lines(statements, end(span));
+        if lines.is_empty() && self.printed == printed {
+            return "{}".into();
+        }
+        let mut inner = vec![header];
+        if !lines.is_empty() {
+            inner.extend([Doc::HardLine, Doc::Concat(lines)]);
+        }
+        Doc::Group(vec![
+            "{".into(),
+            Doc::Indent(inner),
+            Doc::HardLine,
+            "}".into(),
+        ])
+    }
+
+    fn block(&mut self, block: &Spanned<Statements>) -> Doc {
+        self.block_of(block.span, &block.data.0)
+    }
+
+    /// Whether a while loop came from a for loop, which is what the parser turns them into.
+    /// The condition comes after a ;, or is the ; when it was left out
+    fn is_for_loop(&self, cond: &Spanned<Expression>) -> bool {
+        let next = self
+            .tokens
+            .partition_point(|token| begin(token.span) < begin(cond.span));
+        let before = self.tokens[..next]
+            .iter()
+            .rev()
+            .find(|token| token.data != Token::LParen);
+        let at = self.tokens.get(next);
+        [before, at]
+            .into_iter()
+            .flatten()
+            .any(|token| token.data == Token::Semicolon)
+    }
+
+    fn for_loop(
+        &mut self,
+        init: Option<&Spanned<Statement>>,
+        cond: &Spanned<Expression>,
+        body: &Spanned<Statements>,
+    ) -> Doc {
+        let mut docs = vec!["for ".into()];
+        match init {
+            Some(init) => docs.push(self.statement(init)),
+            None => docs.push(";".into()),
+        }
+        // a missing condition is a true where the ; is
+        if &self.source[cond.span] != ";" {
+            docs.extend([" ".into(), self.expression(cond, Context::ASSIGNABLE)]);
+        }
+        docs.push(";".into());
+        // the increment is put at the end of the body, but it comes before it
+        let statements = &body.data.0[..];
+        let (increment, statements) = match statements.split_last() {
+            Some((last, rest)) if begin(last.span) < begin(body.span) => (Some(last), rest),
+            _ => (None, statements),
+        };
+        if let Some(Spanned {
+            data: Statement::Expr(increment),
+            ..
+        }) = increment
+        {
+            docs.extend([" ".into(), self.expression(increment, Context::ASSIGNABLE)]);
+        }
+        docs.extend([" ".into(), self.block_of(body.span, statements)]);
+        Doc::Concat(docs)
+    }
+
+    fn statement(&mut self, statement: &Spanned<Statement>) -> Doc {
+        let docs = match &statement.data {
+            Statement::Expr(expr) => vec![self.expression(expr, Context::ASSIGNABLE), ";".into()],
+            Statement::Print(expr) => vec![
+                "print ".into(),
+                self.expression(expr, Context::EXPRESSION),
+                ";".into(),
+            ],
+            Statement::VarDeclaration { id, rhs } => {
+                let mut docs = vec![Doc::text(format!("var {}", id.data))];
+                if let Some(rhs) = rhs {
+                    docs.extend([" = ".into(), self.expression(rhs, Context::ASSIGNABLE)]);
+                }
+                docs.push(";".into());
+                docs
+            }
+            Statement::ConstDeclaration { id, rhs } => vec![
+                Doc::text(format!("const {} = ", id.data)),
+                self.expression(rhs, Context::ASSIGNABLE),
+                ";".into(),
+            ],
+            Statement::FunctionDeclaration(FunctionDeclaration { name, function }) => vec![
+                Doc::text(format!("fun {}", name.data)),
+                self.function(function),
+            ],
+            // a for loop with an initializer is a block around it and the loop
+            Statement::Block(block) if !self.source[block.span].starts_with('{') => {
+                match &block.data.0[..] {
+                    [init, Spanned {
+                        data: Statement::While { cond, body },
+                        ..
+                    }] => vec![self.for_loop(Some(init), cond, body)],
+                    _ => vec![self.block_of(block.span, &block.data.0)],
+                }
+            }
+            Statement::Block(block) => vec![self.block(block)],
+            Statement::IfElse {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let mut docs = vec![
+                    "if ".into(),
+                    self.expression(cond, Context::EXPRESSION),
+                    " ".into(),
+                    self.block(then_branch),
+                ];
+                if let Some(else_branch) = else_branch {
+                    let keyword = self.after_block(then_branch, "else");
+                    docs.extend([keyword, self.block(else_branch)]);
+                }
+                docs
+            }
+            Statement::While { cond, body } if self.is_for_loop(cond) => {
+                vec![self.for_loop(None, cond, body)]
+            }
+            Statement::While { cond, body } => vec![
+                "while ".into(),
+                self.expression(cond, Context::EXPRESSION),
+                " ".into(),
+                self.block(body),
+            ],
+            Statement::Return { span: _, value } => {
+                let mut docs = vec!["return".into()];
+                if let Some(value) = value {
+                    docs.extend([" ".into(), self.expression(value, Context::EXPRESSION)]);
+                }
+                docs.push(";".into());
+                docs
+            }
+            Statement::Throw { span: _, value } => vec![
+                "throw ".into(),
+                self.expression(value, Context::EXPRESSION),
+                ";".into(),
+            ],
+            Statement::Import { path, alias } => vec![Doc::text(format!(
+                "import {} as {};",
+                &self.source[path.span], alias.data
+            ))],
+            Statement::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                let mut docs = vec!["try ".into(), self.block(body)];
+                let mut last = body;
+                if let Some(Catch { id, body }) = catch {
+                    docs.extend([
+                        self.after_block(last, "catch"),
+                        Doc::text(format!("({}) ", id.data)),
+                        self.block(body),
+                    ]);
+                    last = body;
+                }
+                if let Some(finally) = finally {
+                    docs.extend([self.after_block(last, "finally"), self.block(finally)]);
+                }
+                docs
+            }
+        };
+        Doc::Concat(docs)
+    }
+
+    fn parameters(&mut self, function: &Function) -> Doc {
+        let mut parameters = vec![];
+        for arg in function.args.iter() {
+            let comments = self.comments_before(begin(arg.span));
+            parameters.push(Doc::Concat(vec![comments, Doc::text(&arg.data.0)]));
+        }
+        for (arg, default) in function.defaults.iter() {
+            let comments = self.comments_before(begin(arg.span));
+            parameters.push(Doc::Concat(vec![
+                comments,
+                Doc::text(format!("{} = ", arg.data)),
+                self.expression(default, Context::EXPRESSION),
+            ]));
+        }
+        if let Some(rest) = &function.rest {
+            let comments = self.comments_before(begin(rest.span));
+            parameters.push(Doc::Concat(vec![
+                comments,
+                Doc::text(format!("...{}", rest.data)),
+            ]));
+        }
+        list(parameters)
+    }
+
+    /// The parameters and body of a function, after its name
+    fn function(&mut self, function: &Function) -> Doc {
+        let parameters = self.parameters(function);
+        Doc::Concat(vec![parameters, " ".into(), self.block(&function.body)])
+    }
+
+    /// How tightly an expression binds, which is Primary for anything that can be the operand
+    /// of - or !, since those only take primaries
+    fn precedence(&self, expr: &Spanned<Expression>) -> Precedence {
+        match &expr.data {
+            Expression::Assignment { .. } | Expression::CompoundAssignment { .. } => {
+                Precedence::Assignment
+            }
+            // the body of an arrow function takes everything after it
+            Expression::Lambda(function) if self.arrow_value(function).is_some() => {
+                Precedence::Assignment
+            }
+            Expression::Ternary { .. } => Precedence::Ternary,
+            Expression::Binary(BinaryExpr { kind, .. }) => Precedence::from(kind.data),
+            Expression::Call(_) | Expression::Get { .. } => Precedence::Call,
+            Expression::Unary { .. }
+            | Expression::Literal(_)
+            | Expression::Identifier(_)
+            | Expression::Lambda(_) => Precedence::Primary,
+        }
+    }
+
+    /// What an arrow function returns, if the lambda was written as one
+    fn arrow_value<'a>(&self, function: &'a Spanned<Function>) -> Option<&'a Spanned<Expression>> {
+        if self.source[function.span].starts_with("fun") {
+            return None;
+        }
+        match &function.data.body.data.0[..] {
+            [Spanned {
+                data:
+                    Statement::Return {
+                        value: Some(value), ..
+                    },
+                ..
+            }] => Some(value),
+            _ => None,
+        }
+    }
+
+    fn expression(&mut self, expr: &Spanned<Expression>, context: Context) -> Doc {
+        let comments = self.comments_before(begin(expr.span));
+        let precedence = self.precedence(expr);
+        let is_assignment = matches!(
+            expr.data,
+            Expression::Assignment { .. } | Expression::CompoundAssignment { .. }
+        );
+        let parenthesize = precedence < context.min
+            || context.strict && precedence == context.min
+            || is_assignment && !context.assign;
+        let doc = self.unparenthesized(expr);
+        if parenthesize {
+            Doc::Concat(vec![comments, "(".into(), doc, ")".into()])
+        } else {
+            Doc::Concat(vec![comments, doc])
+        }
+    }
+
+    /// a + b + c is one group, so that it breaks after every operator or none of them
+    fn binary(&mut self, binary: &BinaryExpr) -> Doc {
+        let precedence = Precedence::from(binary.kind.data);
+        let mut operations = vec![binary];
+        let mut first = &binary.lhs;
+        while let Expression::Binary(lhs) = &first.data {
+            if Precedence::from(lhs.kind.data) != precedence {
+                break;
+            }
+            operations.push(lhs);
+            first = &lhs.lhs;
+        }
+        let first = self.expression(first, Context::operand(precedence, false));
+        let mut rest = vec![];
+        for operation in operations.into_iter().rev() {
+            rest.extend([Doc::text(format!(" {}", operation.kind.data)), Doc::Line]);
+            rest.push(self.expression(&operation.rhs, Context::operand(precedence, true)));
+        }
+        Doc::Group(vec![first, Doc::Indent(rest)])
+    }
+
+    fn unparenthesized(&mut self, expr: &Spanned<Expression>) -> Doc {
+        match &expr.data {
+            Expression::Assignment { id, rhs } => Doc::Concat(vec![
+                Doc::text(format!("{} = ", id.data)),
+                self.expression(rhs, Context::ASSIGNABLE),
+            ]),
+            Expression::CompoundAssignment { id, kind, rhs } => Doc::Concat(vec![
+                Doc::text(format!("{} {} ", id.data, kind.data)),
+                self.expression(rhs, Context::ASSIGNABLE),
+            ]),
+            Expression::Binary(binary) => self.binary(binary),
+            Expression::Unary { kind, val } => Doc::Concat(vec![
+                Doc::text(kind.data.to_string()),
+                self.expression(val, Context::operand(Precedence::Primary, false)),
+            ]),
+            Expression::Literal(literal) => match &literal.data {
+                Literal::Number(_) | Literal::String(_) => self.source[literal.span].into(),
+                Literal::Boolean(b) => Doc::text(b.to_string()),
+                Literal::Nil => "nil".into(),
+            },
+            Expression::Identifier(id) => Doc::text(&id.data.0),
+            Expression::Ternary {
+                cond,
+                then_branch,
+                else_branch,
+            } => Doc::Group(vec![
+                self.expression(cond, Context::operand(Precedence::Ternary, true)),
+                Doc::Indent(vec![
+                    Doc::Line,
+                    "? ".into(),
+                    self.expression(then_branch, Context::EXPRESSION),
+                    Doc::Line,
+                    ": ".into(),
+                    self.expression(else_branch, Context::operand(Precedence::Ternary, false)),
+                ]),
+            ]),
+            Expression::Call(Call { callee, args }) => {
+                let callee = self.expression(callee, Context::operand(Precedence::Call, false));
+                let args = args
+                    .iter()
+                    .map(|arg| self.expression(arg, Context::EXPRESSION))
+                    .collect();
+                Doc::Concat(vec![callee, list(args)])
+            }
+            Expression::Get { object, name } => Doc::Concat(vec![
+                self.expression(object, Context::operand(Precedence::Call, false)),
+                Doc::text(format!(".{}", name.data)),
+            ]),
+            Expression::Lambda(function) => match self.arrow_value(function) {
+                Some(value) => Doc::Concat(vec![
+                    self.parameters(&function.data),
+                    " => ".into(),
+                    self.expression(value, Context::ASSIGNABLE),
+                ]),
+                None => Doc::Concat(vec!["fun ".into(), self.function(&function.data)]),
+            },
+        }
+    }
+}
+
+/// The file formatted, or why it couldn't be parsed
+pub fn format(sources: &SourceMap, file: FileId) -> Result<String, Vec<Diagnostic>> {
+    let ast = parse_file(sources, file)?;
+    let mut formatter = Formatter::new(sources.text(file), file);
+    let doc = formatter.program(&ast);
+    Ok(doc::render(&doc, WIDTH))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format;
+    use crate::common::ui::{SourceMap, SCRIPT_NAME};
+    use crate::compiler::parse::parse;
+
+    fn fmt(source: &str) -> String {
+        let mut sources = SourceMap::default();
+        let file = sources.add(SCRIPT_NAME, source);
+        match format(&sources, file) {
+            Ok(formatted) => formatted,
+            Err(diagnostics) => panic!("{source}\n{diagnostics:?}"),
+        }
+    }
+
+    /// The AST without any spans, which formatting moves around
+    fn shape(source: &str) -> String {
+        let ast = parse(source).unwrap_or_else(|e| panic!("{source}\n{e:?}"));
+        let mut debug = format!("{ast:?}");
+        while let Some(start) = debug.find("Span {") {
+            let end = start + debug[start..].find('}').unwrap();
+            debug.replace_range(start..=end, "");
+        }
+        debug
+    }
+
+    fn comments(source: &str) -> Vec<&str> {
+        let mut comments = vec![];
+        let mut rest = source;
+        while let Some((_, comment)) = rest.split_once("//") {
+            let (comment, after) = comment.split_once('\n').unwrap_or((comment, ""));
+            comments.push(comment.trim_end());
+            rest = after;
+        }
+        comments
+    }
+
+    /// Formats source and checks that nothing but the formatting changed
+    fn check(source: &str) -> String {
+        let formatted = fmt(source);
+        assert_eq!(
+            fmt(&formatted),
+            formatted,
+            "Formatting twice changed\n{source}"
+        );
+        assert_eq!(
+            shape(&formatted),
+            shape(source),
+            "The AST changed\n{formatted}"
+        );
+        assert_eq!(
+            comments(&formatted),
+            comments(source),
+            "The comments changed\n{formatted}"
+        );
+        formatted
+    }
+
+    #[test]
+    fn statements() {
+        let source = "var x=1;const y = x+2; fun add(a,b=2,...rest){return a+b;}\nprint add(x, y);\nif (x < 2) { print \"small\"; } else { x = 2; }\nwhile x>0{x-=1;}\ntry { throw \"x\"; } catch (e) { print e; } finally {}\nimport \"math.lox\" as m;\nprint m.pi;";
+        assert_eq!(
+            check(source),
+            "\
+var x = 1;
+const y = x + 2;
+fun add(a, b = 2, ...rest) {
+    return a + b;
+}
+print add(x, y);
+if x < 2 {
+    print \"small\";
+} else {
+    x = 2;
+}
+while x > 0 {
+    x -= 1;
+}
+try {
+    throw \"x\";
+} catch (e) {
+    print e;
+} finally {}
+import \"math.lox\" as m;
+print m.pi;
+"
+        );
+    }
+
+    #[test]
+    fn for_loops() {
+        let source = "for var i = 0; i < 3; i += 1 { print i; }\nfor ;; {}\nfor i = 0; (i < 3); { i += 1; }\nwhile true {}";
+        assert_eq!(
+            check(source),
+            "\
+for var i = 0; i < 3; i += 1 {
+    print i;
+}
+for ;; {}
+for i = 0; i < 3; {
+    i += 1;
+}
+while true {}
+"
+        );
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(
+            check("print ((1 - 2) - (3 - 4)) * -(f(1)) / (-f)(2);"),
+            "print (1 - 2 - (3 - 4)) * -(f(1)) / -f(2);\n"
+        );
+        assert_eq!(
+            check("print (a = 1); print (a ? b : c) ? (d ?? e) : (f ? g : h);"),
+            "print (a = 1);\nprint (a ? b : c) ? d ?? e : f ? g : h;\n"
+        );
+        assert_eq!(
+            check("var f = (a) => (b) => a + b; print ((x) => x)(1) + ((y) => y);"),
+            "var f = (a) => (b) => a + b;\nprint ((x) => x)(1) + ((y) => y);\n"
+        );
+        assert_eq!(
+            check("fun () { print 1; }();"),
+            "fun () {\n    print 1;\n}();\n"
+        );
+    }
+
+    #[test]
+    fn comments_are_kept() {
+        let source = "\
+// the header
+
+
+var x = 1;   // trailing
+f(a, // after a
+  b);
+{
+    // only a comment
+}
+if x { // after the brace
+    print x;
+    // at the end
+}
+// before else
+else { print 0; }
+while x // the header
+{ x -= 1; }
+fun f()
+// before the brace
+{}
+try { f(); } // before catch
+catch (e) { print e; }
+// before finally
+finally {}
+// the footer";
+        assert_eq!(
+            check(source),
+            "\
+// the header
+
+var x = 1; // trailing
+f(
+    a,
+    // after a
+    b
+);
+{
+    // only a comment
+}
+if x { // after the brace
+    print x;
+    // at the end
+} // before else
+else {
+    print 0;
+}
+while x { // the header
+    x -= 1;
+}
+fun f() { // before the brace
+}
+try {
+    f();
+} // before catch
+catch (e) {
+    print e;
+} // before finally
+finally {}
+// the footer
+"
+        );
+    }
+
+    #[test]
+    fn long_lines_wrap() {
+        let source = "var total = first(1, 2) + second(3, 4) + third(5, 6) + fourth(7, 8) + fifth(9, 10);\nprint someCondition and anotherCondition ? theFirstAlternative : theSecondAlternative;\nmap(list, fun (item) { return item * 2; });";
+        assert_eq!(
+            check(source),
+            "\
+var total = first(1, 2) +
+    second(3, 4) +
+    third(5, 6) +
+    fourth(7, 8) +
+    fifth(9, 10);
+print someCondition and anotherCondition
+    ? theFirstAlternative
+    : theSecondAlternative;
+map(list, fun (item) {
+    return item * 2;
+});
+"
+        );
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(check(""), "");
+        assert_eq!(check("\n\n// just this\n\n"), "// just this\n");
+    }
+
+    /// xorshift, so that the random programs are the same on every run
+    struct Random(u64);
+
+    impl Random {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn pick<'a>(&mut self, options: &[&'a str]) -> &'a str {
+            options[self.below(options.len())]
+        }
+
+        /// Somewhere two tokens can be separated, which is sometimes a comment
+        fn gap(&mut self) -> &'static str {
+            self.pick(&[" ", " ", " ", " ", " ", "  ", "\n", " // gap\n"])
+        }
+
+        fn expression(&mut self, depth: usize) -> String {
+            if depth == 0 || self.below(4) == 0 {
+                let atoms = ["a", "b", "count", "1", "2.50", "\"str\"", "true", "nil"];
+                return self.pick(&atoms).to_owned();
+            }
+            let mut e = || self.expression(depth - 1);
+            let (a, b, c) = (e(), e(), e());
+            let gap = self.gap();
+            match self.below(11) {
+                0 | 1 => {
+                    let ops = ["+", "-", "*", "/", "==", "<", "and", "or", "??"];
+                    format!("{a} {}{gap}{b}", self.pick(&ops))
+                }
+                2 => format!("{}{a}", self.pick(&["-", "!"])),
+                3 | 4 => format!("({a})"),
+                5 => format!("{a} ?{gap}{b} : {c}"),
+                6 => format!("f({a},{gap}{b}, {c})"),
+                7 => format!("veryLongFunctionName(firstArgument + {a}, secondArgument, {b})"),
+                8 => format!("m.{}", self.pick(&["x", "y"])),
+                9 => format!("(x, y = {a}) => {b}"),
+                _ => format!("fun (x) {{{gap}return {a};{gap}}}"),
+            }
+        }
+
+        fn block(&mut self, depth: usize) -> String {
+            let statements: String = (0..self.below(4))
+                .map(|_| self.statement(depth - 1) + self.gap())
+                .collect();
+            format!("{{{}{statements}}}", self.gap())
+        }
+
+        fn statement(&mut self, depth: usize) -> String {
+            let e = self.expression(2);
+            if depth == 0 {
+                return format!("print {e};");
+            }
+            let gap = self.gap();
+            match self.below(12) {
+                0 => format!("var {} ={gap}{e};", self.pick(&["a", "b", "x"])),
+                1 => format!("const c = {e};"),
+                2 => format!("print {e};"),
+                3 => format!("a = {e};"),
+                4 => format!("if {e} {} else {}", self.block(depth), self.block(depth)),
+                5 => format!("while {e}{gap}{}", self.block(depth)),
+                6 => format!("for var i = 0; i < {e}; i += 1 {}", self.block(depth)),
+                7 => format!("for ; {e}; {}", self.block(depth)),
+                8 => format!("fun g(p, q = {e}) {}", self.block(depth)),
+                9 => format!("return {e};"),
+                10 => format!(
+                    "try {} catch (err) {}",
+                    self.block(depth),
+                    self.block(depth)
+                ),
+                _ => self.block(depth),
+            }
+        }
+    }
+
+    #[test]
+    fn formatting_is_idempotent_and_keeps_the_ast() {
+        let mut checked = 0;
+        for seed in 1..200u64 {
+            let mut random = Random(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+            let source: String = (0..1 + random.below(6))
+                .map(|_| random.statement(3) + random.pick(&["\n", "\n\n\n", " // end\n"]))
+                .collect();
+            // arrow functions take everything after them, so some of these don't parse
+            if parse(&source).is_err() {
+                continue;
+            }
+            check(&source);
+            checked += 1;
+        }
+        assert!(checked > 150, "Only {checked} programs parsed");
+    }
+}
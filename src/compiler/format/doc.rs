@@ -0,0 +1,244 @@
+//! A document of text and places where lines may break, which is laid out to fit a width
+//!
+//! This is Wadler's prettier printer, more or less: a group is printed on one line if it fits,
+//! and otherwise every line directly inside it breaks.
+
+/// Spaces per level of indentation
+const INDENT: usize = 4;
+
+#[derive(Debug, Clone)]
+pub enum Doc {
+    Text(String),
+    /// Text that doesn't count towards the width, so that a long trailing comment doesn't make
+    /// the code before it wrap
+    Comment(String),
+    /// A space, or a line break when its group is broken
+    Line,
+    /// Nothing, or a line break when its group is broken
+    SoftLine,
+    /// Always a line break, which also breaks the group it's directly in
+    HardLine,
+    Indent(Vec<Doc>),
+    Group(Vec<Doc>),
+    Concat(Vec<Doc>),
+}
+
+impl Doc {
+    pub fn text(text: impl Into<String>) -> Self {
+        Self::Text(text.into())
+    }
+
+    /// Whether the group has a hard line that isn't in a nested group, so it can't be flat
+    fn must_break(docs: &[Doc]) -> bool {
+        docs.iter().any(|doc| match doc {
+            Doc::HardLine => true,
+            Doc::Indent(docs) | Doc::Concat(docs) => Self::must_break(docs),
+            _ => false,
+        })
+    }
+}
+
+impl From<&str> for Doc {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_owned())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+struct Printer {
+    out: String,
+    width: usize,
+    column: usize,
+    /// The indentation owed to the current line, which is only written before text so that
+    /// blank lines don't end up with trailing whitespace
+    pending: Option<usize>,
+}
+
+impl Printer {
+    fn write(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        if let Some(indent) = self.pending.take() {
+            self.out.extend(std::iter::repeat_n(' ', indent));
+            self.column = indent;
+        }
+        self.out.push_str(text);
+        // multiline strings carry on from wherever their last line ends
+        match text.rfind('\n') {
+            Some(newline) => self.column = text[newline + 1..].chars().count(),
+            None => self.column += text.chars().count(),
+        }
+    }
+
+    fn newline(&mut self, indent: usize) {
+        self.out.push('\n');
+        self.column = 0;
+        self.pending = Some(indent);
+    }
+
+    /// Whether the next command fits in what's left of the line, along with everything after it
+    /// up to the next line break
+    fn fits(&self, next: (usize, Mode, &Doc), rest: &[(usize, Mode, &Doc)]) -> bool {
+        let column = self.pending.unwrap_or(self.column);
+        let mut remaining = self.width as isize - column as isize;
+        let mut stack = vec![next];
+        let mut rest = rest.iter().rev();
+        while remaining >= 0 {
+            let Some((indent, mode, doc)) = stack.pop().or_else(|| rest.next().copied()) else {
+                return true;
+            };
+            match doc {
+                Doc::Text(text) => match text.split_once('\n') {
+                    Some((line, _)) => return remaining >= line.chars().count() as isize,
+                    None => remaining -= text.chars().count() as isize,
+                },
+                Doc::Comment(_) => {}
+                Doc::Line | Doc::SoftLine if mode == Mode::Break => return true,
+                Doc::Line => remaining -= 1,
+                Doc::SoftLine => {}
+                Doc::HardLine => return mode == Mode::Break,
+                Doc::Indent(docs) => {
+                    stack.extend(docs.iter().rev().map(|doc| (indent + INDENT, mode, doc)));
+                }
+                Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc))),
+                Doc::Group(docs) => {
+                    // groups that have to break are measured up to their first line break
+                    let mode = if Doc::must_break(docs) {
+                        Mode::Break
+                    } else {
+                        mode
+                    };
+                    stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc)));
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Lays the document out in lines of at most width characters, where it can
+pub fn render(doc: &Doc, width: usize) -> String {
+    let mut printer = Printer {
+        out: String::new(),
+        width,
+        column: 0,
+        pending: None,
+    };
+    let mut stack = vec![(0, Mode::Break, doc)];
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(text) | Doc::Comment(text) => printer.write(text),
+            Doc::Line if mode == Mode::Flat => printer.write(" "),
+            Doc::SoftLine if mode == Mode::Flat => {}
+            Doc::Line | Doc::SoftLine | Doc::HardLine => printer.newline(indent),
+            // a flat group stays on the line it started on, so a group that breaks inside it
+            // lines up with that line, like a function body in a list of arguments
+            Doc::Indent(docs) if mode == Mode::Flat => {
+                stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc)));
+            }
+            Doc::Indent(docs) => {
+                stack.extend(docs.iter().rev().map(|doc| (indent + INDENT, mode, doc)));
+            }
+            Doc::Concat(docs) => stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc))),
+            Doc::Group(docs) => {
+                let mode =
+                    if Doc::must_break(docs) || !printer.fits((indent, Mode::Flat, doc), &stack) {
+                        Mode::Break
+                    } else {
+                        Mode::Flat
+                    };
+                stack.extend(docs.iter().rev().map(|doc| (indent, mode, doc)));
+            }
+        }
+    }
+    printer.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, Doc};
+
+    fn call(name: &str, args: &[&str]) -> Doc {
+        let mut list = vec![Doc::SoftLine];
+        for (i, arg) in args.iter().enumerate() {
+            if i > 0 {
+                list.extend([",".into(), Doc::Line]);
+            }
+            list.push((*arg).into());
+        }
+        Doc::Group(vec![
+            name.into(),
+            "(".into(),
+            Doc::Indent(list),
+            Doc::SoftLine,
+            ")".into(),
+        ])
+    }
+
+    #[test]
+    fn groups_break_when_they_dont_fit() {
+        let doc = call("f", &["aaaa", "bbbb"]);
+        assert_eq!(render(&doc, 80), "f(aaaa, bbbb)");
+        assert_eq!(render(&doc, 10), "f(\n    aaaa,\n    bbbb\n)");
+    }
+
+    #[test]
+    fn whatever_follows_counts() {
+        let doc = Doc::Concat(vec![call("f", &["a"]), ";".into()]);
+        assert_eq!(render(&doc, 5), "f(a);");
+        assert_eq!(render(&doc, 4), "f(\n    a\n);");
+    }
+
+    #[test]
+    fn hard_lines() {
+        let doc = Doc::Group(vec![
+            "{".into(),
+            Doc::Indent(vec![
+                Doc::HardLine,
+                "a".into(),
+                Doc::HardLine,
+                Doc::HardLine,
+                "b".into(),
+            ]),
+            Doc::HardLine,
+            "}".into(),
+        ]);
+        // no trailing whitespace on the blank line
+        assert_eq!(render(&doc, 80), "{\n    a\n\n    b\n}");
+        // a group that has to break only counts up to its first line break
+        let doc = Doc::Concat(vec![call("f", &["x"]), " ".into(), doc]);
+        assert_eq!(render(&doc, 8), "f(x) {\n    a\n\n    b\n}");
+    }
+
+    #[test]
+    fn groups_that_break_inside_flat_ones() {
+        let body = Doc::Group(vec![
+            "{".into(),
+            Doc::Indent(vec![Doc::HardLine, "body".into()]),
+            Doc::HardLine,
+            "}".into(),
+        ]);
+        let doc = Doc::Group(vec![
+            "f(".into(),
+            Doc::Indent(vec![Doc::SoftLine, "a,".into(), Doc::Line, body]),
+            Doc::SoftLine,
+            ")".into(),
+        ]);
+        assert_eq!(render(&doc, 80), "f(a, {\n    body\n})");
+    }
+
+    #[test]
+    fn comments_have_no_width() {
+        let doc = Doc::Concat(vec![
+            call("f", &["a"]),
+            Doc::Comment(" // a long comment".to_owned()),
+        ]);
+        assert_eq!(render(&doc, 5), "f(a) // a long comment");
+    }
+}
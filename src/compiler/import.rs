@@ -0,0 +1,154 @@
+//! Finds every file that's imported, before anything is compiled
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::common::codes::{self, Code};
+use crate::common::diagnostic::Diagnostic;
+use crate::common::ui::*;
+
+use super::parse::parse_file;
+use super::parse::Statement;
+use super::parse::Statements;
+use super::parse::StringLiteral;
+
+/// A file that was imported, which is compiled once no matter how often it's imported
+pub struct Module {
+    /// The path it was first imported by, unless another module already goes by that
+    pub name: String,
+    pub ast: Statements,
+}
+
+#[derive(Default)]
+pub struct Modules {
+    /// Modules always come after everything they import
+    pub modules: Vec<Module>,
+    /// Which module each import refers to, by the span of its path
+    pub imports: HashMap<Span, usize>,
+}
+
+struct Loader<'src> {
+    sources: &'src mut SourceMap,
+    diagnostics: &'src mut Vec<Diagnostic>,
+    modules: Modules,
+    loaded: HashMap<PathBuf, usize>,
+    /// The files currently being loaded, to find cycles
+    stack: Vec<PathBuf>,
+}
+
+impl<'src> Loader<'src> {
+    fn error(&mut self, code: Code, span: Span, message: String, label: &str) {
+        self.diagnostics.push(
+            Diagnostic::error(code, span)
+                .with_message(message)
+                .with_label(label),
+        );
+    }
+
+    /// Only imports at the top level are loaded, since codegen rejects the rest anyways
+    fn load_imports(&mut self, ast: &Statements, dir: &Path) -> Result<(), ()> {
+        for statement in ast.0.iter() {
+            if let Statement::Import { path, .. } = &statement.data {
+                let index = self.load(path, dir)?;
+                self.modules.imports.insert(path.span, index);
+            }
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, path: &Spanned<StringLiteral>, dir: &Path) -> Result<usize, ()> {
+        let written = &path.data.0;
+        // what reports call it, which stays relative if the script's path was
+        let shown: PathBuf = dir.join(written).components().collect();
+        let file = match shown.canonicalize() {
+            Ok(file) => file,
+            Err(e) => {
+                self.error(
+                    codes::IMPORT_NOT_FOUND,
+                    path.span,
+                    format!("Cannot import {written}: {e}"),
+                    "Imported here",
+                );
+                return Err(());
+            }
+        };
+        if let Some(start) = self.stack.iter().position(|f| *f == file) {
+            let cycle: Vec<_> = self.stack[start..]
+                .iter()
+                .chain([&file])
+                .map(|f| f.file_name().unwrap_or_default().to_string_lossy())
+                .collect();
+            let message = format!("Import cycle: {}", cycle.join(" -> "));
+            self.error(
+                codes::IMPORT_CYCLE,
+                path.span,
+                message,
+                "This is still being imported",
+            );
+            return Err(());
+        }
+        if let Some(&index) = self.loaded.get(&file) {
+            return Ok(index);
+        }
+
+        let text = match fs::read_to_string(&file) {
+            Ok(text) => text,
+            Err(e) => {
+                self.error(
+                    codes::IMPORT_NOT_FOUND,
+                    path.span,
+                    format!("Cannot import {written}: {e}"),
+                    "Imported here",
+                );
+                return Err(());
+            }
+        };
+        let id = self.sources.add(shown.display().to_string(), text);
+        let ast = match parse_file(self.sources, id) {
+            Ok(ast) => ast,
+            Err(diagnostics) => {
+                self.diagnostics.extend(diagnostics);
+                return Err(());
+            }
+        };
+
+        self.stack.push(file.clone());
+        let res = self.load_imports(&ast, shown.parent().unwrap_or(Path::new("")));
+        self.stack.pop();
+        res?;
+
+        let name = if self.modules.modules.iter().any(|m| m.name == *written) {
+            file.display().to_string()
+        } else {
+            written.clone()
+        };
+        let index = self.modules.modules.len();
+        self.modules.modules.push(Module { name, ast });
+        self.loaded.insert(file, index);
+        Ok(index)
+    }
+}
+
+/// Loads everything ast imports, adding each file to sources
+/// Paths are relative to the importing file, or the working directory without a path
+pub fn load(
+    sources: &mut SourceMap,
+    path: Option<&Path>,
+    diagnostics: &mut Vec<Diagnostic>,
+    ast: &Statements,
+) -> Option<Modules> {
+    let mut loader = Loader {
+        sources,
+        diagnostics,
+        modules: Modules::default(),
+        loaded: HashMap::new(),
+        stack: vec![],
+    };
+    if let Some(main) = path.and_then(|path| path.canonicalize().ok()) {
+        loader.stack.push(main);
+    }
+    let dir = path.and_then(Path::parent).unwrap_or(Path::new(""));
+    loader.load_imports(ast, dir).ok()?;
+    Some(loader.modules)
+}
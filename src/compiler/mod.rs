@@ -1,12 +1,54 @@
 use crate::bytecode::chunk::Chunk;
-use std::io::Write;
+use crate::common::diagnostic::Diagnostic;
+use crate::common::ui::{FileId, SourceMap};
+use crate::value::native_function::NativeFn;
+use parse::Statements;
+use std::path::Path;
 
 mod codegen;
+mod format;
+mod import;
 pub mod parse;
+mod resolve;
 
+pub use codegen::NATIVES;
+pub use format::format;
 pub use parse::parse;
+pub use resolve::{Declaration, Resolution};
 
-pub fn compile(source: &str, mut stderr: impl Write) -> Option<Chunk> {
-    let ast = parse::parse(source, &mut stderr)?;
-    codegen::generate(source, stderr, &ast).ok()
+/// Compiles the main file along with everything it imports, which are added to sources
+/// path is where the main file was read from, which imports are relative to
+/// natives are defined by the host on top of the built in ones
+/// Warnings come back alongside the chunk, and fail the compilation if deny_warnings is set
+pub fn compile(
+    sources: &mut SourceMap,
+    main: FileId,
+    path: Option<&Path>,
+    natives: &[(&'static str, NativeFn)],
+    deny_warnings: bool,
+) -> (Option<Chunk>, Vec<Diagnostic>) {
+    let ast = match parse::parse_file(sources, main) {
+        Ok(ast) => ast,
+        Err(diagnostics) => return (None, diagnostics),
+    };
+    let mut diagnostics = vec![];
+    let Some(modules) = import::load(sources, path, &mut diagnostics, &ast) else {
+        return (None, diagnostics);
+    };
+    for module in modules.modules.iter() {
+        diagnostics.extend(resolve::resolve(&module.ast, natives, deny_warnings));
+    }
+    diagnostics.extend(resolve::resolve(&ast, natives, deny_warnings));
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        return (None, diagnostics);
+    }
+    let chunk = codegen::generate(&ast, &modules, natives, &mut diagnostics).ok();
+    (chunk, diagnostics)
+}
+
+/// Parses a single file and works out what its names refer to, without compiling anything
+pub fn analyze(sources: &SourceMap, file: FileId) -> Option<(Statements, Resolution)> {
+    let ast = parse::parse_file(sources, file).ok()?;
+    let resolution = resolve::resolution(&ast);
+    Some((ast, resolution))
 }
@@ -48,6 +48,28 @@ pub enum BinaryKind {
     Divide,
     And,
     Or,
+    /// The rhs, but only if the lhs is nil
+    Coalesce,
+}
+
+/// The operators that can be combined with an assignment, e.g. `+=`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Arbitrary)]
+pub enum CompoundKind {
+    Plus,
+    Minus,
+    Multiply,
+    Divide,
+}
+
+impl From<CompoundKind> for BinaryKind {
+    fn from(value: CompoundKind) -> Self {
+        match value {
+            CompoundKind::Plus => BinaryKind::Plus,
+            CompoundKind::Minus => BinaryKind::Minus,
+            CompoundKind::Multiply => BinaryKind::Multiply,
+            CompoundKind::Divide => BinaryKind::Divide,
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Arbitrary)]
@@ -114,6 +136,12 @@ pub enum Expression {
         id: Spanned<Identifier>,
         rhs: Node<Expression>,
     },
+    /// `id op= rhs`, which is `id = id op rhs` without resolving id twice
+    CompoundAssignment {
+        id: Spanned<Identifier>,
+        kind: Spanned<CompoundKind>,
+        rhs: Node<Expression>,
+    },
     Binary(BinaryExpr),
     Unary {
         kind: Spanned<UnaryKind>,
@@ -121,7 +149,20 @@ pub enum Expression {
     },
     Literal(Spanned<Literal>),
     Identifier(Spanned<Identifier>),
+    /// `cond ? then_branch : else_branch`
+    Ternary {
+        cond: Node<Expression>,
+        then_branch: Node<Expression>,
+        else_branch: Node<Expression>,
+    },
     Call(Call),
+    /// `object.name`, which reads an export of a module or a method of userdata
+    Get {
+        object: Node<Expression>,
+        name: Spanned<Identifier>,
+    },
+    /// An anonymous function, from either `fun (..) {..}` or `(..) => ..`
+    Lambda(Spanned<Function>),
 }
 
 #[derive(Debug, PartialEq, Clone, Arbitrary)]
@@ -144,10 +185,34 @@ impl Debug for FuzzStatements {
     }
 }
 
+/// The parameters and body of a function, named or not
+#[derive(Debug, PartialEq, Clone, Arbitrary)]
+pub struct Function {
+    pub args: Vec<Spanned<Identifier>>,
+    /// Parameters with default values, which always come after args
+    pub defaults: Vec<(Spanned<Identifier>, Spanned<Expression>)>,
+    /// Collects any extra arguments into a list
+    pub rest: Option<Spanned<Identifier>>,
+    pub body: Spanned<Statements>,
+}
+
+impl Function {
+    /// The number of named parameters, not counting rest
+    pub fn parameter_count(&self) -> usize {
+        self.args.len() + self.defaults.len()
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Arbitrary)]
 pub struct FunctionDeclaration {
     pub name: Spanned<Identifier>,
-    pub args: Vec<Spanned<Identifier>>,
+    pub function: Function,
+}
+
+/// `catch (id) { body }`, where id holds whatever was thrown
+#[derive(Debug, PartialEq, Clone, Arbitrary)]
+pub struct Catch {
+    pub id: Spanned<Identifier>,
     pub body: Spanned<Statements>,
 }
 
@@ -159,6 +224,11 @@ pub enum Statement {
         id: Spanned<Identifier>,
         rhs: Option<Spanned<Expression>>,
     },
+    /// Like a var declaration, but it can't be assigned to afterwards
+    ConstDeclaration {
+        id: Spanned<Identifier>,
+        rhs: Spanned<Expression>,
+    },
     FunctionDeclaration(FunctionDeclaration),
     Block(Spanned<Statements>),
     IfElse {
@@ -174,4 +244,19 @@ pub enum Statement {
         span: Span,
         value: Option<Spanned<Expression>>,
     },
+    Throw {
+        span: Span,
+        value: Spanned<Expression>,
+    },
+    /// `import "path" as alias;`, where the path is relative to the importing file
+    Import {
+        path: Spanned<StringLiteral>,
+        alias: Spanned<Identifier>,
+    },
+    /// The parser makes sure there's a catch, a finally or both
+    Try {
+        body: Spanned<Statements>,
+        catch: Option<Catch>,
+        finally: Option<Spanned<Statements>>,
+    },
 }
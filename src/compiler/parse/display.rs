@@ -17,11 +17,18 @@ impl Display for BinaryKind {
             BinaryKind::Divide => "/",
             BinaryKind::And => "and",
             BinaryKind::Or => "or",
+            BinaryKind::Coalesce => "??",
         }
         .fmt(f)
     }
 }
 
+impl Display for CompoundKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}=", BinaryKind::from(*self))
+    }
+}
+
 impl Display for UnaryKind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -47,6 +54,9 @@ impl Display for Expression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Expression::Assignment { id, rhs } => write!(f, "{} = {}", id.data.0, rhs.data),
+            Expression::CompoundAssignment { id, kind, rhs } => {
+                write!(f, "{} {} {}", id.data.0, kind.data, rhs.data)
+            }
             Expression::Binary(BinaryExpr { kind, lhs, rhs }) => {
                 write!(f, "({} {} {})", lhs.data, kind.data, rhs.data)
             }
@@ -54,13 +64,42 @@ impl Display for Expression {
                 write!(f, "{}", callee)?;
                 fmt_list(args.iter(), f)
             }
+            Expression::Get { object, name } => write!(f, "{}.{}", object.data, name.data.0),
+            Expression::Ternary {
+                cond,
+                then_branch,
+                else_branch,
+            } => write!(
+                f,
+                "({} ? {} : {})",
+                cond.data, then_branch.data, else_branch.data
+            ),
             Expression::Unary { kind, val } => write!(f, "({}{})", kind.data, val.data),
             Expression::Literal(lit) => lit.data.fmt(f),
             Expression::Identifier(id) => id.data.0.fmt(f),
+            Expression::Lambda(function) => write!(f, "fun {}", function.data),
         }
     }
 }
 
+impl Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Function {
+            args,
+            defaults,
+            rest,
+            body,
+        } = self;
+        let args = args.iter().map(|arg| arg.data.0.clone());
+        let defaults = defaults
+            .iter()
+            .map(|(arg, default)| format!("{} = {}", arg.data.0, default.data));
+        let rest = rest.iter().map(|rest| format!("...{}", rest.data.0));
+        fmt_list(args.chain(defaults).chain(rest), f)?;
+        write!(f, " {{\n{body}}}")
+    }
+}
+
 impl Display for Statement {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -73,11 +112,11 @@ impl Display for Statement {
                 }
                 ";".fmt(f)?;
             }
-            Statement::FunctionDeclaration(FunctionDeclaration { name, args, body }) => {
-                write!(f, "fun {}", name.data.0)?;
-                fmt_list(args.iter(), f)?;
-                " ".fmt(f)?;
-                write!(f, "{{\n{body}}}")?;
+            Statement::ConstDeclaration { id, rhs } => {
+                write!(f, "const {} = {};", id.data.0, rhs.data)?;
+            }
+            Statement::FunctionDeclaration(FunctionDeclaration { name, function }) => {
+                write!(f, "fun {}{function}", name.data.0)?;
             }
             Statement::Block(body) => write!(f, "{{\n{body}}}")?,
             Statement::IfElse {
@@ -100,6 +139,26 @@ impl Display for Statement {
                 }
                 ";".fmt(f)?;
             }
+            Statement::Throw { span: _, value } => write!(f, "throw {};", value.data)?,
+            Statement::Import { path, alias } => {
+                write!(f, "import \"{}\" as {};", path.data, alias.data.0)?;
+            }
+            Statement::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                write!(f, "try {{\n{body}}}")?;
+                if let Some(Catch { id, body }) = catch {
+                    write!(f, " catch ({}) {{\n{body}}}", id.data.0)?;
+                }
+                match finally {
+                    Some(finally) => write!(f, " finally {{\n{finally}}}")?,
+                    // the parser never makes this, but arbitrary ASTs can
+                    None if catch.is_none() => " finally {}".fmt(f)?,
+                    None => {}
+                }
+            }
         }
         "\n".fmt(f)
     }
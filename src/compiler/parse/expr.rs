@@ -6,6 +6,7 @@ impl Expression {
     pub fn spanned(self) -> Spanned<Self> {
         let span = match &self {
             Expression::Assignment { id, rhs } => id.span.unite(rhs.span),
+            Expression::CompoundAssignment { id, rhs, .. } => id.span.unite(rhs.span),
             Expression::Binary(BinaryExpr { kind, lhs, rhs }) => {
                 Span::unite_many(&[kind.span, lhs.span, rhs.span])
             }
@@ -16,9 +17,14 @@ impl Expression {
                     callee.span
                 }
             }
+            Expression::Get { object, name } => object.span.unite(name.span),
+            Expression::Ternary {
+                cond, else_branch, ..
+            } => cond.span.unite(else_branch.span),
             Expression::Unary { kind, val } => kind.span.unite(val.span),
             Expression::Literal(lit) => lit.span,
             Expression::Identifier(id) => id.span,
+            Expression::Lambda(function) => function.span,
         };
         Spanned { data: self, span }
     }
@@ -1,8 +1,7 @@
-use crate::common::ui::{Span, Spanned};
+use crate::common::ui::{FileId, Span, Spanned};
 use logos::Logos;
 
 #[derive(Logos, Debug, PartialEq, Copy, Clone)]
-#[logos(skip r"//[^\n]*")]
 #[logos(skip r"[ \n\r\f]+")]
 pub enum Token {
     #[token("(")]
@@ -17,16 +16,32 @@ pub enum Token {
     Comma,
     #[token(".")]
     Dot,
+    #[token("...")]
+    Ellipsis,
     #[token("-")]
     Minus,
+    #[token("-=")]
+    MinusEq,
     #[token("+")]
     Plus,
+    #[token("+=")]
+    PlusEq,
     #[token(";")]
     Semicolon,
     #[token("/")]
     Slash,
+    #[token("/=")]
+    SlashEq,
     #[token("*")]
     Star,
+    #[token("*=")]
+    StarEq,
+    #[token("?")]
+    Question,
+    #[token("??")]
+    QuestionQuestion,
+    #[token(":")]
+    Colon,
 
     #[token("!")]
     Bang,
@@ -36,6 +51,8 @@ pub enum Token {
     Eq,
     #[token("==")]
     EqEq,
+    #[token("=>")]
+    Arrow,
     #[token(">")]
     Greater,
     #[token(">=")]
@@ -59,16 +76,26 @@ pub enum Token {
 
     #[token("and")]
     And,
+    #[token("as")]
+    As,
+    #[token("catch")]
+    Catch,
     #[token("class")]
     Class,
+    #[token("const")]
+    Const,
     #[token("else")]
     Else,
+    #[token("finally")]
+    Finally,
     #[token("for")]
     For,
     #[token("fun")]
     Fun,
     #[token("if")]
     If,
+    #[token("import")]
+    Import,
     #[token("nil")]
     Nil,
     #[token("or")]
@@ -81,18 +108,49 @@ pub enum Token {
     Super,
     #[token("this")]
     This,
+    #[token("throw")]
+    Throw,
+    #[token("try")]
+    Try,
     #[token("var")]
     Var,
     #[token("while")]
     While,
+    /// Only produced by Lexer::with_comments, since the parser has no use for them
+    #[regex(r"//[^\n]*")]
+    Comment,
     Eof,
 }
 
-pub struct Lexer<'src>(logos::SpannedIter<'src, Token>);
+/// The keywords the parser understands, which leaves out the reserved class, super and this
+pub const KEYWORDS: &[&str] = &[
+    "and", "as", "catch", "const", "else", "false", "finally", "for", "fun", "if", "import", "nil",
+    "or", "print", "return", "throw", "true", "try", "var", "while",
+];
+
+#[derive(Clone)]
+pub struct Lexer<'src> {
+    lexer: logos::Lexer<'src, Token>,
+    file: FileId,
+    /// Whether comments are kept as tokens, for tools that care about more than the AST
+    comments: bool,
+}
 
 impl<'src> Lexer<'src> {
-    pub fn new(src: &'src str) -> Self {
-        Self(Token::lexer(src).spanned())
+    pub fn new(src: &'src str, file: FileId) -> Self {
+        Self {
+            lexer: Token::lexer(src),
+            file,
+            comments: false,
+        }
+    }
+
+    /// A lexer that also produces Token::Comment, for the formatter to put comments back
+    pub fn with_comments(src: &'src str, file: FileId) -> Self {
+        Self {
+            comments: true,
+            ..Self::new(src, file)
+        }
     }
 }
 
@@ -100,22 +158,28 @@ impl<'src> Iterator for Lexer<'src> {
     type Item = Result<Spanned<Token>, Span>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.next().map(|(tok, span)| {
-            let span = span.into();
-            tok.map(|t| Spanned::new(t, span)) // rustfmt guard
-                .map_err(|_| span)
-        })
+        loop {
+            let tok = self.lexer.next()?;
+            if tok == Ok(Token::Comment) && !self.comments {
+                continue;
+            }
+            let span = Span::from(self.lexer.span()).in_file(self.file);
+            return Some(
+                tok.map(|t| Spanned::new(t, span)) // rustfmt guard
+                    .map_err(|_| span),
+            );
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Lexer, Token};
-    use crate::common::ui::Span;
+    use super::{Lexer, Token, KEYWORDS};
+    use crate::common::ui::{FileId, Span};
     use Token::*;
 
     fn lex(src: &str) -> Result<Vec<Token>, Span> {
-        Lexer::new(src)
+        Lexer::new(src, FileId::default())
             .map(|t| t.map(|t| t.data))
             .collect::<Result<Vec<_>, _>>()
     }
@@ -134,7 +198,15 @@ mod tests {
 
     #[test]
     fn comparisons() {
-        assert_eq!(lex_ok("< <= == ="), &[Less, LessEq, EqEq, Eq]);
+        assert_eq!(lex_ok("< <= == = =>"), &[Less, LessEq, EqEq, Eq, Arrow]);
+    }
+
+    #[test]
+    fn compound_assignments() {
+        assert_eq!(
+            lex_ok("+= -= *= /= + ="),
+            &[PlusEq, MinusEq, StarEq, SlashEq, Plus, Eq]
+        );
     }
 
     #[test]
@@ -142,6 +214,27 @@ mod tests {
         assert_eq!(lex_ok("\"1\n2\n3\n\""), &[String])
     }
 
+    #[test]
+    fn keywords() {
+        for keyword in KEYWORDS {
+            let tokens = lex_ok(keyword);
+            assert!(matches!(tokens[..], [token] if token != Ident), "{keyword}");
+        }
+    }
+
+    #[test]
+    fn comments() {
+        let src = "a // one\n/ b //two";
+        assert_eq!(lex_ok(src), &[Ident, Slash, Ident]);
+        let comments: Vec<_> = Lexer::with_comments(src, FileId::default())
+            .map(|t| t.unwrap())
+            .filter(|t| t.data == Comment)
+            .map(|t| &src[t.span])
+            .collect();
+        assert_eq!(comments, ["// one", "//two"]);
+        assert_eq!(lex_ok("\"// not a comment\""), &[String]);
+    }
+
     #[test]
     fn sequence_of_numbers() {
         assert_eq!(lex_ok("1 1"), &[Num, Num]);
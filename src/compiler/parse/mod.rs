@@ -1,10 +1,12 @@
 pub mod ast;
 mod display;
 mod expr;
-mod lex;
+pub mod lex;
 pub mod parser;
 mod stmt;
 mod token_conversion;
 pub use ast::*;
+pub use lex::KEYWORDS;
 pub use parser::parse;
+pub use parser::parse_file;
 pub use token_conversion::Precedence;
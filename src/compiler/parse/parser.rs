@@ -1,14 +1,10 @@
-use std::io::Write;
+use std::collections::HashMap;
 use std::iter::Peekable;
 
-use crate::compiler::parse::FunctionDeclaration;
+use crate::compiler::parse::{Function, FunctionDeclaration};
 #[cfg(not(feature = "verbose_parsing"))]
 use crate::noop as trace;
 
-use ariadne::Color;
-use ariadne::Label;
-use ariadne::Report;
-use ariadne::ReportKind;
 #[cfg(feature = "verbose_parsing")]
 use tracing::trace;
 
@@ -17,21 +13,32 @@ use super::lex::Token;
 use super::BinaryExpr;
 use super::BinaryKind;
 use super::Call;
+use super::Catch;
+use super::CompoundKind;
 use super::Expression;
 use super::Identifier;
 use super::Literal;
 use super::Precedence;
 use super::Statement;
 use super::Statements;
+use super::StringLiteral;
 use super::UnaryKind;
 
+use crate::common::codes::{self, Code};
+use crate::common::diagnostic::Diagnostic;
 use crate::common::ui;
 use crate::common::ui::*;
 
-struct Parser<'src, StdErr: Write> {
+struct Parser<'src> {
     lexer: Peekable<Lexer<'src>>,
+    /// The text of the file being parsed, which spans index into
     source: &'src str,
-    stderr: StdErr,
+    file: FileId,
+    /// Errors that were reported on the spot, which ParseError::Handled refers to
+    diagnostics: Vec<Diagnostic>,
+    /// Whether the parens that at_arrow_function scanned past are followed by =>, by where the
+    /// ( starts
+    arrows: HashMap<usize, bool>,
 }
 
 #[derive(Debug)]
@@ -42,35 +49,29 @@ pub enum ParseError {
     Handled,
 }
 
-fn simple_parse_error(span: Span, msg: String) -> Report<'static, Span> {
-    Report::build(ReportKind::Error, (), ui::OFFSET)
+fn simple_parse_error(code: Code, span: Span, msg: String) -> Diagnostic {
+    Diagnostic::error(code, span)
         .with_message("Parse error")
-        .with_label(Label::new(span).with_color(Color::Red).with_message(msg))
-        .finish()
+        .with_label(msg)
 }
 
 impl ParseError {
-    pub fn print(&self, stderr: impl Write, source: &str) {
-        use ariadne::Source;
-        let report = match self {
-            Self::InvalidToken(span) => Report::build(ReportKind::Error, (), ui::OFFSET)
+    /// Handled errors have already been turned into diagnostics
+    pub fn diagnostic(&self) -> Option<Diagnostic> {
+        Some(match self {
+            Self::InvalidToken(span) => Diagnostic::error(codes::INVALID_TOKEN, *span)
                 .with_message("Lexing error")
-                .with_label(
-                    Label::new(*span)
-                        .with_color(Color::Red)
-                        .with_message("Invalid token"),
-                )
-                .finish(),
+                .with_label("Invalid token"),
             Self::AssignmentDepth { at } => simple_parse_error(
+                codes::INVALID_ASSIGNMENT,
                 *at,
                 "Invalid assignment at this expression depth".to_string(),
             ),
             Self::ExpectError { expected, got } => {
-                simple_parse_error(*got, format!("Expected {expected}"))
+                simple_parse_error(codes::EXPECTED_TOKEN, *got, format!("Expected {expected}"))
             }
-            Self::Handled => return,
-        };
-        report.write(Source::from(source), stderr).unwrap();
+            Self::Handled => return None,
+        })
     }
 }
 
@@ -87,44 +88,37 @@ fn stack_safe<O>(f: impl FnOnce() -> O) -> O {
 
 pub type ParseResult<T> = Result<T, ParseError>;
 
-impl<'src, StdErr: Write> Parser<'src, StdErr> {
-    fn new(source: &'src str, stderr: StdErr) -> Self {
-        let lexer = Lexer::new(source).peekable();
+impl<'src> Parser<'src> {
+    fn new(source: &'src str, file: FileId) -> Self {
         Self {
-            lexer,
+            lexer: Lexer::new(source, file).peekable(),
             source,
-            stderr,
+            file,
+            diagnostics: vec![],
+            arrows: HashMap::new(),
         }
     }
 
     fn mismatched_pair(
         &mut self,
+        code: Code,
         left: ui::Span,
         left_msg: &str,
         right: ui::Span,
         right_msg: &str,
     ) {
-        use ariadne::{Color, Label, Report, ReportKind, Source};
-        Report::build(ReportKind::Error, (), ui::OFFSET)
-            .with_label(
-                Label::new(left)
-                    .with_color(Color::Red)
-                    .with_message(left_msg),
-            )
-            .with_label(
-                Label::new(right)
-                    .with_color(Color::Red)
-                    .with_message(right_msg),
-            )
-            .finish()
-            .write(Source::from(self.source), &mut self.stderr)
-            .unwrap();
+        self.diagnostics.push(
+            Diagnostic::error(code, left)
+                .with_label(left_msg)
+                .with_secondary(right, right_msg),
+        );
     }
 
     fn check_semicolon(&mut self, lhs: Span) -> ParseResult<()> {
         let next = self.pop()?;
         if next.data != Token::Semicolon {
             self.mismatched_pair(
+                codes::MISSING_SEMICOLON,
                 lhs,
                 "This statement should be terminated with ;",
                 next.span,
@@ -135,18 +129,14 @@ impl<'src, StdErr: Write> Parser<'src, StdErr> {
         Ok(())
     }
 
-    fn simple_error(&mut self, span: ui::Span, msg: &str) {
-        use ariadne::{Color, Label, Report, ReportKind, Source};
-        Report::build(ReportKind::Error, (), ui::OFFSET)
-            .with_label(Label::new(span).with_color(Color::Red).with_message(msg))
-            .finish()
-            .write(Source::from(self.source), &mut self.stderr)
-            .unwrap();
+    fn simple_error(&mut self, code: Code, span: ui::Span, msg: &str) {
+        self.diagnostics
+            .push(Diagnostic::error(code, span).with_label(msg));
     }
 
     fn eof(&self) -> Spanned<Token> {
         let len = self.source.len();
-        let span = ui::Span::from(len.saturating_sub(1)..len);
+        let span = ui::Span::from(len.saturating_sub(1)..len).in_file(self.file);
         Spanned {
             data: Token::Eof,
             span,
@@ -172,6 +162,66 @@ impl<'src, StdErr: Write> Parser<'src, StdErr> {
         }
     }
 
+    /// Looks at the token after the next one, without consuming anything
+    fn peek_second(&self) -> Option<Token> {
+        let mut lexer = self.lexer.clone();
+        lexer.next();
+        match lexer.next() {
+            Some(Ok(t)) => Some(t.data),
+            _ => None,
+        }
+    }
+
+    /// Whether the ( coming up starts the parameter list of an arrow function.
+    /// Finding the end of a parameter list also settles every ( inside it, which is remembered
+    /// so that deeply nested parens are only scanned once
+    fn at_arrow_function(&mut self) -> bool {
+        let mut lexer = self.lexer.clone();
+        let start = match lexer.peek() {
+            Some(Ok(token)) if token.data == Token::LParen => token.span.range().start,
+            _ => return false,
+        };
+        let mut tokens = lexer.map(|t| t.map(|t| t.data));
+        tokens.next();
+        match tokens.next() {
+            Some(Ok(Token::RParen)) => return tokens.next() == Some(Ok(Token::Arrow)),
+            Some(Ok(Token::Ellipsis)) => {}
+            Some(Ok(Token::Ident)) => match tokens.next() {
+                Some(Ok(Token::RParen)) => return tokens.next() == Some(Ok(Token::Arrow)),
+                Some(Ok(Token::Comma | Token::Eq)) => {}
+                _ => return false,
+            },
+            _ => return false,
+        }
+        if let Some(&arrow) = self.arrows.get(&start) {
+            return arrow;
+        }
+        // we're somewhere in what looks like a parameter list, so find its end
+        let mut tokens = self.lexer.clone();
+        let mut open = vec![];
+        while let Some(Ok(token)) = tokens.next() {
+            match token.data {
+                Token::LParen => open.push(token.span.range().start),
+                Token::RParen => {
+                    let Some(start) = open.pop() else {
+                        unreachable!("The scan stops once the first ( is closed");
+                    };
+                    let arrow = matches!(tokens.peek(), Some(Ok(t)) if t.data == Token::Arrow);
+                    self.arrows.insert(start, arrow);
+                    if open.is_empty() {
+                        return arrow;
+                    }
+                }
+                _ => {}
+            }
+        }
+        // whatever's still open is never closed before the end of the file or an invalid token
+        for start in open {
+            self.arrows.insert(start, false);
+        }
+        false
+    }
+
     fn matches(&mut self, maybe: Token) -> Option<Spanned<Token>> {
         let Ok(token) = self.peek() else {
             return None;
@@ -215,6 +265,18 @@ impl<'src, StdErr: Write> Parser<'src, StdErr> {
                 rhs: rhs.boxed(),
             }
             .spanned())
+        } else if let Ok(kind) = CompoundKind::try_from(self.peek()?.data) {
+            let operator = self.pop().unwrap();
+            if !can_assign {
+                return Err(ParseError::AssignmentDepth { at: operator.span });
+            }
+            let rhs = self.expression(can_assign)?;
+            Ok(Expression::CompoundAssignment {
+                id,
+                kind: kind.with_span(operator.span),
+                rhs: rhs.boxed(),
+            }
+            .spanned())
         } else {
             Ok(Expression::Identifier(id).spanned())
         }
@@ -223,6 +285,9 @@ impl<'src, StdErr: Write> Parser<'src, StdErr> {
     /// ensures: Ok(_) ==> Exactly one additional value on the stack
     #[cfg_attr(feature = "instrument", tracing::instrument(skip(self, chunk)))]
     fn primary(&mut self, can_assign: bool) -> ParseResult<Spanned<Expression>> {
+        if self.at_arrow_function() {
+            return self.arrow_function();
+        }
         let token = self.pop()?;
         match token.data {
             Token::Minus => Ok(Expression::Unary {
@@ -240,6 +305,7 @@ impl<'src, StdErr: Write> Parser<'src, StdErr> {
                 let next = self.pop()?;
                 if next.data != Token::RParen {
                     self.mismatched_pair(
+                        codes::UNMATCHED_DELIMITER,
                         token.span,
                         "This ( is unmatched",
                         next.span,
@@ -263,6 +329,11 @@ impl<'src, StdErr: Write> Parser<'src, StdErr> {
                 Ok(Expression::literal(token.span, str.to_owned()).spanned())
             }
             Token::Ident => self.variable_access_or_assignment(token.span, can_assign),
+            Token::Fun => {
+                let function = self.function()?;
+                let span = token.span.unite(function.body.span);
+                Ok(Expression::Lambda(function.with_span(span)).spanned())
+            }
             _ => Err(ParseError::ExpectError {
                 expected: "primary",
                 got: token.span,
@@ -339,6 +410,7 @@ impl<'src, StdErr: Write> Parser<'src, StdErr> {
         let rparen = self.pop()?;
         if rparen.data != Token::RParen {
             self.mismatched_pair(
+                codes::UNMATCHED_DELIMITER,
                 lparen_span,
                 "This ( must be terminated",
                 rparen.span,
@@ -349,6 +421,7 @@ impl<'src, StdErr: Write> Parser<'src, StdErr> {
 
         if args.len() > u8::MAX as usize {
             self.simple_error(
+                codes::TOO_MANY_ARGUMENTS,
                 lparen_span.unite(rparen.span),
                 "Cannot have more than 255 arguments",
             );
@@ -368,17 +441,43 @@ impl<'src, StdErr: Write> Parser<'src, StdErr> {
         loop {
             let operation = self.peek()?;
 
-            match operation.data {
-                Token::LParen => {
-                    let args = self.argument_list()?;
-                    lhs = Expression::Call(Call {
-                        callee: lhs.boxed(),
-                        args,
-                    })
-                    .spanned();
-                    continue;
+            if operation.data == Token::LParen {
+                let args = self.argument_list()?;
+                lhs = Expression::Call(Call {
+                    callee: lhs.boxed(),
+                    args,
+                })
+                .spanned();
+                continue;
+            }
+
+            if operation.data == Token::Dot {
+                self.pop().unwrap();
+                let name = self.parameter()?;
+                lhs = Expression::Get {
+                    object: lhs.boxed(),
+                    name,
                 }
-                _ => {}
+                .spanned();
+                continue;
+            }
+
+            if operation.data == Token::Question {
+                if Precedence::Ternary <= min {
+                    break;
+                }
+                self.pop().unwrap();
+                let then_branch = self.expression(false)?;
+                self.expect(Token::Colon, ":")?;
+                // right associative, so a ? b : c ? d : e nests in the else branch
+                let else_branch = stack_safe(|| self.expression_bp(Precedence::Assignment, false))?;
+                lhs = Expression::Ternary {
+                    cond: lhs.boxed(),
+                    then_branch: then_branch.boxed(),
+                    else_branch: else_branch.boxed(),
+                }
+                .spanned();
+                continue;
             }
 
             let Ok(kind) = BinaryKind::try_from(operation.data) else {
@@ -426,9 +525,14 @@ impl<'src, StdErr: Write> Parser<'src, StdErr> {
 
         let rbrace = self.pop()?;
         if rbrace.data == Token::RBrace {
-            Ok(Statements(body).spanned())
+            // the braces are included, so that the span is where the block's scope is
+            Ok(Spanned::new(
+                Statements(body),
+                lbrace.span.unite(rbrace.span),
+            ))
         } else {
             self.mismatched_pair(
+                codes::UNMATCHED_DELIMITER,
                 lbrace.span,
                 "This { must be terminated",
                 rbrace.span,
@@ -475,6 +579,35 @@ impl<'src, StdErr: Write> Parser<'src, StdErr> {
         Ok(Statement::VarDeclaration { id, rhs }.spanned())
     }
 
+    fn const_declaration(&mut self) -> ParseResult<Spanned<Statement>> {
+        let const_token = self.pop().unwrap();
+        debug_assert_eq!(const_token.data, Token::Const);
+
+        let namespan = self.expect(Token::Ident, "identifier")?;
+        let id = Spanned {
+            data: Identifier::from(self.source[namespan].to_owned()),
+            span: namespan,
+        };
+
+        if self.peek()?.data != Token::Eq {
+            let next = self.peek()?;
+            self.mismatched_pair(
+                codes::CONST_WITHOUT_INITIALIZER,
+                namespan,
+                "This constant must be initialized",
+                next.span,
+                "Expected =",
+            );
+            return Err(ParseError::Handled);
+        }
+        self.pop().unwrap();
+        let rhs = self.expression(true)?;
+
+        self.check_semicolon(const_token.span)?;
+
+        Ok(Statement::ConstDeclaration { id, rhs }.spanned())
+    }
+
     fn if_statement(&mut self) -> ParseResult<Spanned<Statement>> {
         let if_token = self.pop().unwrap();
         debug_assert_eq!(if_token.data, Token::If);
@@ -497,26 +630,137 @@ impl<'src, StdErr: Write> Parser<'src, StdErr> {
         .spanned())
     }
 
-    fn parameter_list(&mut self) -> ParseResult<Vec<Spanned<Identifier>>> {
-        let lparen_span = self.expect(Token::LParen, "(")?;
+    fn import_statement(&mut self) -> ParseResult<Spanned<Statement>> {
+        let import_token = self.pop().unwrap();
+        debug_assert_eq!(import_token.data, Token::Import);
+        let path = self.expect(Token::String, "a path")?;
+        self.expect(Token::As, "as")?;
+        let alias = self.parameter()?;
+        self.check_semicolon(import_token.span)?;
+        // remove quotes
+        let path_str = &self.source[path];
+        let path_str = StringLiteral(path_str[1..path_str.len() - 1].to_owned());
+        Ok(Statement::Import {
+            path: path_str.with_span(path),
+            alias,
+        }
+        .spanned())
+    }
 
-        let mut args = vec![];
-        let ident = self.peek()?;
-        if ident.data == Token::Ident {
-            self.pop().unwrap();
-            args.push(
-                Identifier::from(String::from(&self.source[ident.span])).with_span(ident.span),
+    fn throw_statement(&mut self) -> ParseResult<Spanned<Statement>> {
+        let throw_token = self.pop().unwrap();
+        debug_assert_eq!(throw_token.data, Token::Throw);
+        let value = self.expression(false)?;
+        self.check_semicolon(throw_token.span)?;
+        Ok(Statement::Throw {
+            span: throw_token.span,
+            value,
+        }
+        .spanned())
+    }
+
+    fn try_statement(&mut self) -> ParseResult<Spanned<Statement>> {
+        let try_token = self.pop().unwrap();
+        debug_assert_eq!(try_token.data, Token::Try);
+        let body = self.block()?;
+
+        let catch = if self.matches(Token::Catch).is_some() {
+            let lparen_span = self.expect(Token::LParen, "(")?;
+            let id = self.parameter()?;
+            let rparen = self.pop()?;
+            if rparen.data != Token::RParen {
+                self.mismatched_pair(
+                    codes::UNMATCHED_DELIMITER,
+                    lparen_span,
+                    "This ( must be terminated",
+                    rparen.span,
+                    "Expected )",
+                );
+                return Err(ParseError::Handled);
+            }
+            Some(Catch {
+                id,
+                body: self.block()?,
+            })
+        } else {
+            None
+        };
+
+        let finally = if self.matches(Token::Finally).is_some() {
+            Some(self.block()?)
+        } else {
+            None
+        };
+
+        if catch.is_none() && finally.is_none() {
+            let next = self.peek()?;
+            self.mismatched_pair(
+                codes::TRY_WITHOUT_HANDLER,
+                try_token.span,
+                "This try needs a catch or a finally",
+                next.span,
+                "Expected catch or finally",
             );
-            while self.peek()?.data == Token::Comma {
+            return Err(ParseError::Handled);
+        }
+
+        Ok(Statement::Try {
+            body,
+            catch,
+            finally,
+        }
+        .spanned())
+    }
+
+    fn parameter(&mut self) -> ParseResult<Spanned<Identifier>> {
+        let ident = self.expect(Token::Ident, "identifier")?;
+        Ok(Identifier::from(String::from(&self.source[ident])).with_span(ident))
+    }
+
+    /// Parses a parenthesized parameter list into a function without a body
+    fn parameter_list(&mut self) -> ParseResult<Function> {
+        let lparen_span = self.expect(Token::LParen, "(")?;
+
+        let mut declaration = Function {
+            args: vec![],
+            defaults: vec![],
+            rest: None,
+            body: Statements(vec![]).spanned(),
+        };
+        if self.peek()?.data != Token::RParen {
+            loop {
+                if self.peek()?.data == Token::Ellipsis {
+                    self.pop().unwrap();
+                    declaration.rest = Some(self.parameter()?);
+                    // nothing can come after the rest parameter
+                    break;
+                }
+                let arg = self.parameter()?;
+                if self.peek()?.data == Token::Eq {
+                    self.pop().unwrap();
+                    let default = self.expression(false)?;
+                    declaration.defaults.push((arg, default));
+                } else if !declaration.defaults.is_empty() {
+                    self.simple_error(
+                        codes::REQUIRED_AFTER_DEFAULT,
+                        arg.span,
+                        "Parameters without defaults can't come after ones with defaults",
+                    );
+                    return Err(ParseError::Handled);
+                } else {
+                    declaration.args.push(arg);
+                }
+                if self.peek()?.data != Token::Comma {
+                    break;
+                }
                 self.pop().unwrap();
-                let ident = self.expect(Token::Ident, "identifier")?;
-                args.push(Identifier::from(String::from(&self.source[ident])).with_span(ident));
             }
         }
 
         let rparen = self.pop()?;
         if rparen.data != Token::RParen {
             self.mismatched_pair(
+                codes::UNMATCHED_DELIMITER,
                 lparen_span,
                 "This ( must be terminated",
                 rparen.span,
@@ -525,14 +769,38 @@ impl<'src, StdErr: Write> Parser<'src, StdErr> {
             return Err(ParseError::Handled);
         }
 
-        if args.len() > u8::MAX as usize {
+        if declaration.parameter_count() > u8::MAX as usize {
             self.simple_error(
+                codes::TOO_MANY_PARAMETERS,
                 lparen_span.unite(rparen.span),
                 "Cannot have more than 255 parameters",
             );
             return Err(ParseError::Handled);
         }
-        Ok(args)
+        Ok(declaration)
+    }
+
+    /// Parses the parameter list and body of a function
+    fn function(&mut self) -> ParseResult<Function> {
+        let mut function = self.parameter_list()?;
+        function.body = self.block()?;
+        Ok(function)
+    }
+
+    /// `(params) => value` is short for `fun (params) { return value; }`
+    fn arrow_function(&mut self) -> ParseResult<Spanned<Expression>> {
+        let lparen_span = self.peek()?.span;
+        let mut function = self.parameter_list()?;
+        let arrow = self.expect(Token::Arrow, "=>")?;
+        let value = self.expression(true)?;
+        let span = lparen_span.unite(value.span);
+        function.body = Statements(vec![Statement::Return {
+            span: arrow,
+            value: Some(value),
+        }
+        .spanned()])
+        .spanned();
+        Ok(Expression::Lambda(function.with_span(span)).spanned())
     }
 
     fn function_declaration(&mut self) -> ParseResult<Spanned<Statement>> {
@@ -540,25 +808,28 @@ impl<'src, StdErr: Write> Parser<'src, StdErr> {
         debug_assert_eq!(fun_token.data, Token::Fun);
 
         let name = self.expect(Token::Ident, "identifier")?;
-        let args = self.parameter_list()?;
-        let body = self.block()?;
-
-        Ok(Statement::FunctionDeclaration(FunctionDeclaration {
+        let declaration = FunctionDeclaration {
             name: Identifier::from(String::from(&self.source[name])).with_span(name),
-            args,
-            body,
-        })
-        .spanned())
+            function: self.function()?,
+        };
+
+        Ok(Statement::FunctionDeclaration(declaration).spanned())
     }
 
     fn _declaration(&mut self) -> ParseResult<Spanned<Statement>> {
         let token = self.peek()?;
         match token.data {
             Token::If => self.if_statement(),
+            // a lambda at the start of a statement, rather than a declaration
+            Token::Fun if self.peek_second() == Some(Token::LParen) => self.expression_statement(),
             Token::Fun => self.function_declaration(),
             Token::Var => self.var_declaration(),
+            Token::Const => self.const_declaration(),
             Token::While => self.while_loop(),
             Token::For => self.for_loop(),
+            Token::Throw => self.throw_statement(),
+            Token::Import => self.import_statement(),
+            Token::Try => self.try_statement(),
             Token::Return => {
                 self.pop().unwrap();
                 let value = if self.matches(Token::Semicolon).is_some() {
@@ -587,7 +858,7 @@ impl<'src, StdErr: Write> Parser<'src, StdErr> {
         stack_safe(|| self._declaration())
     }
 
-    fn top(mut self) -> ParseResult<Statements> {
+    fn top(&mut self) -> ParseResult<Statements> {
         let mut res = vec![];
         while self.peek()?.data != Token::Eof {
             res.push(self.declaration()?);
@@ -596,19 +867,25 @@ impl<'src, StdErr: Write> Parser<'src, StdErr> {
     }
 }
 
-pub fn parse_res(source: &str, stderr: impl Write) -> ParseResult<Statements> {
-    let parser = Parser::new(source, stderr);
-    parser.top()
+pub fn parse_res(source: &str) -> ParseResult<Statements> {
+    Parser::new(source, FileId::default()).top()
 }
 
-pub fn parse(source: &str, mut stderr: impl Write) -> Option<Statements> {
-    match parse_res(source, &mut stderr) {
-        Ok(ast) => Some(ast),
-        Err(e) => {
-            e.print(stderr, source);
-            None
-        }
-    }
+/// Parses a script on its own, with spans that are relative to source
+pub fn parse(source: &str) -> Result<Statements, Vec<Diagnostic>> {
+    let mut sources = SourceMap::default();
+    let file = sources.add(SCRIPT_NAME, source);
+    parse_file(&sources, file)
+}
+
+/// Parses one of the files in sources, so that its spans point into that file
+pub fn parse_file(sources: &SourceMap, file: FileId) -> Result<Statements, Vec<Diagnostic>> {
+    let mut parser = Parser::new(sources.text(file), file);
+    parser.top().map_err(|e| {
+        let mut diagnostics = parser.diagnostics;
+        diagnostics.extend(e.diagnostic());
+        diagnostics
+    })
 }
 
 #[cfg(test)]
@@ -629,6 +906,27 @@ mod tests {
     snap_parse!(parens, "print 2 * (6 + 1) / (2) -- 100;");
     snap_parse!(nested_parens, "print ((1) / (1 + (1 / 0.5)) * 3);");
     snap_parse!(unary, "print -1 - -2 == --1 == true;");
+    snap_parse! {
+        lambdas,
+        "
+        fun (a) {
+            print a;
+        }(1);
+        var f = (a, b = 1, ...rest) => a + b;
+        print ((a) => a)(fun () {});
+        "
+    }
+    // the outer parens are scanned first, which settles the ones inside them
+    snap_parse!(
+        arrows_in_parens,
+        "print (a = ((b) => b)((c, d = (e)) => (c)) + (f = 1));"
+    );
+    snap_parse!(const_declaration, "const x = 1 + 2;");
+    snap_parse!(compound_assignment, "a += b -= 1 * 2; a /= -1;");
+    snap_parse!(
+        ternary_precedence,
+        "print a ?? b ? c or d : e ? f : g ?? h;"
+    );
     snap_parse! {
         call_nil,
         "
@@ -650,6 +948,8 @@ mod errors {
     snap_parse!(missing_lhs, "print + 1;\n");
     snap_parse!(invalid_token, "print $;");
     snap_parse!(missing_semicolon, "print 1; x");
+    snap_parse!(arrow_without_body, "var f = (a) => ;");
+    snap_parse!(nested_compound_assignment, "print 1 + a += 1;");
 
     snap_parse! {
         global_declaration_without_identifier,
@@ -711,7 +1011,7 @@ mod errors {
         if (true) print 1;
         "
     }
-    
+
     // miri can't use stacker
     #[cfg(not(miri))]
     snap_parse! {
@@ -1,6 +1,6 @@
 use crate::common::ui::{Span, Spanned};
 
-use super::{FunctionDeclaration, Statement, Statements};
+use super::{Catch, FunctionDeclaration, Statement, Statements};
 
 impl Statement {
     pub fn spanned(self) -> Spanned<Self> {
@@ -14,11 +14,10 @@ impl Statement {
                 }
                 span
             }
-            Statement::FunctionDeclaration(FunctionDeclaration {
-                name,
-                args: _,
-                body,
-            }) => name.span.unite(body.span),
+            Statement::ConstDeclaration { id, rhs } => id.span.unite(rhs.span),
+            Statement::FunctionDeclaration(FunctionDeclaration { name, function }) => {
+                name.span.unite(function.body.span)
+            }
             Statement::Block(block) => block.span,
             Statement::IfElse {
                 cond,
@@ -39,6 +38,22 @@ impl Statement {
                 }
                 span
             }
+            Statement::Throw { span, value } => span.unite(value.span),
+            Statement::Import { path, alias } => path.span.unite(alias.span),
+            Statement::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                let mut span = body.span;
+                if let Some(Catch { id, body }) = catch {
+                    span = span.unite(id.span).unite(body.span);
+                }
+                if let Some(finally) = finally {
+                    span = span.unite(finally.span);
+                }
+                span
+            }
         };
         Spanned { data: self, span }
     }
@@ -1,6 +1,7 @@
 use super::lex::Token;
 
 use super::BinaryKind;
+use super::CompoundKind;
 
 impl TryFrom<Token> for BinaryKind {
     type Error = ();
@@ -19,6 +20,21 @@ impl TryFrom<Token> for BinaryKind {
             Token::LessEq => Ok(BinaryKind::LessThanEqual),
             Token::And => Ok(BinaryKind::And),
             Token::Or => Ok(BinaryKind::Or),
+            Token::QuestionQuestion => Ok(BinaryKind::Coalesce),
+            _ => Err(()),
+        }
+    }
+}
+
+impl TryFrom<Token> for CompoundKind {
+    type Error = ();
+
+    fn try_from(value: Token) -> Result<Self, Self::Error> {
+        match value {
+            Token::PlusEq => Ok(CompoundKind::Plus),
+            Token::MinusEq => Ok(CompoundKind::Minus),
+            Token::StarEq => Ok(CompoundKind::Multiply),
+            Token::SlashEq => Ok(CompoundKind::Divide),
             _ => Err(()),
         }
     }
@@ -29,6 +45,8 @@ pub enum Precedence {
     None,
     Start,
     Assignment,
+    Ternary,
+    Coalesce,
     Or,
     And,
     Equality,
@@ -55,6 +73,7 @@ impl From<BinaryKind> for Precedence {
             BinaryKind::LessThanEqual => Self::Comparison,
             BinaryKind::And => Self::And,
             BinaryKind::Or => Self::Or,
+            BinaryKind::Coalesce => Self::Coalesce,
         }
     }
 }
@@ -0,0 +1,392 @@
+//! Looks for likely mistakes that are still valid programs, and warns about them before codegen
+
+use std::collections::HashMap;
+
+use crate::common::codes::{self, Code};
+use crate::common::diagnostic::Diagnostic;
+use crate::common::suggest::undefined_help;
+use crate::common::ui::*;
+use crate::value::native_function::NativeFn;
+
+use super::codegen::NATIVES;
+use super::parse::BinaryExpr;
+use super::parse::Call;
+use super::parse::Catch;
+use super::parse::Expression;
+use super::parse::Function;
+use super::parse::FunctionDeclaration;
+use super::parse::Identifier;
+use super::parse::Statement;
+use super::parse::Statements;
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum LocalKind {
+    Variable,
+    Parameter,
+    /// The slot a function uses to call itself, which doesn't need to be used
+    Recursion,
+}
+
+struct Local<'ast> {
+    name: &'ast str,
+    span: Span,
+    kind: LocalKind,
+    used: bool,
+}
+
+/// A name that was declared, for tools that need to know what's visible where
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Declaration {
+    pub name: String,
+    pub span: Span,
+    /// Where the name can be used, or None for a global, which is visible everywhere
+    pub scope: Option<Span>,
+}
+
+/// What every name in a file refers to
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Resolution {
+    /// In the order they were declared, with globals first
+    pub declarations: Vec<Declaration>,
+    /// The span of each name that refers to a declaration, along with the declaration's span
+    pub references: Vec<(Span, Span)>,
+}
+
+struct Resolver<'ast> {
+    /// Whether to report everything as errors instead
+    deny: bool,
+    warnings: Vec<Diagnostic>,
+    /// Every global defined at the top level, plus the natives, which weren't declared anywhere
+    globals: HashMap<&'ast str, Option<Span>>,
+    /// The locals of each scope, along with where that scope is
+    scopes: Vec<(Span, Vec<Local<'ast>>)>,
+    resolution: Resolution,
+}
+
+impl<'ast> Resolver<'ast> {
+    fn warn(&mut self, code: Code, message: &str, labels: &[(Span, String)]) {
+        let warning = self.warning(code, message, labels);
+        self.warnings.push(warning);
+    }
+
+    fn warning(&self, code: Code, message: &str, labels: &[(Span, String)]) -> Diagnostic {
+        let ((span, label), rest) = labels.split_first().unwrap();
+        let mut warning = if self.deny {
+            Diagnostic::error(code, *span)
+        } else {
+            Diagnostic::warning(code, *span)
+        };
+        warning = warning.with_message(message).with_label(label);
+        for (span, label) in rest {
+            warning = warning.with_secondary(*span, label);
+        }
+        warning
+    }
+
+    fn begin_scope(&mut self, span: Span) {
+        self.scopes.push((span, vec![]));
+    }
+
+    fn end_scope(&mut self) {
+        let (_, scope) = self.scopes.pop().unwrap();
+        for local in scope {
+            // an underscore marks something as intentionally unused
+            if local.used || local.name.starts_with('_') {
+                continue;
+            }
+            let message = match local.kind {
+                LocalKind::Variable => "Unused variable",
+                LocalKind::Parameter => "Unused parameter",
+                LocalKind::Recursion => continue,
+            };
+            let label = format!("{} is never read", local.name);
+            self.warn(codes::UNUSED_VARIABLE, message, &[(local.span, label)]);
+        }
+    }
+
+    /// Globals aren't tracked, since they're collected up front
+    fn declare(&mut self, id: &'ast Spanned<Identifier>, kind: LocalKind) {
+        let Some(&(scope_span, ref scope)) = self.scopes.last() else {
+            return;
+        };
+        let name = id.data.0.as_str();
+        let previous = scope
+            .iter()
+            .rev()
+            .find(|local| local.name == name && local.kind != LocalKind::Recursion);
+        if let Some(previous) = previous {
+            let labels = [
+                (id.span, format!("This shadows {name}")),
+                (previous.span, format!("{name} was already declared here")),
+            ];
+            self.warn(
+                codes::SHADOWED_VARIABLE,
+                "Shadowed variable in the same scope",
+                &labels,
+            );
+        }
+        self.resolution.declarations.push(Declaration {
+            name: name.to_owned(),
+            span: id.span,
+            scope: Some(scope_span),
+        });
+        self.scopes.last_mut().unwrap().1.push(Local {
+            name,
+            span: id.span,
+            kind,
+            used: false,
+        });
+    }
+
+    fn resolve_local(&mut self, name: &str) -> Option<&mut Local<'ast>> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .flat_map(|(_, scope)| scope.iter_mut().rev())
+            .find(|local| local.name == name)
+    }
+
+    /// Warns if id doesn't refer to anything
+    fn resolve(&mut self, id: &Spanned<Identifier>) -> Option<&mut Local<'ast>> {
+        let name = id.data.0.as_str();
+        let declaration = match self.resolve_local(name) {
+            Some(local) => Some(local.span),
+            None => self.globals.get(name).copied().flatten(),
+        };
+        if let Some(declaration) = declaration {
+            self.resolution.references.push((id.span, declaration));
+        }
+        if self.resolve_local(name).is_none() && !self.globals.contains_key(name) {
+            let label = format!("{name} is never defined");
+            let mut warning = self.warning(
+                codes::UNDEFINED_VARIABLE,
+                "Undefined variable",
+                &[(id.span, label)],
+            );
+            let locals = self.scopes.iter().flat_map(|(_, scope)| scope.iter());
+            let locals = locals.map(|local| local.name);
+            if let Some(help) = undefined_help(name, locals.chain(self.globals.keys().copied())) {
+                warning = warning.with_help(help);
+            }
+            self.warnings.push(warning);
+            return None;
+        }
+        self.resolve_local(name)
+    }
+
+    fn function(&mut self, name: Option<&'ast Spanned<Identifier>>, function: &'ast Function) {
+        let Function {
+            args,
+            defaults,
+            rest,
+            body,
+        } = function;
+        self.begin_scope(body.span);
+        if let Some(name) = name {
+            self.declare(name, LocalKind::Recursion);
+        }
+        for arg in args {
+            self.declare(arg, LocalKind::Parameter);
+        }
+        // each default can see the parameters before it
+        for (arg, default) in defaults {
+            self.expression(&default.data);
+            self.declare(arg, LocalKind::Parameter);
+        }
+        if let Some(rest) = rest {
+            self.declare(rest, LocalKind::Parameter);
+        }
+        self.statements(&body.data);
+        self.end_scope();
+    }
+
+    fn expression(&mut self, expression: &'ast Expression) {
+        match expression {
+            Expression::Assignment { id, rhs } => {
+                self.expression(&rhs.data);
+                // assigning isn't a use, but it still has to refer to something
+                self.resolve(id);
+            }
+            Expression::CompoundAssignment { id, rhs, .. } => {
+                self.expression(&rhs.data);
+                // unlike plain assignment, this reads the old value
+                if let Some(local) = self.resolve(id) {
+                    local.used = true;
+                }
+            }
+            Expression::Binary(BinaryExpr { lhs, rhs, .. }) => {
+                self.expression(&lhs.data);
+                self.expression(&rhs.data);
+            }
+            Expression::Ternary {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(&cond.data);
+                self.expression(&then_branch.data);
+                self.expression(&else_branch.data);
+            }
+            Expression::Unary { val, .. } => self.expression(&val.data),
+            Expression::Literal(_) => {}
+            Expression::Identifier(id) => {
+                if let Some(local) = self.resolve(id) {
+                    local.used = true;
+                }
+            }
+            Expression::Call(Call { callee, args }) => {
+                self.expression(&callee.data);
+                for arg in args {
+                    self.expression(&arg.data);
+                }
+            }
+            Expression::Get { object, .. } => self.expression(&object.data),
+            Expression::Lambda(function) => self.function(None, &function.data),
+        }
+    }
+
+    fn scoped_statements(&mut self, statements: &'ast Spanned<Statements>) {
+        self.begin_scope(statements.span);
+        self.statements(&statements.data);
+        self.end_scope();
+    }
+
+    fn statements(&mut self, statements: &'ast Statements) {
+        let mut statements = statements.0.iter();
+        let mut exit = None;
+        for statement in statements.by_ref() {
+            self.statement(&statement.data);
+            exit = match statement.data {
+                Statement::Return { .. } => Some("return"),
+                Statement::Throw { .. } => Some("throw"),
+                _ => continue,
+            };
+            break;
+        }
+        let unreachable: Vec<_> = statements.collect();
+        if let (Some(first), Some(last), Some(exit)) =
+            (unreachable.first(), unreachable.last(), exit)
+        {
+            let span = first.span.unite(last.span);
+            self.warn(
+                codes::UNREACHABLE_CODE,
+                "Unreachable code",
+                &[(span, format!("This comes after a {exit}"))],
+            );
+            for statement in unreachable {
+                self.statement(&statement.data);
+            }
+        }
+    }
+
+    fn statement(&mut self, statement: &'ast Statement) {
+        match statement {
+            Statement::Expr(expr) | Statement::Print(expr) => self.expression(&expr.data),
+            Statement::VarDeclaration { id, rhs } => {
+                if let Some(rhs) = rhs {
+                    self.expression(&rhs.data);
+                }
+                self.declare(id, LocalKind::Variable);
+            }
+            Statement::ConstDeclaration { id, rhs } => {
+                self.expression(&rhs.data);
+                self.declare(id, LocalKind::Variable);
+            }
+            Statement::FunctionDeclaration(FunctionDeclaration { name, function }) => {
+                // like codegen, the name is only declared after the body
+                self.function(Some(name), function);
+                self.declare(name, LocalKind::Variable);
+            }
+            Statement::Block(block) => self.scoped_statements(block),
+            Statement::IfElse {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.expression(&cond.data);
+                self.scoped_statements(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.scoped_statements(else_branch);
+                }
+            }
+            Statement::While { cond, body } => {
+                self.expression(&cond.data);
+                self.scoped_statements(body);
+            }
+            Statement::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expression(&value.data);
+                }
+            }
+            Statement::Throw { value, .. } => self.expression(&value.data),
+            Statement::Import { alias, .. } => self.declare(alias, LocalKind::Variable),
+            Statement::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.scoped_statements(body);
+                if let Some(Catch { id, body }) = catch {
+                    self.begin_scope(body.span);
+                    self.declare(id, LocalKind::Variable);
+                    self.statements(&body.data);
+                    self.end_scope();
+                }
+                if let Some(finally) = finally {
+                    self.scoped_statements(finally);
+                }
+            }
+        }
+    }
+}
+
+fn run<'a>(ast: &'a Statements, natives: &[(&'static str, NativeFn)], deny: bool) -> Resolver<'a> {
+    let mut globals: HashMap<&str, Option<Span>> = NATIVES
+        .iter()
+        .chain(natives)
+        .map(|(name, _)| (*name, None))
+        .collect();
+    let mut declarations = vec![];
+    for statement in ast.0.iter() {
+        let id = match &statement.data {
+            Statement::VarDeclaration { id, .. }
+            | Statement::ConstDeclaration { id, .. }
+            | Statement::Import { alias: id, .. }
+            | Statement::FunctionDeclaration(FunctionDeclaration { name: id, .. }) => id,
+            _ => continue,
+        };
+        // redeclaring a global just assigns to it, so uses refer to the first declaration
+        globals.entry(&id.data.0).or_insert(Some(id.span));
+        declarations.push(Declaration {
+            name: id.data.0.clone(),
+            span: id.span,
+            scope: None,
+        });
+    }
+    let mut resolver = Resolver {
+        deny,
+        warnings: vec![],
+        globals,
+        scopes: vec![],
+        resolution: Resolution {
+            declarations,
+            references: vec![],
+        },
+    };
+    resolver.statements(ast);
+    resolver
+}
+
+/// Returns the warnings, which are errors instead if deny is set
+pub fn resolve(
+    ast: &Statements,
+    natives: &[(&'static str, NativeFn)],
+    deny: bool,
+) -> Vec<Diagnostic> {
+    run(ast, natives, deny).warnings
+}
+
+/// What the names in ast refer to, ignoring any warnings
+pub fn resolution(ast: &Statements) -> Resolution {
+    run(ast, &[], false).resolution
+}
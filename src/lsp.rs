@@ -0,0 +1,211 @@
+//! A language server, which speaks the Language Server Protocol over stdin and stdout
+//!
+//! Documents are synced in full on every change, and each change republishes the file's
+//! diagnostics. Everything else is answered from the file's AST and what its names resolve to.
+
+mod document;
+mod rpc;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use serde_json::{json, Value};
+
+use self::document::Document;
+
+/// Error codes from JSON-RPC and LSP
+mod error_code {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+}
+
+struct ResponseError {
+    code: i64,
+    message: String,
+}
+
+impl ResponseError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+struct Server<W: Write> {
+    output: W,
+    documents: HashMap<String, Document>,
+    /// Set by the shutdown request, after which only exit is expected
+    shutting_down: bool,
+}
+
+impl<W: Write> Server<W> {
+    fn respond(&mut self, id: Value, result: Result<Value, ResponseError>) -> io::Result<()> {
+        let response = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(ResponseError { code, message }) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        rpc::write_message(&mut self.output, &response)
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        let notification = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        rpc::write_message(&mut self.output, &notification)
+    }
+
+    fn publish_diagnostics(&mut self, uri: &str) -> io::Result<()> {
+        // a closed document has its diagnostics cleared
+        let diagnostics = self
+            .documents
+            .get(uri)
+            .map(Document::diagnostics)
+            .unwrap_or_default();
+        self.notify(
+            "textDocument/publishDiagnostics",
+            json!({ "uri": uri, "diagnostics": diagnostics }),
+        )
+    }
+
+    fn document(&self, params: &Value) -> Result<&Document, ResponseError> {
+        let uri = params["textDocument"]["uri"].as_str();
+        uri.and_then(|uri| self.documents.get(uri))
+            .ok_or_else(|| ResponseError::new(error_code::INVALID_PARAMS, "Unknown document"))
+    }
+
+    fn document_position(&self, params: &Value) -> Result<(&Document, usize), ResponseError> {
+        let document = self.document(params)?;
+        let offset = document.offset(&params["position"]).ok_or_else(|| {
+            ResponseError::new(
+                error_code::INVALID_PARAMS,
+                "Position is outside the document",
+            )
+        })?;
+        Ok((document, offset))
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, ResponseError> {
+        match method {
+            "initialize" => Ok(json!({
+                "capabilities": {
+                    // the whole document is sent on every change
+                    "textDocumentSync": 1,
+                    "documentSymbolProvider": true,
+                    "definitionProvider": true,
+                    "referencesProvider": true,
+                    "hoverProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "rlox", "version": env!("CARGO_PKG_VERSION") },
+            })),
+            "shutdown" => {
+                self.shutting_down = true;
+                Ok(Value::Null)
+            }
+            _ if self.shutting_down => Err(ResponseError::new(
+                error_code::INVALID_REQUEST,
+                "The server is shutting down",
+            )),
+            "textDocument/documentSymbol" => Ok(json!(self.document(params)?.symbols())),
+            "textDocument/definition" => {
+                let (document, offset) = self.document_position(params)?;
+                let definition = document.definition(offset);
+                Ok(definition.map_or(Value::Null, |span| document.location(span)))
+            }
+            "textDocument/references" => {
+                let (document, offset) = self.document_position(params)?;
+                let include_declaration = params["context"]["includeDeclaration"] == true;
+                let references = document.references(offset, include_declaration);
+                let locations = references.map(|spans| {
+                    let locations = spans.into_iter().map(|span| document.location(span));
+                    locations.collect::<Vec<_>>()
+                });
+                Ok(json!(locations))
+            }
+            "textDocument/hover" => {
+                let (document, offset) = self.document_position(params)?;
+                Ok(document.hover(offset).unwrap_or(Value::Null))
+            }
+            "textDocument/completion" => {
+                let (document, offset) = self.document_position(params)?;
+                Ok(json!(document.completions(offset)))
+            }
+            _ => Err(ResponseError::new(
+                error_code::METHOD_NOT_FOUND,
+                format!("Unsupported method {method}"),
+            )),
+        }
+    }
+
+    /// Whether the server exited cleanly, once the client says to exit
+    fn notification(&mut self, method: &str, params: &Value) -> io::Result<Option<bool>> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "exit" => return Ok(Some(self.shutting_down)),
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                let document = Document::new(uri.to_owned(), text.to_owned());
+                self.documents.insert(uri.to_owned(), document);
+                self.publish_diagnostics(uri)?;
+            }
+            "textDocument/didChange" => {
+                // with full syncing, the last change has the whole text
+                let changes = params["contentChanges"].as_array();
+                let text = changes.and_then(|changes| changes.last()?["text"].as_str());
+                if let Some(text) = text {
+                    let document = Document::new(uri.to_owned(), text.to_owned());
+                    self.documents.insert(uri.to_owned(), document);
+                    self.publish_diagnostics(uri)?;
+                }
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                self.publish_diagnostics(uri)?;
+            }
+            // like initialized, which doesn't need an answer
+            _ => {}
+        }
+        Ok(None)
+    }
+}
+
+/// Answers the client until it exits, returning whether it shut the server down first
+pub fn serve(mut input: impl BufRead, output: impl Write) -> io::Result<bool> {
+    let mut server = Server {
+        output,
+        documents: HashMap::new(),
+        shutting_down: false,
+    };
+    while let Some(body) = rpc::read_message(&mut input)? {
+        let message: Value = match serde_json::from_slice(&body) {
+            Ok(message) => message,
+            Err(e) => {
+                let error = ResponseError::new(error_code::PARSE_ERROR, e.to_string());
+                server.respond(Value::Null, Err(error))?;
+                continue;
+            }
+        };
+        let params = message.get("params").unwrap_or(&Value::Null);
+        match (message.get("id"), message["method"].as_str()) {
+            (Some(id), Some(method)) => {
+                let result = server.request(method, params);
+                server.respond(id.clone(), result)?;
+            }
+            (None, Some(method)) => {
+                if let Some(clean) = server.notification(method, params)? {
+                    return Ok(clean);
+                }
+            }
+            // responses, which never come since the server doesn't make requests
+            _ => {}
+        }
+    }
+    // the client went away without exiting
+    Ok(false)
+}
@@ -0,0 +1,509 @@
+//! What the server knows about an open file, and the questions it answers about it
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use serde_json::{json, Value};
+
+use crate::common::diagnostic::{Diagnostic, Severity};
+use crate::common::ui::{FileId, SourceMap, Span, Spanned};
+use crate::compiler::parse::{
+    BinaryExpr, Call, Catch, Expression, Function, FunctionDeclaration, Identifier, Statement,
+    Statements, KEYWORDS,
+};
+use crate::compiler::{analyze, compile, Resolution, NATIVES};
+
+/// The numbers LSP uses for each kind of symbol
+mod symbol_kind {
+    pub const MODULE: u8 = 2;
+    pub const FUNCTION: u8 = 12;
+    pub const VARIABLE: u8 = 13;
+    pub const CONSTANT: u8 = 14;
+}
+
+/// The numbers LSP uses for each kind of completion
+mod completion_kind {
+    pub const FUNCTION: u8 = 3;
+    pub const VARIABLE: u8 = 6;
+    pub const KEYWORD: u8 = 14;
+}
+
+pub struct Document {
+    uri: String,
+    sources: SourceMap,
+    file: FileId,
+    /// Missing while the file doesn't parse
+    analysis: Option<(Statements, Resolution)>,
+}
+
+impl Document {
+    pub fn new(uri: String, text: String) -> Self {
+        let mut sources = SourceMap::default();
+        let file = sources.add(uri.clone(), text);
+        let analysis = analyze(&sources, file);
+        Self {
+            uri,
+            sources,
+            file,
+            analysis,
+        }
+    }
+
+    fn text(&self) -> &str {
+        self.sources.text(self.file)
+    }
+
+    /// LSP counts characters in UTF-16 code units, from the start of the line
+    fn position(&self, offset: usize) -> Value {
+        let text = self.text();
+        let offset = offset.min(text.len());
+        let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let line = text[..line_start].matches('\n').count();
+        let character: usize = text[line_start..offset].chars().map(char::len_utf16).sum();
+        json!({ "line": line, "character": character })
+    }
+
+    fn range(&self, span: Span) -> Value {
+        let range = span.range();
+        json!({ "start": self.position(range.start), "end": self.position(range.end) })
+    }
+
+    pub fn location(&self, span: Span) -> Value {
+        json!({ "uri": self.uri, "range": self.range(span) })
+    }
+
+    /// The byte offset of an LSP position, where a position past the end of a line is its end
+    pub fn offset(&self, position: &Value) -> Option<usize> {
+        let line = usize::try_from(position["line"].as_u64()?).ok()?;
+        let character = usize::try_from(position["character"].as_u64()?).ok()?;
+        let text = self.text();
+        let line_start = match line {
+            0 => 0,
+            _ => text.match_indices('\n').nth(line - 1)?.0 + 1,
+        };
+        let line_text = text[line_start..].split('\n').next().unwrap_or_default();
+        let mut units = 0;
+        for (i, c) in line_text.char_indices() {
+            if units >= character {
+                return Some(line_start + i);
+            }
+            units += c.len_utf16();
+        }
+        Some(line_start + line_text.len())
+    }
+
+    /// Everything the compiler reports about this file, short of running it
+    pub fn diagnostics(&self) -> Vec<Value> {
+        // compiling adds imported files, which shouldn't stick around between edits
+        let mut sources = SourceMap::default();
+        let file = sources.add(self.uri.clone(), self.text());
+        let path = path_from_uri(&self.uri);
+        let (_, diagnostics) = compile(&mut sources, file, path.as_deref(), &[], false);
+        diagnostics
+            .iter()
+            // problems inside an imported file belong to that file
+            .filter(|diagnostic| diagnostic.primary.span.file() == file)
+            .map(|diagnostic| self.diagnostic(diagnostic))
+            .collect()
+    }
+
+    fn diagnostic(&self, diagnostic: &Diagnostic) -> Value {
+        let severity = match diagnostic.severity {
+            Severity::Error => 1,
+            Severity::Warning => 2,
+        };
+        let parts = [&diagnostic.message, &diagnostic.primary.message];
+        let mut message: Vec<&str> = parts.into_iter().flatten().map(String::as_str).collect();
+        message.extend(diagnostic.notes.iter().map(String::as_str));
+        message.extend(diagnostic.help.as_deref());
+        let related: Vec<Value> = diagnostic
+            .secondary
+            .iter()
+            .filter(|label| label.span.file() == self.file)
+            .map(|label| {
+                json!({
+                    "location": self.location(label.span),
+                    "message": label.message.as_deref().unwrap_or_default(),
+                })
+            })
+            .collect();
+        json!({
+            "range": self.range(diagnostic.primary.span),
+            "severity": severity,
+            "code": diagnostic.code.id,
+            "source": "rlox",
+            "message": message.join("\n"),
+            "relatedInformation": related,
+        })
+    }
+
+    pub fn symbols(&self) -> Vec<Value> {
+        match &self.analysis {
+            Some((ast, _)) => self.symbols_in(ast, true),
+            None => vec![],
+        }
+    }
+
+    /// Functions wherever they're declared, but variables only at the top level, where they're globals
+    fn symbols_in(&self, statements: &Statements, top_level: bool) -> Vec<Value> {
+        let mut symbols = vec![];
+        for statement in statements.0.iter() {
+            let symbol = |id: &Spanned<Identifier>, kind, detail: String, children| {
+                json!({
+                    "name": id.data.0,
+                    "kind": kind,
+                    "detail": detail,
+                    "range": self.range(statement.span.unite(id.span)),
+                    "selectionRange": self.range(id.span),
+                    "children": children,
+                })
+            };
+            match &statement.data {
+                Statement::VarDeclaration { id, .. } if top_level => {
+                    symbols.push(symbol(id, symbol_kind::VARIABLE, String::new(), vec![]));
+                }
+                Statement::ConstDeclaration { id, .. } if top_level => {
+                    symbols.push(symbol(id, symbol_kind::CONSTANT, String::new(), vec![]));
+                }
+                Statement::Import { alias, .. } if top_level => {
+                    symbols.push(symbol(alias, symbol_kind::MODULE, String::new(), vec![]));
+                }
+                Statement::FunctionDeclaration(FunctionDeclaration { name, function }) => {
+                    let children = self.symbols_in(&function.body.data, false);
+                    let detail = parameters(function);
+                    symbols.push(symbol(name, symbol_kind::FUNCTION, detail, children));
+                }
+                Statement::Block(body) | Statement::While { body, .. } => {
+                    symbols.extend(self.symbols_in(&body.data, false));
+                }
+                Statement::IfElse {
+                    then_branch,
+                    else_branch,
+                    ..
+                } => {
+                    for branch in [Some(then_branch), else_branch.as_ref()]
+                        .into_iter()
+                        .flatten()
+                    {
+                        symbols.extend(self.symbols_in(&branch.data, false));
+                    }
+                }
+                Statement::Try {
+                    body,
+                    catch,
+                    finally,
+                } => {
+                    let catch = catch.as_ref().map(|catch| &catch.body);
+                    for body in [Some(body), catch, finally.as_ref()].into_iter().flatten() {
+                        symbols.extend(self.symbols_in(&body.data, false));
+                    }
+                }
+                _ => {}
+            }
+        }
+        symbols
+    }
+
+    /// The name at offset, along with where it was declared, which is itself for a declaration
+    fn name_at(&self, offset: usize) -> Option<(Span, Span)> {
+        let (_, resolution) = self.analysis.as_ref()?;
+        // the cursor can also be right after the name
+        let contains = |span: Span| span.range().contains(&offset) || span.range().end == offset;
+        let reference = resolution
+            .references
+            .iter()
+            .find(|(usage, _)| contains(*usage))
+            .copied();
+        reference.or_else(|| {
+            let declaration = resolution.declarations.iter().find(|d| contains(d.span))?;
+            Some((declaration.span, declaration.span))
+        })
+    }
+
+    pub fn definition(&self, offset: usize) -> Option<Span> {
+        self.name_at(offset).map(|(_, declaration)| declaration)
+    }
+
+    pub fn references(&self, offset: usize, include_declaration: bool) -> Option<Vec<Span>> {
+        let (_, declaration) = self.name_at(offset)?;
+        let (_, resolution) = self.analysis.as_ref()?;
+        let mut spans: Vec<Span> = include_declaration
+            .then_some(declaration)
+            .into_iter()
+            .collect();
+        let references = resolution.references.iter();
+        spans.extend(
+            references
+                .filter(|(_, d)| *d == declaration)
+                .map(|(usage, _)| *usage),
+        );
+        Some(spans)
+    }
+
+    /// How a function that's hovered over can be called
+    pub fn hover(&self, offset: usize) -> Option<Value> {
+        let (name, declaration) = self.name_at(offset)?;
+        let (ast, _) = self.analysis.as_ref()?;
+        let mut functions = vec![];
+        named_functions(ast, &mut functions);
+        let (id, function) = functions
+            .into_iter()
+            .find(|(id, _)| id.span == declaration)?;
+        let arity = arity(function);
+        let noun = if arity == "1" {
+            "argument"
+        } else {
+            "arguments"
+        };
+        let value = format!(
+            "```lox\nfun {}{}\n```\nTakes {arity} {noun}",
+            id.data,
+            parameters(function)
+        );
+        Some(json!({
+            "contents": { "kind": "markdown", "value": value },
+            "range": self.range(name),
+        }))
+    }
+
+    /// The names visible at offset, then the natives and keywords
+    pub fn completions(&self, offset: usize) -> Vec<Value> {
+        let mut names: Vec<(&str, u8)> = vec![];
+        match &self.analysis {
+            Some((ast, resolution)) => {
+                let mut functions = vec![];
+                named_functions(ast, &mut functions);
+                // the innermost declarations are the latest ones
+                for declaration in resolution.declarations.iter().rev() {
+                    let visible = declaration.scope.is_none_or(|scope| {
+                        scope.range().contains(&offset) && declaration.span.range().end <= offset
+                    });
+                    let kind = if functions.iter().any(|(id, _)| id.span == declaration.span) {
+                        completion_kind::FUNCTION
+                    } else {
+                        completion_kind::VARIABLE
+                    };
+                    if visible {
+                        names.push((&declaration.name, kind));
+                    }
+                }
+            }
+            // while the file doesn't parse, every name in it is a reasonable guess
+            None => {
+                let text = self.text();
+                let words = text.split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'));
+                let words = words.filter(|word| {
+                    word.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                        && !KEYWORDS.contains(word)
+                });
+                names.extend(words.map(|word| (word, completion_kind::VARIABLE)));
+            }
+        }
+        names.extend(
+            NATIVES
+                .iter()
+                .map(|(name, _)| (*name, completion_kind::FUNCTION)),
+        );
+        names.extend(
+            KEYWORDS
+                .iter()
+                .map(|keyword| (*keyword, completion_kind::KEYWORD)),
+        );
+        let mut seen = HashSet::new();
+        names
+            .into_iter()
+            .filter(|(name, _)| seen.insert(*name))
+            .map(|(name, kind)| json!({ "label": name, "kind": kind }))
+            .collect()
+    }
+}
+
+/// Where a file URI points, which imports are relative to
+fn path_from_uri(uri: &str) -> Option<PathBuf> {
+    let mut rest = uri.strip_prefix("file://")?.as_bytes();
+    let mut path = vec![];
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(escaped) if byte == b'%' => {
+                path.push(escaped);
+                rest = &tail[2..];
+            }
+            _ => {
+                path.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(path).ok().map(PathBuf::from)
+}
+
+/// Like the runtime's arity, but from the declaration
+fn arity(function: &Function) -> String {
+    let min = function.args.len();
+    let max = function.parameter_count();
+    if function.rest.is_some() {
+        format!("at least {min}")
+    } else if min == max {
+        min.to_string()
+    } else {
+        format!("{min} to {max}")
+    }
+}
+
+fn parameters(function: &Function) -> String {
+    let args = function.args.iter().map(|arg| arg.data.to_string());
+    let defaults = function
+        .defaults
+        .iter()
+        .map(|(arg, default)| format!("{} = {}", arg.data, default.data));
+    let rest = function.rest.iter().map(|rest| format!("...{}", rest.data));
+    let parameters: Vec<String> = args.chain(defaults).chain(rest).collect();
+    format!("({})", parameters.join(", "))
+}
+
+/// Every function with a name, which includes lambdas that are assigned to a variable
+fn named_functions<'a>(
+    statements: &'a Statements,
+    out: &mut Vec<(&'a Spanned<Identifier>, &'a Function)>,
+) {
+    for statement in statements.0.iter() {
+        match &statement.data {
+            Statement::Expr(expr)
+            | Statement::Print(expr)
+            | Statement::Throw { value: expr, .. } => expression_functions(&expr.data, out),
+            Statement::VarDeclaration { id, rhs: Some(rhs) }
+            | Statement::ConstDeclaration { id, rhs } => {
+                if let Expression::Lambda(function) = &rhs.data {
+                    out.push((id, &function.data));
+                }
+                expression_functions(&rhs.data, out);
+            }
+            Statement::VarDeclaration { rhs: None, .. } | Statement::Import { .. } => {}
+            Statement::FunctionDeclaration(FunctionDeclaration { name, function }) => {
+                out.push((name, function));
+                function_functions(function, out);
+            }
+            Statement::Block(body) => named_functions(&body.data, out),
+            Statement::IfElse {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                expression_functions(&cond.data, out);
+                named_functions(&then_branch.data, out);
+                if let Some(else_branch) = else_branch {
+                    named_functions(&else_branch.data, out);
+                }
+            }
+            Statement::While { cond, body } => {
+                expression_functions(&cond.data, out);
+                named_functions(&body.data, out);
+            }
+            Statement::Return { value, .. } => {
+                if let Some(value) = value {
+                    expression_functions(&value.data, out);
+                }
+            }
+            Statement::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                named_functions(&body.data, out);
+                if let Some(Catch { body, .. }) = catch {
+                    named_functions(&body.data, out);
+                }
+                if let Some(finally) = finally {
+                    named_functions(&finally.data, out);
+                }
+            }
+        }
+    }
+}
+
+fn function_functions<'a>(
+    function: &'a Function,
+    out: &mut Vec<(&'a Spanned<Identifier>, &'a Function)>,
+) {
+    for (_, default) in function.defaults.iter() {
+        expression_functions(&default.data, out);
+    }
+    named_functions(&function.body.data, out);
+}
+
+fn expression_functions<'a>(
+    expression: &'a Expression,
+    out: &mut Vec<(&'a Spanned<Identifier>, &'a Function)>,
+) {
+    match expression {
+        Expression::Assignment { rhs, .. } | Expression::CompoundAssignment { rhs, .. } => {
+            expression_functions(&rhs.data, out)
+        }
+        Expression::Binary(BinaryExpr { lhs, rhs, .. }) => {
+            expression_functions(&lhs.data, out);
+            expression_functions(&rhs.data, out);
+        }
+        Expression::Unary { val, .. } => expression_functions(&val.data, out),
+        Expression::Ternary {
+            cond,
+            then_branch,
+            else_branch,
+        } => {
+            expression_functions(&cond.data, out);
+            expression_functions(&then_branch.data, out);
+            expression_functions(&else_branch.data, out);
+        }
+        Expression::Call(Call { callee, args }) => {
+            expression_functions(&callee.data, out);
+            for arg in args {
+                expression_functions(&arg.data, out);
+            }
+        }
+        Expression::Get { object, .. } => expression_functions(&object.data, out),
+        Expression::Lambda(function) => function_functions(&function.data, out),
+        Expression::Literal(_) | Expression::Identifier(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use serde_json::json;
+
+    use super::{path_from_uri, Document};
+
+    #[test]
+    fn positions_count_utf16() {
+        let document = Document::new("untitled:a".to_owned(), "print \"💩\";\nvar x;".to_owned());
+        // the poop emoji is 4 bytes, but 2 UTF-16 code units
+        assert_eq!(document.position(12), json!({"line": 0, "character": 10}));
+        assert_eq!(document.position(15), json!({"line": 1, "character": 1}));
+        assert_eq!(
+            document.offset(&json!({"line": 0, "character": 10})),
+            Some(12)
+        );
+        assert_eq!(
+            document.offset(&json!({"line": 1, "character": 1})),
+            Some(15)
+        );
+        assert_eq!(
+            document.offset(&json!({"line": 1, "character": 99})),
+            Some(20)
+        );
+        assert_eq!(document.offset(&json!({"line": 2, "character": 0})), None);
+    }
+
+    #[test]
+    fn uris() {
+        assert_eq!(
+            path_from_uri("file:///home/me/my%20scripts/a.lox"),
+            Some(PathBuf::from("/home/me/my scripts/a.lox"))
+        );
+        assert_eq!(path_from_uri("untitled:Untitled-1"), None);
+    }
+}
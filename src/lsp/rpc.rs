@@ -0,0 +1,75 @@
+//! The framing of JSON-RPC messages, which are each preceded by a Content-Length header
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// The body of the next message, or None once the client closes the input
+pub fn read_message(input: &mut impl BufRead) -> io::Result<Option<Vec<u8>>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return match length {
+                None => Ok(None),
+                Some(_) => Err(invalid("The input ended in the middle of a header")),
+            };
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        // other headers, like Content-Type, don't change anything
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                let value = value.trim().parse().map_err(|_| invalid(header))?;
+                length = Some(value);
+            }
+        }
+    }
+    let Some(length) = length else {
+        return Err(invalid("A message is missing its Content-Length"));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+pub fn write_message(output: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{read_message, write_message};
+
+    #[test]
+    fn round_trip() {
+        let mut buffer = vec![];
+        write_message(&mut buffer, &json!({"id": 1})).unwrap();
+        write_message(&mut buffer, &json!("💩")).unwrap();
+        let mut input = &buffer[..];
+        assert_eq!(read_message(&mut input).unwrap().unwrap(), br#"{"id":1}"#);
+        assert_eq!(
+            read_message(&mut input).unwrap().unwrap(),
+            "\"💩\"".as_bytes()
+        );
+        assert_eq!(read_message(&mut input).unwrap(), None);
+    }
+
+    #[test]
+    fn other_headers() {
+        let mut input = &b"Content-Type: application/json\r\ncontent-length: 2\r\n\r\n{}"[..];
+        assert_eq!(read_message(&mut input).unwrap().unwrap(), b"{}");
+        let mut input = &b"Content-Type: application/json\r\n\r\n{}"[..];
+        assert!(read_message(&mut input).is_err());
+    }
+}
@@ -3,18 +3,26 @@
 use std::{
     env::args,
     fs::File,
-    io::{stderr, stdout, Read},
+    io::{stderr, stdin, stdout, Read, Write},
     process::ExitCode,
 };
 
-use vm::interpret;
+use cli::ErrorFormat;
+use common::codes;
+use common::diagnostic::render_all;
+use common::ui::SourceMap;
+use vm::{interpret_outcome, interpret_with_stats};
 
 mod bytecode;
-mod common;
+mod cli;
+pub mod common;
 pub mod compiler;
-mod value;
+mod lsp;
+pub mod value;
 pub mod vm;
 
+// main.rs doubles as the lib root for fuzzing, where these are unused
+#[allow(dead_code)]
 fn read_file(filename: &str) -> std::io::Result<String> {
     let mut file = File::open(filename)?;
     let mut source = String::new();
@@ -22,14 +30,93 @@ fn read_file(filename: &str) -> std::io::Result<String> {
     Ok(source)
 }
 
+#[allow(dead_code)]
+fn explain(code: &str) -> ExitCode {
+    match codes::explain(code) {
+        Some(code) => {
+            println!("{}: {}", code.id, code.explanation);
+            ExitCode::SUCCESS
+        }
+        None => {
+            eprintln!("{code} isn't a known error code");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[allow(dead_code)]
+fn lsp() -> ExitCode {
+    // stdout is reserved for the protocol
+    tracing_subscriber::fmt().with_writer(stderr).init();
+    match lsp::serve(stdin().lock(), stdout().lock()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("The language server failed: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Formats each file in place, or with check, only says which ones would change
+#[allow(dead_code)]
+fn fmt(filenames: &[String], check: bool) -> ExitCode {
+    let mut clean = true;
+    for filename in filenames {
+        let source = match read_file(filename) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("Failed to read {filename} with error: {:?}", e);
+                clean = false;
+                continue;
+            }
+        };
+        let mut sources = SourceMap::default();
+        let file = sources.add(filename.as_str(), source);
+        let formatted = match compiler::format(&sources, file) {
+            Ok(formatted) => formatted,
+            Err(diagnostics) => {
+                render_all(&diagnostics, &sources, stderr());
+                clean = false;
+                continue;
+            }
+        };
+        if formatted == sources.text(file) {
+            continue;
+        }
+        if check {
+            eprintln!("{filename} isn't formatted");
+            clean = false;
+        } else if let Err(e) = std::fs::write(filename, formatted) {
+            eprintln!("Failed to write {filename} with error: {:?}", e);
+            clean = false;
+        }
+    }
+    if clean {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+#[allow(dead_code)]
 fn main() -> ExitCode {
-    tracing_subscriber::fmt::init();
-    let mut args = args();
-    args.next();
-    let Some(filename) = args.next() else {
-        eprintln!("Usage: rlox <filename>");
-        return ExitCode::FAILURE;
+    let cli::Args {
+        filename,
+        config,
+        gc_stats,
+        error_format,
+    } = match cli::parse_command(args().skip(1)) {
+        Ok(cli::Command::Run(args)) => args,
+        Ok(cli::Command::Explain(code)) => return explain(&code),
+        Ok(cli::Command::Lsp) => return lsp(),
+        Ok(cli::Command::Fmt { filenames, check }) => return fmt(&filenames, check),
+        Err(e) => {
+            eprintln!("{e}\n\n{}", cli::USAGE);
+            return ExitCode::FAILURE;
+        }
     };
+    tracing_subscriber::fmt::init();
     let source = match read_file(&filename) {
         Ok(file) => file,
         Err(e) => {
@@ -37,15 +124,30 @@ fn main() -> ExitCode {
             return ExitCode::FAILURE;
         }
     };
-    match interpret(&source, stderr(), stdout()) {
+    let config = config.script_path(&filename);
+    let (res, stats) = match error_format {
+        ErrorFormat::Human => interpret_with_stats(&source, config, stderr(), stdout()),
+        ErrorFormat::Json => {
+            let outcome = interpret_outcome(&source, config, stdout());
+            let mut stderr = stderr().lock();
+            for diagnostic in outcome.diagnostics.iter() {
+                writeln!(stderr, "{}", diagnostic.to_json(&outcome.sources)).unwrap();
+            }
+            (outcome.result, outcome.stats)
+        }
+    };
+    if gc_stats {
+        eprintln!("{stats}");
+    }
+    match res {
         Ok(_) => ExitCode::SUCCESS,
-        Err(_) => ExitCode::FAILURE,
+        Err(e) => ExitCode::from(e as u8),
     }
 }
 
 #[cfg(test)]
 mod test_runtime {
-    use crate::{snap_all, snap_interpret};
+    use crate::{snap_all, snap_interpret, vm::config::VMConfig};
     snap_interpret!(floating_expr, "1;");
     snap_interpret!(mismatched_add, "print true + 1;");
     snap_interpret!(mismatched_sub, "print true - 1;");
@@ -93,6 +195,20 @@ mod test_runtime {
         unicode,
         r#"print "💩" + "👪" + "༕" + "갍" + "⑯" + "ฒ" + "ڦ";"#
     );
+    snap_interpret!(error_after_unicode, r#"print "💩👪"; print "갍" + -true;"#);
+    snap_interpret!(
+        error_after_unicode_lines,
+        r#"
+        var s = "༕ฒڦ";
+        print s;
+        print "⑯⑯" + -s;
+        "#
+    );
+    snap_interpret!(parse_error_after_unicode, r#"var s = "갍갍"; print s +;"#);
+    snap_interpret!(
+        warning_after_unicode,
+        r#"{ print "💩"; var unused = "👪"; }"#
+    );
     snap_interpret!(
         globals,
         "
@@ -1119,7 +1235,1045 @@ mod test_runtime {
         "
     }
 
-    snap_interpret!{
+    snap_interpret! {
+        deep_tail_recursion,
+        "
+        fun count(n, acc) {
+            if n == 0 {
+                return acc;
+            }
+            return count(n - 1, acc + 1);
+        }
+        print count(100000, 0);
+        "
+    }
+
+    snap_interpret! {
+        mutual_tail_recursion,
+        "
+        fun even(n) {
+            if n == 0 {
+                return true;
+            }
+            return odd(n - 1);
+        }
+        fun odd(n) {
+            if n == 0 {
+                return false;
+            }
+            return even(n - 1);
+        }
+        print even(10001);
+        "
+    }
+
+    snap_interpret! {
+        tail_call_closes_upvalues,
+        "
+        fun show(f) {
+            return f();
+        }
+        fun outer(n) {
+            var x = n;
+            fun inner() {
+                return x;
+            }
+            var unused = 0;
+            return show(inner);
+        }
+        print outer(1);
+        "
+    }
+
+    snap_interpret! {
+        tail_call_escaping_closure,
+        "
+        fun id(f) {
+            return f;
+        }
+        fun outer(n) {
+            var x = n;
+            fun inner() {
+                print x;
+            }
+            return id(inner);
+        }
+        var filler = 0;
+        outer(1)();
+        "
+    }
+
+    snap_interpret! {
+        tail_call_native,
+        "
+        fun time() {
+            return clock();
+        }
+        print time() == nil;
+        "
+    }
+
+    snap_interpret! {
+        tail_call_wrong_num_args,
+        "
+        fun foo(n) { return n; }
+        fun bar() { return foo(1, 2); }
+        bar();
+        "
+    }
+
+    snap_interpret! {
+        max_frames,
+        "
+        fun sum(n) {
+            if n == 0 {
+                return 0;
+            }
+            return n + sum(n - 1);
+        }
+        print sum(10);
+        print sum(20);
+        ",
+        VMConfig::default().max_frames(15)
+    }
+
+    snap_interpret! {
+        small_stack_overflow,
+        "
+        fun sum(n) {
+            if n == 0 {
+                return 0;
+            }
+            return n + sum(n - 1);
+        }
+        print sum(1000);
+        ",
+        VMConfig::default().max_frames(2000).stack_size(1000)
+    }
+
+    snap_interpret! {
+        growable_stack,
+        "
+        fun sum(n) {
+            if n == 0 {
+                return 0;
+            }
+            return n + sum(n - 1);
+        }
+        print sum(1000);
+        ",
+        VMConfig::default()
+            .max_frames(2000)
+            .stack_size(600)
+            .growable_stack(true)
+    }
+
+    snap_interpret! {
+        growable_stack_relocates_upvalues,
+        "
+        fun deep(n) {
+            var x = n;
+            fun get() {
+                return x;
+            }
+            if n == 0 {
+                return 0;
+            }
+            x = x + deep(n - 1);
+            return get();
+        }
+        print deep(1000);
+        ",
+        VMConfig::default()
+            .max_frames(2000)
+            .stack_size(600)
+            .growable_stack(true)
+    }
+
+    snap_interpret! {
+        out_of_fuel,
+        "
+        var i = 0;
+        while true {
+            i = i + 1;
+        }
+        ",
+        VMConfig::default().fuel(1000)
+    }
+
+    snap_interpret! {
+        enough_fuel,
+        "
+        var i = 0;
+        while i < 10 {
+            i = i + 1;
+        }
+        print i;
+        ",
+        VMConfig::default().fuel(1000)
+    }
+
+    #[test]
+    fn timeout() {
+        let output = crate::common::test_util::mock_interpret_with(
+            "while true {}",
+            VMConfig::default().timeout(std::time::Duration::from_millis(10)),
+        );
+        assert!(output.contains("Exceeded the time limit of 10ms"));
+    }
+
+    snap_interpret! {
+        out_of_memory,
+        r#"
+        var s = "ab";
+        while true {
+            s = s + s;
+        }
+        "#,
+        VMConfig::default().max_memory(4096)
+    }
+
+    snap_interpret! {
+        memory_limit_collects_garbage,
+        r#"
+        var i = 0;
+        while i < 1000 {
+            var garbage = "foo" + "bar";
+            fun closure() {
+                return i;
+            }
+            i = i + 1;
+        }
+        print i;
+        "#,
+        VMConfig::default().max_memory(1024)
+    }
+
+    snap_interpret! {
+        incremental_gc_keeps_closures,
+        r#"
+        fun counter(start) {
+            var count = start;
+            fun increment() {
+                count = count + 1;
+                return count;
+            }
+            return increment;
+        }
+        var a = counter(0);
+        var b = counter(100);
+        var i = 0;
+        while i < 2000 {
+            var garbage = counter(i);
+            garbage();
+            a();
+            b = counter(b());
+            i = i + 1;
+        }
+        print a();
+        print b();
+        "#,
+        VMConfig::default().gc_min_heap(0)
+    }
+
+    snap_interpret! {
+        long_closure_chain,
+        "
+        var f = nil;
+        var i = 0;
+        while i < 100000 {
+            var prev = f;
+            fun g() {
+                return prev;
+            }
+            f = g;
+            i = i + 1;
+        }
+        var depth = 0;
+        while f != nil {
+            f = f();
+            depth = depth + 1;
+        }
+        print depth;
+        ",
+        VMConfig::default().gc_min_heap(0)
+    }
+
+    snap_interpret! {
+        weak_refs,
+        r#"
+        var bang = "!";
+        var kept = "kept" + bang;
+        var strong = weakref(kept);
+        var weak = weakref("dropped" + bang);
+        print deref(strong);
+        var i = 0;
+        while i < 1000 {
+            var garbage = "foo" + "bar";
+            i = i + 1;
+        }
+        print deref(strong);
+        print deref(weak);
+        print weak;
+        "#,
+        VMConfig::default().gc_min_heap(0)
+    }
+    snap_interpret! {
+        weak_ref_to_constant,
+        r#"
+        var weak = weakref("literal");
+        var i = 0;
+        while i < 1000 {
+            var garbage = "foo" + "bar";
+            i = i + 1;
+        }
+        print deref(weak);
+        "#,
+        VMConfig::default().gc_min_heap(0)
+    }
+    snap_interpret!(weak_ref_to_number, "weakref(1);");
+    snap_interpret!(deref_non_weak_ref, "deref(\"foo\");");
+
+    #[test]
+    fn host_natives_with_finalizers() {
+        use crate::value::{native_function::CallError, object::ObjectKind, Value};
+        use std::cell::Cell;
+        thread_local! {
+            static FINALIZED: Cell<u32> = const { Cell::new(0) };
+        }
+        let config = VMConfig::default()
+            .gc_min_heap(0)
+            .deny_warnings(true)
+            .native("resource", |heap, _| {
+                let resource = heap.alloc(ObjectKind::from("resource".to_owned()))?;
+                let finalizer = || FINALIZED.with(|finalized| finalized.set(finalized.get() + 1));
+                heap.add_finalizer(resource, Box::new(finalizer));
+                Ok(Value::Object(resource))
+            })
+            .native("finalized", |_, args| match args {
+                [] => Ok(Value::Num(FINALIZED.with(Cell::get).into())),
+                _ => Err(CallError::ArityMismatch(0)),
+            });
+        let mut stderr = vec![];
+        let mut stdout = vec![];
+        let res = crate::vm::interpret_with(
+            r#"
+            var kept = resource();
+            var i = 0;
+            while i < 100 {
+                resource();
+                i = i + 1;
+            }
+            while i < 2000 {
+                var _garbage = "foo" + "bar";
+                i = i + 1;
+            }
+            print finalized();
+            print kept;
+            "#,
+            config,
+            &mut stderr,
+            &mut stdout,
+        );
+        assert!(res.is_ok(), "{}", String::from_utf8_lossy(&stderr));
+        assert_eq!(String::from_utf8(stdout).unwrap(), "100\nresource\n");
+        // the rest run once the VM is done with them
+        assert_eq!(FINALIZED.with(Cell::get), 101);
+    }
+
+    mod userdata {
+        use std::cell::Cell;
+
+        use crate::{
+            common::try_as::TryAs,
+            snap_interpret,
+            value::{
+                native_function::{CallError, Heap, NativeFn},
+                userdata::{ObjUserData, UserDataType},
+                Value,
+            },
+            vm::config::VMConfig,
+        };
+
+        thread_local! {
+            static DROPPED: Cell<u32> = const { Cell::new(0) };
+        }
+
+        struct Counter(Cell<f64>);
+
+        impl Drop for Counter {
+            fn drop(&mut self) {
+                DROPPED.with(|dropped| dropped.set(dropped.get() + 1));
+            }
+        }
+
+        fn with_count<T>(this: Value, f: impl FnOnce(&Cell<f64>) -> T) -> Result<T, CallError> {
+            let data: ObjUserData = this.try_as().ok_or(CallError::TypeMismatch(1, "counter"))?;
+            let Counter(count) = data
+                .downcast_ref()
+                .ok_or(CallError::TypeMismatch(1, "counter"))?;
+            Ok(f(count))
+        }
+
+        const INCREMENT: NativeFn = |_, args| {
+            let [this, rest @ ..] = args else {
+                return Err(CallError::ArityMismatch(1));
+            };
+            let by = match rest {
+                [] => 1.0,
+                [Value::Num(by)] => *by,
+                [_] => return Err(CallError::TypeMismatch(2, "number")),
+                _ => return Err(CallError::ArityMismatch(2)),
+            };
+            with_count(*this, |count| count.set(count.get() + by))?;
+            Ok(Value::Nil)
+        };
+
+        static COUNTER: UserDataType = UserDataType {
+            name: "counter",
+            display: |data, f| {
+                let Counter(count) = data.downcast_ref().unwrap();
+                write!(f, "<counter at {}>", count.get())
+            },
+            methods: &[
+                ("increment", INCREMENT),
+                ("get", |_, args| match args {
+                    [this] => Ok(Value::Num(with_count(*this, Cell::get)?)),
+                    _ => Err(CallError::ArityMismatch(1)),
+                }),
+            ],
+        };
+
+        fn config() -> VMConfig {
+            VMConfig::default()
+                .native("counter", |heap: &mut dyn Heap, args| {
+                    if !args.is_empty() {
+                        return Err(CallError::ArityMismatch(0));
+                    }
+                    let data = ObjUserData::new(&COUNTER, Counter(Cell::new(0.0)));
+                    Ok(Value::Object(heap.alloc(data.into())?))
+                })
+                .native("dropped", |_, _| {
+                    Ok(Value::Num(DROPPED.with(Cell::get).into()))
+                })
+        }
+
+        snap_interpret! {
+            methods,
+            r#"
+            var c = counter();
+            c.increment();
+            c.increment(5);
+            print c.get();
+            var increment = c.increment;
+            increment();
+            print c;
+            print increment;
+            "#,
+            config()
+        }
+        snap_interpret!(missing_method, "counter().decrement();", config());
+        snap_interpret!(method_arity, "counter().increment(1, 2);", config());
+        snap_interpret!(method_argument_type, "counter().increment(nil);", config());
+        snap_interpret! {
+            dropped_by_sweep,
+            r#"
+            var kept = counter();
+            var i = 0;
+            while i < 100 {
+                counter().increment();
+                i = i + 1;
+            }
+            while i < 2000 {
+                var _garbage = "foo" + "bar";
+                i = i + 1;
+            }
+            print dropped();
+            kept.increment();
+            print kept;
+            "#,
+            config().gc_min_heap(0)
+        }
+    }
+
+    #[test]
+    fn incremental_gc_collects_many_times() {
+        let mut stderr = vec![];
+        let mut stdout = vec![];
+        let (res, stats) = crate::vm::interpret_with_stats(
+            r#"
+            var keep = "kept";
+            var i = 0;
+            while i < 5000 {
+                var garbage = "foo" + "bar";
+                i = i + 1;
+            }
+            print keep + "!";
+            "#,
+            VMConfig::default().gc_min_heap(0),
+            &mut stderr,
+            &mut stdout,
+        );
+        assert!(res.is_ok());
+        assert_eq!(String::from_utf8(stdout).unwrap(), "kept!\n");
+        // every cycle is split into steps, so it takes a while to get through them
+        assert!(stats.collections > 1);
+        assert!(stats.objects_freed > 1000);
+    }
+
+    #[test]
+    fn diagnostics_are_data() {
+        use crate::common::diagnostic::Severity;
+        use crate::common::ui::LineCol;
+        let mut stdout = vec![];
+        let outcome = crate::vm::interpret_outcome(
+            "{ var unused; }\nprint -true;",
+            VMConfig::default(),
+            &mut stdout,
+        );
+        assert!(matches!(
+            outcome.result,
+            Err(crate::vm::InterpretError::RuntimeError)
+        ));
+        let [warning, error] = &outcome.diagnostics[..] else {
+            panic!("{:?}", outcome.diagnostics);
+        };
+        assert_eq!(warning.severity, Severity::Warning);
+        assert_eq!(warning.message.as_deref(), Some("Unused variable"));
+        assert_eq!(&outcome.sources[warning.primary.span], "unused");
+        assert_eq!(error.severity, Severity::Error);
+        assert_eq!(
+            error.message.as_deref(),
+            Some("Tried to negate a boolean (true)")
+        );
+        assert_eq!(
+            outcome.sources.line_col(error.primary.span),
+            LineCol { line: 2, column: 7 }
+        );
+    }
+
+    #[test]
+    fn json_diagnostics() {
+        let outcome = crate::vm::interpret_outcome(
+            "
+            fun inner(x) {
+                return x + \"💩\";
+            }
+            fun outer() {
+                inner(1);
+            }
+            { var unused; }
+            outer();
+            ",
+            VMConfig::default(),
+            vec![],
+        );
+        let lines: Vec<_> = outcome
+            .diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_json(&outcome.sources))
+            .collect();
+        crate::common::test_util::assert_snapshot!(lines.join("\n"));
+    }
+
+    #[test]
+    fn memory_stats() {
+        let mut stderr = vec![];
+        let mut stdout = vec![];
+        let (res, stats) = crate::vm::interpret_with_stats(
+            r#"
+            var s = "";
+            var i = 0;
+            while i < 100 {
+                s = s + "a";
+                i = i + 1;
+            }
+            "#,
+            VMConfig::default(),
+            &mut stderr,
+            &mut stdout,
+        );
+        assert!(res.is_ok());
+        assert!(stats.instructions > 100);
+        assert!(stats.objects > 0);
+        assert!(stats.bytes_allocated >= 100);
+        assert!(stats.peak_bytes_allocated >= stats.bytes_allocated);
+    }
+
+    #[test]
+    fn large_strings_trigger_gc() {
+        let mut stderr = vec![];
+        let mut stdout = vec![];
+        let (res, stats) = crate::vm::interpret_with_stats(
+            r#"
+            var s = "0123456789";
+            var i = 0;
+            while i < 10 {
+                s = s + s;
+                i = i + 1;
+            }
+            i = 0;
+            while i < 10 {
+                var garbage = s + s;
+                i = i + 1;
+            }
+            "#,
+            VMConfig::default().gc_min_heap(16 * 1024),
+            &mut stderr,
+            &mut stdout,
+        );
+        assert!(res.is_ok());
+        // few objects, but each is large
+        assert!(stats.collections > 0);
+        assert!(stats.objects_freed > 0);
+        assert!(stats.bytes_reclaimed > 10 * 1024);
+        assert!(stats.longest_pause <= stats.total_pause);
+    }
+
+    snap_all! {
+        default_parameters,
+        "
+        fun f(a, b = a * 2, c = b + 1) {
+            print a + b + c;
+        }
+        f(1);
+        f(1, 10);
+        f(1, 10, 100);
+        "
+    }
+    snap_all! {
+        rest_parameter,
+        "
+        fun f(a, b = 2, ...rest) {
+            print a;
+            print b;
+            print rest;
+            print len(rest);
+            print get(rest, 0);
+        }
+        f(1);
+        f(1, 3, 4, 5);
+        "
+    }
+    snap_interpret! {
+        default_parameter_tail_call,
+        "
+        fun sum(n, acc = 0) {
+            if n == 0 {
+                return acc;
+            }
+            return sum(n - 1, acc + n);
+        }
+        print sum(10000);
+        "
+    }
+    snap_interpret!(too_few_with_defaults, "fun f(a, b = 1) {} f();");
+    snap_interpret!(too_many_with_defaults, "fun f(a, b = 1) {} f(1, 2, 3);");
+    snap_interpret!(too_few_with_rest, "fun f(a, ...rest) {} f();");
+    snap_interpret!(required_after_default, "fun f(a = 1, b) {}");
+    snap_interpret!(parameter_after_rest, "fun f(...rest, a) {}");
+
+    snap_all! {
+        lambdas,
+        "
+        fun twice(f, x) {
+            return f(f(x));
+        }
+        print twice(fun (x) { return x * 3; }, 1);
+        print twice((x) => x + 1, 1);
+        print (() => \"called\")();
+        fun (x) {
+            print x;
+        }(\"statement\");
+        print fun () {};
+        "
+    }
+    snap_interpret! {
+        lambda_captures,
+        "
+        fun counter() {
+            var count = 0;
+            return () => count = count + 1;
+        }
+        var next = counter();
+        next();
+        print next();
+        var add = (a, b = 10, ...rest) => a + b + len(rest);
+        print add(1);
+        print add(1, 2, 3, 4);
+        "
+    }
+    snap_interpret!(lambda_wrong_num_args, "((a) => a)();");
+
+    snap_all! {
+        constants,
+        "
+        const answer = 42;
+        print answer;
+        {
+            const local = answer + 1;
+            var shadow = local;
+            {
+                var local = 0;
+                local = local + 1;
+                print local;
+            }
+            print local;
+        }
+        var answer = 0;
+        answer = answer + 1;
+        print answer;
+        "
+    }
+    snap_interpret!(assign_const_global, "const x = 1; x = 2;");
+    snap_interpret!(assign_const_local, "{ const x = 1; print x; x = 2; }");
+    snap_interpret! {
+        assign_const_upvalue,
+        "
+        fun outer() {
+            const x = 1;
+            return () => x = 2;
+        }
+        "
+    }
+    snap_interpret! {
+        assign_const_global_at_runtime,
+        "
+        fun set() {
+            x = 2;
+        }
+        const x = 1;
+        set();
+        "
+    }
+    snap_interpret!(const_without_initializer, "const x;");
+
+    snap_interpret! {
+        warnings,
+        "
+        fun f(used, unused, _ignored) {
+            var captured = used;
+            var a = 1;
+            var a = 2;
+            return () => captured + a;
+            print \"unreachable\";
+        }
+        fun recursive(n) {
+            if n > 0 {
+                recursive(n - 1);
+            }
+        }
+        print f(1, 2, 3)();
+        print missing;
+        "
+    }
+    snap_all! {
+        ternary,
+        "
+        print true ? 1 : 2;
+        print false ? 1 : nil ? 2 : 3;
+        print 1 < 2 ? \"yes\" : \"no\";
+        print true or false ? \"a\" : \"b\";
+        "
+    }
+    snap_all! {
+        coalesce,
+        "
+        var missing;
+        print missing ?? \"default\";
+        print false ?? \"default\";
+        print missing ?? missing ?? 0;
+        print nil ?? 1 + 2;
+        "
+    }
+    snap_interpret! {
+        coalesce_short_circuits,
+        "
+        fun loud(value) {
+            print \"evaluated\";
+            return value;
+        }
+        print 1 ?? loud(2);
+        print false ? loud(1) : 2;
+        "
+    }
+    snap_interpret!(ternary_missing_colon, "print true ? 1 2;");
+
+    snap_all! {
+        compound_assignment,
+        "
+        var global = 1;
+        global += 2;
+        global *= 10;
+        global -= 5;
+        global /= 5;
+        print global;
+        {
+            var local = \"a\";
+            local += \"b\";
+            fun append() {
+                local += \"c\";
+            }
+            append();
+            print local;
+        }
+        "
+    }
+    snap_interpret!(compound_assign_const, "{ const x = 1; x += 1; }");
+    snap_interpret!(compound_assign_type_error, "var x = 1; x += true;");
+
+    snap_all! {
+        exceptions,
+        "
+        try {
+            throw \"thrown\";
+        } catch (e) {
+            print e;
+        }
+        fun fails() {
+            return 1 + nil;
+        }
+        try {
+            fails();
+            print \"skipped\";
+        } catch (e) {
+            print e;
+        } finally {
+            print \"finally\";
+        }
+        "
+    }
+    snap_interpret! {
+        builtin_errors_are_catchable,
+        "
+        try { print undefined; } catch (e) { print e; }
+        try { nil(); } catch (e) { print e; }
+        fun two(a, b) {}
+        try { two(1); } catch (e) { print e; }
+        try { len(1); } catch (e) { print e; }
+        "
+    }
+    snap_interpret! {
+        uncaught_throw,
+        "
+        print \"before\";
+        throw 1 + 2;
+        "
+    }
+    snap_interpret! {
+        rethrow_keeps_original_error,
+        "
+        try {
+            print -\"a\";
+        } catch (e) {
+            throw e;
+        }
+        "
+    }
+    snap_interpret! {
+        finally_runs_on_every_path,
+        "
+        fun returns() {
+            try {
+                return \"returned\";
+            } finally {
+                print \"finally after return\";
+            }
+        }
+        print returns();
+        try {
+            try {
+                throw \"inner\";
+            } finally {
+                print \"finally after throw\";
+            }
+        } catch (e) {
+            print e;
+        }
+        try {
+            try {
+                throw \"first\";
+            } catch (_e) {
+                throw \"from catch\";
+            } finally {
+                print \"finally after catch\";
+            }
+        } catch (e) {
+            print e;
+        }
+        "
+    }
+    snap_interpret! {
+        throw_unwinds_frames_and_closes_upvalues,
+        "
+        var get;
+        fun deep(n) {
+            var local = n;
+            fun capture() {
+                return local;
+            }
+            get = capture;
+            if n == 0 {
+                throw \"bottom\";
+            }
+            deep(n - 1);
+        }
+        try {
+            deep(3);
+        } catch (e) {
+            print e;
+            var after = \"stack is intact\";
+            print after;
+        }
+        print get();
+        "
+    }
+    snap_interpret! {
+        try_disables_tail_calls,
+        "
+        fun fails() {
+            throw \"caught\";
+        }
+        fun wrapper() {
+            try {
+                return fails();
+            } catch (e) {
+                return e;
+            }
+        }
+        print wrapper();
+        "
+    }
+    snap_interpret! {
+        stack_overflow_is_catchable,
+        "
+        fun recurse() {
+            recurse();
+        }
+        try {
+            recurse();
+        } catch (_e) {
+            print \"recovered\";
+        }
+        "
+    }
+    snap_interpret!(try_without_handler, "try { print 1; }");
+    snap_interpret!(
+        out_of_fuel_is_not_catchable,
+        "try { while true {} } catch (e) { print e; }",
+        VMConfig::default().fuel(100)
+    );
+
+    snap_interpret!(
+        deny_warnings,
+        "print 1; { var unused; }",
+        VMConfig::default().deny_warnings(true)
+    );
+
+    snap_interpret!(
+        import_module,
+        "
+        import \"math.lox\" as m;
+        print m;
+        print m.pi;
+        print m.square(4);
+        print m.calls();
+        var count = \"main's count\";
+        print count;
+        ",
+        VMConfig::default().script_path("tests/modules/main.lox")
+    );
+    snap_interpret!(
+        import_runs_once,
+        "
+        import \"math.lox\" as m;
+        import \"reexport.lox\" as r;
+        import \"./math.lox\" as again;
+        print r.area(2);
+        print again.calls();
+        print r.count;
+        print m == again;
+        ",
+        VMConfig::default().script_path("tests/modules/main.lox")
+    );
+    snap_interpret!(
+        import_live_exports,
+        "
+        import \"math.lox\" as m;
+        print m.count;
+        m.square(3);
+        print m.count;
+        ",
+        VMConfig::default().script_path("tests/modules/main.lox")
+    );
+    snap_interpret!(
+        import_runtime_error,
+        "
+        import \"fails.lox\" as f;
+        f.add_true(1);
+        ",
+        VMConfig::default().script_path("tests/modules/main.lox")
+    );
+    snap_interpret!(
+        import_missing_export,
+        "import \"math.lox\" as m; print m.nope;",
+        VMConfig::default().script_path("tests/modules/main.lox")
+    );
+    snap_interpret!(
+        import_property_of_non_module,
+        "var x = 1; print x.y;",
+        VMConfig::default()
+    );
+    snap_interpret!(
+        import_cycle,
+        "import \"cycle_a.lox\" as a;",
+        VMConfig::default().script_path("tests/modules/main.lox")
+    );
+    snap_interpret!(
+        import_missing_file,
+        "import \"missing.lox\" as m;",
+        VMConfig::default().script_path("tests/modules/main.lox")
+    );
+    snap_interpret!(
+        import_not_top_level,
+        "{ import \"math.lox\" as m; }",
+        VMConfig::default().script_path("tests/modules/main.lox")
+    );
+    snap_interpret!(
+        import_top_level_return,
+        "import \"returns.lox\" as m;",
+        VMConfig::default().script_path("tests/modules/main.lox")
+    );
+    snap_interpret!(
+        import_uncaught_throw,
+        "import \"throws.lox\" as m;",
+        VMConfig::default().script_path("tests/modules/main.lox")
+    );
+
+    snap_interpret!(
+        suggest_global,
+        "
+        fun show() { print countr; }
+        var counter = 1;
+        show();
+        "
+    );
+    snap_interpret!(suggest_native, "print clokc();");
+    snap_interpret!(
+        suggest_local,
+        "
+        fun area(width, height) { return width * heigth; }
+        print area(1, 2);
+        "
+    );
+    snap_interpret!(suggest_nothing_similar, "print nothing_like_it;");
+    snap_interpret!(
+        suggest_within_module,
+        "var totl = 1; import \"typo.lox\" as t; t.add(1);",
+        VMConfig::default().script_path("tests/modules/main.lox")
+    );
+
+    snap_interpret! {
         escape_mutate,
         "
         {
@@ -0,0 +1,33 @@
+use std::fmt::Display;
+
+use crate::common::{codes::Code, ui::Span};
+
+use super::string::UnsafeString;
+
+/// A built-in runtime error that was caught, which remembers where it happened
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ObjError {
+    pub code: Code,
+    pub message: UnsafeString,
+    pub span: Span,
+}
+
+impl ObjError {
+    pub fn new(code: Code, message: String, span: Span) -> Self {
+        Self {
+            code,
+            message: UnsafeString::from(message),
+            span,
+        }
+    }
+
+    pub unsafe fn free(self) {
+        self.message.free();
+    }
+}
+
+impl Display for ObjError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.message.fmt(f)
+    }
+}
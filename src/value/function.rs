@@ -1,14 +1,24 @@
 use std::fmt::Display;
 
-use crate::vm::upvalue::Upvalue;
+use crate::vm::{
+    gc::{GrayStack, Trace},
+    upvalue::Upvalue,
+};
 
 use super::{string::UnsafeString, valid::ValidPtr};
 
 #[derive(Copy, Clone, Debug, Eq)]
 pub struct ObjFunction {
-    pub arity: u8,
+    /// The number of parameters without defaults
+    pub min_arity: u8,
+    /// The number of parameters, not counting rest
+    pub max_arity: u8,
+    /// Whether extra arguments are collected into a list
+    pub rest: bool,
     pub upvalues: u8,
     pub addr: usize,
+    /// Where to start for each number of arguments from min_arity to max_arity, skipping defaults that were passed
+    pub entries: ValidPtr<[usize]>,
     pub name: UnsafeString,
 }
 
@@ -25,8 +35,30 @@ impl PartialEq for ObjFunction {
 }
 
 impl ObjFunction {
+    pub fn accepts(&self, arg_count: u8) -> bool {
+        self.min_arity <= arg_count && (self.rest || arg_count <= self.max_arity)
+    }
+
+    /// Where to start executing when called with this many arguments
+    pub fn entry(&self, arg_count: u8) -> usize {
+        let defaults_passed = arg_count.min(self.max_arity) - self.min_arity;
+        self.entries[defaults_passed as usize]
+    }
+
+    /// How many arguments this accepts, for error messages
+    pub fn arity(&self) -> String {
+        if self.rest {
+            format!("at least {}", self.min_arity)
+        } else if self.min_arity == self.max_arity {
+            self.min_arity.to_string()
+        } else {
+            format!("{} to {}", self.min_arity, self.max_arity)
+        }
+    }
+
     pub unsafe fn free(&self) {
         self.name.free();
+        ValidPtr::free(self.entries);
     }
 }
 
@@ -38,7 +70,8 @@ pub struct ObjClosure {
 
 impl PartialEq for ObjClosure {
     fn eq(&self, other: &Self) -> bool {
-        self.function == other.function && self.upvalues.as_ptr() == other.upvalues.as_ptr()
+        self.function == other.function
+            && std::ptr::addr_eq(self.upvalues.as_ptr(), other.upvalues.as_ptr())
     }
 }
 
@@ -48,13 +81,12 @@ impl ObjClosure {
     pub unsafe fn free(&self) {
         ValidPtr::free(self.upvalues);
     }
+}
 
-    pub fn mark(&self) {
+impl Trace for ObjClosure {
+    fn trace(&self, gray: &mut GrayStack) {
         for upvalue in &*self.upvalues {
-            unsafe {
-                (*upvalue.as_ptr()).marked = true;
-            }
-            upvalue.mark();
+            gray.shade_upvalue(*upvalue);
         }
     }
 }
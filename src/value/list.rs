@@ -0,0 +1,61 @@
+use std::{fmt::Display, mem::size_of};
+
+use crate::vm::gc::{GrayStack, Trace};
+
+use super::{valid::ValidPtr, Value};
+
+/// An immutable list of values
+#[derive(Copy, Clone, Debug)]
+pub struct ObjList {
+    items: ValidPtr<[Value]>,
+}
+
+impl ObjList {
+    pub fn new(items: Vec<Value>) -> Self {
+        Self {
+            items: ValidPtr::from(items.into_boxed_slice()),
+        }
+    }
+
+    pub fn items(&self) -> &[Value] {
+        &self.items
+    }
+
+    /// The number of bytes owned by this
+    pub fn size(&self) -> usize {
+        self.items.len() * size_of::<Value>()
+    }
+
+    pub unsafe fn free(&self) {
+        ValidPtr::free(self.items);
+    }
+}
+
+impl PartialEq for ObjList {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self.items.as_ptr(), other.items.as_ptr())
+    }
+}
+
+impl Eq for ObjList {}
+
+impl Display for ObjList {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        "[".fmt(f)?;
+        if let Some((first, rest)) = self.items().split_first() {
+            first.fmt(f)?;
+            for item in rest {
+                write!(f, ", {item}")?;
+            }
+        }
+        "]".fmt(f)
+    }
+}
+
+impl Trace for ObjList {
+    fn trace(&self, gray: &mut GrayStack) {
+        for item in self.items() {
+            gray.shade_value(*item);
+        }
+    }
+}
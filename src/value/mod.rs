@@ -1,7 +1,16 @@
+// freeing is only ever done by whatever owns the object, i.e. the garbage collector or a chunk
+#![allow(clippy::missing_safety_doc)]
+pub mod error;
 pub mod function;
+pub mod list;
+pub mod module;
 pub mod native_function;
 pub mod object;
 pub mod string;
+pub mod userdata;
 pub mod valid;
+pub mod weak;
+
+#[allow(clippy::module_inception)]
 mod value;
 pub use value::Value;
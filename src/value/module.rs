@@ -0,0 +1,64 @@
+use std::{borrow::Borrow, fmt::Display, mem::size_of};
+
+use crate::bytecode::chunk::ModuleInfo;
+
+use super::{string::UnsafeString, valid::ValidPtr};
+
+/// The top-level bindings of an imported file, which are read with `module.name`
+#[derive(Copy, Clone, Debug)]
+pub struct ObjModule {
+    pub name: UnsafeString,
+    /// Each export and the global slot it lives in, so reads see later assignments
+    exports: ValidPtr<[(UnsafeString, u8)]>,
+}
+
+impl ObjModule {
+    /// The names are copied, so this doesn't depend on the chunk
+    pub fn new(info: &ModuleInfo) -> Self {
+        let exports: Vec<_> = info
+            .exports
+            .iter()
+            .map(|(name, slot)| (UnsafeString::from(name.as_str()), *slot))
+            .collect();
+        Self {
+            name: UnsafeString::from(info.name.as_str()),
+            exports: ValidPtr::from(exports.into_boxed_slice()),
+        }
+    }
+
+    /// The global slot of an export
+    pub fn get(&self, name: &str) -> Option<u8> {
+        self.exports
+            .iter()
+            .find(|(export, _)| Borrow::<str>::borrow(export) == name)
+            .map(|(_, slot)| *slot)
+    }
+
+    /// The number of bytes owned by this
+    pub fn size(&self) -> usize {
+        let names: usize = self.exports.iter().map(|(name, _)| name.len()).sum();
+        self.name.len() + names + self.exports.len() * size_of::<(UnsafeString, u8)>()
+    }
+
+    pub unsafe fn free(&self) {
+        self.name.free();
+        for (name, _) in self.exports.iter() {
+            name.free();
+        }
+        ValidPtr::free(self.exports);
+    }
+}
+
+impl PartialEq for ObjModule {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self.exports.as_ptr(), other.exports.as_ptr())
+    }
+}
+
+impl Eq for ObjModule {}
+
+impl Display for ObjModule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<module {}>", self.name)
+    }
+}
@@ -1,16 +1,35 @@
 use std::fmt::{Debug, Display};
 
-use super::{string::UnsafeString, value::Value};
+use crate::vm::{gc::Finalizer, InterpretError};
+
+use super::{
+    object::{Object, ObjectKind},
+    string::UnsafeString,
+    value::Value,
+};
 
 pub enum CallError {
     ArityMismatch(u8),
     TypeMismatch(u8, &'static str),
+    /// The VM has already reported why the call failed
+    Reported(InterpretError),
+}
+
+/// What native functions can ask of the VM's heap
+pub trait Heap {
+    /// Hands a new object to the garbage collector
+    /// It's kept alive until the native function returns, and must be reachable after that
+    fn alloc(&mut self, kind: ObjectKind) -> Result<Object, CallError>;
+    /// Runs `finalizer` once `object` has been collected, or when the VM is dropped
+    fn add_finalizer(&mut self, object: Object, finalizer: Finalizer);
 }
 
+pub type NativeFn = fn(&mut dyn Heap, &[Value]) -> Result<Value, CallError>;
+
 #[derive(Copy, Clone)]
 pub struct NativeFunction {
     pub name: UnsafeString,
-    pub function: fn(&[Value]) -> Result<Value, CallError>,
+    pub function: NativeFn,
 }
 
 impl PartialEq for NativeFunction {
@@ -38,7 +57,7 @@ impl NativeFunction {
         self.name.free();
     }
 
-    pub fn call(&self, args: &[Value]) -> Result<Value, CallError> {
-        (self.function)(args)
+    pub fn call(&self, heap: &mut dyn Heap, args: &[Value]) -> Result<Value, CallError> {
+        (self.function)(heap, args)
     }
 }
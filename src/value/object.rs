@@ -1,9 +1,18 @@
+use super::error::ObjError;
 use super::function::{ObjClosure, ObjFunction};
+use super::list::ObjList;
+use super::module::ObjModule;
 use super::native_function::NativeFunction;
+use super::userdata::{ObjBoundMethod, ObjUserData};
+use super::weak::ObjWeakRef;
 use crate::common::{alloc, try_as::TryAs};
+use crate::vm::{
+    gc::{GrayStack, Trace},
+    upvalue::Upvalue,
+};
 
 use super::{string::UnsafeString, valid::ValidPtr};
-use std::fmt::Display;
+use std::{fmt::Display, mem::size_of};
 
 #[repr(transparent)]
 #[derive(Copy, Clone, Debug)]
@@ -13,7 +22,8 @@ pub struct Object {
 
 impl PartialEq for Object {
     fn eq(&self, other: &Self) -> bool {
-        *self.inner == *other.inner
+        // the mark bit isn't part of the value
+        self.inner.kind == other.inner.kind
     }
 }
 
@@ -33,6 +43,14 @@ impl<T: Into<ObjectKind>> From<T> for Object {
 }
 
 impl Object {
+    /// The bytes every object takes up, regardless of its kind
+    pub const HEADER_SIZE: usize = size_of::<ObjectInner>();
+
+    /// The number of bytes this object owns on the heap
+    pub fn size(self) -> usize {
+        Self::HEADER_SIZE + self.inner.kind.size()
+    }
+
     fn from_inner(inner: ObjectInner) -> Self {
         Self {
             inner: ValidPtr::from(Box::new(inner)),
@@ -54,20 +72,6 @@ impl Object {
     pub fn kind(self) -> ObjectKind {
         self.inner.kind
     }
-
-    pub fn mark(self) {
-        #[cfg(feature = "verbose_gc")]
-        {
-            eprintln!("Mark {:?} == {}", self.inner, self.inner.kind);
-        }
-        if self.inner.marked {
-            return;
-        }
-        unsafe {
-            (*self.inner.as_ptr()).marked = true;
-        }
-        self.inner.kind.mark();
-    }
 }
 
 impl<T> TryAs<T> for Object
@@ -94,6 +98,12 @@ pub enum ObjectKind {
     Function { fun: ObjFunction },
     Closure { fun: ObjClosure },
     NativeFunction { fun: NativeFunction },
+    WeakRef { weak: ObjWeakRef },
+    UserData { data: ObjUserData },
+    BoundMethod { method: ObjBoundMethod },
+    List { list: ObjList },
+    Error { error: ObjError },
+    Module { module: ObjModule },
 }
 
 impl Display for ObjectKind {
@@ -103,6 +113,12 @@ impl Display for ObjectKind {
             Self::Function { fun } => fun.fmt(f),
             Self::Closure { fun } => fun.function.fmt(f),
             Self::NativeFunction { fun } => fun.fmt(f),
+            Self::WeakRef { weak } => weak.fmt(f),
+            Self::UserData { data } => data.fmt(f),
+            Self::BoundMethod { method } => method.fmt(f),
+            Self::List { list } => list.fmt(f),
+            Self::Error { error } => error.fmt(f),
+            Self::Module { module } => module.fmt(f),
         }
     }
 }
@@ -131,6 +147,42 @@ impl From<NativeFunction> for ObjectKind {
     }
 }
 
+impl From<ObjWeakRef> for ObjectKind {
+    fn from(weak: ObjWeakRef) -> Self {
+        ObjectKind::WeakRef { weak }
+    }
+}
+
+impl From<ObjUserData> for ObjectKind {
+    fn from(data: ObjUserData) -> Self {
+        ObjectKind::UserData { data }
+    }
+}
+
+impl From<ObjBoundMethod> for ObjectKind {
+    fn from(method: ObjBoundMethod) -> Self {
+        ObjectKind::BoundMethod { method }
+    }
+}
+
+impl From<ObjList> for ObjectKind {
+    fn from(list: ObjList) -> Self {
+        ObjectKind::List { list }
+    }
+}
+
+impl From<ObjError> for ObjectKind {
+    fn from(error: ObjError) -> Self {
+        ObjectKind::Error { error }
+    }
+}
+
+impl From<ObjModule> for ObjectKind {
+    fn from(module: ObjModule) -> Self {
+        ObjectKind::Module { module }
+    }
+}
+
 impl From<String> for ObjectKind {
     fn from(value: String) -> Self {
         ObjectKind::String {
@@ -157,12 +209,70 @@ impl TryAs<UnsafeString> for ObjectKind {
     }
 }
 
+impl TryAs<ObjWeakRef> for ObjectKind {
+    fn try_as(self) -> Option<ObjWeakRef> {
+        match self {
+            ObjectKind::WeakRef { weak } => Some(weak),
+            _ => None,
+        }
+    }
+}
+
+impl TryAs<ObjUserData> for ObjectKind {
+    fn try_as(self) -> Option<ObjUserData> {
+        match self {
+            ObjectKind::UserData { data } => Some(data),
+            _ => None,
+        }
+    }
+}
+
+impl TryAs<ObjList> for ObjectKind {
+    fn try_as(self) -> Option<ObjList> {
+        match self {
+            ObjectKind::List { list } => Some(list),
+            _ => None,
+        }
+    }
+}
+
+impl TryAs<ObjError> for ObjectKind {
+    fn try_as(self) -> Option<ObjError> {
+        match self {
+            ObjectKind::Error { error } => Some(error),
+            _ => None,
+        }
+    }
+}
+
 impl ObjectKind {
     fn typename(self) -> &'static str {
         match self {
             Self::String { .. } => "string",
             Self::Closure { .. } | Self::Function { .. } => "function",
             Self::NativeFunction { .. } => "native-function",
+            Self::WeakRef { .. } => "weakref",
+            Self::UserData { data } => data.ty().name,
+            Self::BoundMethod { .. } => "native-method",
+            Self::List { .. } => "list",
+            Self::Error { .. } => "error",
+            Self::Module { .. } => "module",
+        }
+    }
+
+    /// The number of bytes owned by this kind of object, beyond the header
+    pub fn size(self) -> usize {
+        match self {
+            Self::String { str } => str.len(),
+            Self::Function { fun } => fun.name.len(),
+            Self::Closure { fun } => fun.upvalues.len() * size_of::<ValidPtr<Upvalue>>(),
+            Self::NativeFunction { fun } => fun.name.len(),
+            Self::WeakRef { .. } => size_of::<Option<Object>>(),
+            Self::UserData { data } => data.size(),
+            Self::BoundMethod { .. } => 0,
+            Self::List { list } => list.size(),
+            Self::Error { error } => error.message.len(),
+            Self::Module { module } => module.size(),
         }
     }
 
@@ -172,15 +282,32 @@ impl ObjectKind {
             Self::Function { fun } => fun.free(),
             Self::Closure { fun } => fun.free(),
             Self::NativeFunction { fun } => fun.free(),
+            Self::WeakRef { weak } => weak.free(),
+            Self::UserData { data } => data.free(),
+            Self::BoundMethod { .. } => {}
+            Self::List { list } => list.free(),
+            Self::Error { error } => error.free(),
+            Self::Module { module } => module.free(),
         }
     }
+}
 
-    pub fn mark(self) {
-        // this is going to be extended when objects are added anyways
-        #[allow(clippy::single_match)]
+impl Trace for ObjectKind {
+    fn trace(&self, gray: &mut GrayStack) {
         match self {
-            ObjectKind::Closure { fun } => fun.mark(),
-            _ => {} // functions and native functions are both static, strings have nothing to collect
+            Self::Closure { fun } => fun.trace(gray),
+            Self::List { list } => list.trace(gray),
+            Self::BoundMethod { method } => gray.shade_object(method.receiver),
+            // functions and native functions are both static, strings have nothing to trace
+            // weak references are cleared instead of keeping their target alive
+            // userdata is opaque, errors only own their message, and modules read their globals
+            Self::String { .. }
+            | Self::Function { .. }
+            | Self::NativeFunction { .. }
+            | Self::WeakRef { .. }
+            | Self::UserData { .. }
+            | Self::Error { .. }
+            | Self::Module { .. } => {}
         }
     }
 }
@@ -58,6 +58,14 @@ impl Add<UnsafeString> for UnsafeString {
 }
 
 impl UnsafeString {
+    pub fn len(&self) -> usize {
+        self.str.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.str.is_empty()
+    }
+
     pub unsafe fn free(self) {
         alloc::trace!("Freeing string '{self}'");
         drop(Box::from_raw(self.str.as_ptr()));
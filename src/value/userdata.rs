@@ -0,0 +1,171 @@
+use std::{
+    any::Any,
+    fmt::{self, Debug, Display, Formatter},
+    mem::{size_of, size_of_val},
+};
+
+use super::{native_function::NativeFn, object::Object, valid::ValidPtr};
+
+/// Describes a kind of host data, which is shared by every value of that kind
+pub struct UserDataType {
+    /// What scripts see as the typename
+    pub name: &'static str,
+    /// Called with the data to print it
+    pub display: fn(&dyn Any, &mut Formatter) -> fmt::Result,
+    /// Natives that expect the data as their first argument
+    pub methods: &'static [(&'static str, NativeFn)],
+}
+
+impl Debug for UserDataType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "<userdata type {}>", self.name)
+    }
+}
+
+#[derive(Debug)]
+pub struct UserDataInner {
+    ty: &'static UserDataType,
+    data: Box<dyn Any>,
+}
+
+/// Host data that scripts can pass around, but only natives can look inside of
+/// The garbage collector doesn't look inside either, so it mustn't hold onto any objects
+#[derive(Copy, Clone, Debug)]
+pub struct ObjUserData {
+    inner: ValidPtr<UserDataInner>,
+}
+
+impl ObjUserData {
+    pub fn new<T: Any>(ty: &'static UserDataType, data: T) -> Self {
+        Self {
+            inner: ValidPtr::new(UserDataInner {
+                ty,
+                data: Box::new(data),
+            }),
+        }
+    }
+
+    pub fn ty(&self) -> &'static UserDataType {
+        self.inner.ty
+    }
+
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.inner.data.downcast_ref()
+    }
+
+    pub fn method(&self, name: &str) -> Option<(&'static str, NativeFn)> {
+        self.ty()
+            .methods
+            .iter()
+            .find(|(method, _)| *method == name)
+            .copied()
+    }
+
+    /// The number of bytes owned by this, including the host's data
+    pub fn size(&self) -> usize {
+        size_of::<UserDataInner>() + size_of_val(&*self.inner.data)
+    }
+
+    /// This runs the host's Drop
+    pub unsafe fn free(&self) {
+        ValidPtr::free(self.inner);
+    }
+}
+
+impl PartialEq for ObjUserData {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner.as_ptr() == other.inner.as_ptr()
+    }
+}
+
+impl Eq for ObjUserData {}
+
+impl Display for ObjUserData {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        (self.ty().display)(&*self.inner.data, f)
+    }
+}
+
+/// A method read off of userdata, which passes that userdata as the first argument when called
+#[derive(Copy, Clone, Debug)]
+pub struct ObjBoundMethod {
+    pub receiver: Object,
+    pub name: &'static str,
+    pub function: NativeFn,
+}
+
+impl PartialEq for ObjBoundMethod {
+    fn eq(&self, other: &Self) -> bool {
+        self.receiver == other.receiver && self.name == other.name
+    }
+}
+
+impl Eq for ObjBoundMethod {}
+
+impl Display for ObjBoundMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "<native method {} of {}>", self.name, self.receiver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{any::Any, cell::Cell, fmt, rc::Rc};
+
+    use super::{ObjUserData, UserDataType};
+    use crate::value::{
+        native_function::{CallError, Heap},
+        object::Object,
+        Value,
+    };
+
+    struct File {
+        name: String,
+        closed: Rc<Cell<bool>>,
+    }
+
+    impl Drop for File {
+        fn drop(&mut self) {
+            self.closed.set(true);
+        }
+    }
+
+    fn display(data: &dyn Any, f: &mut fmt::Formatter) -> fmt::Result {
+        let file: &File = data.downcast_ref().unwrap();
+        write!(f, "<file {}>", file.name)
+    }
+
+    fn close(_: &mut dyn Heap, _: &[Value]) -> Result<Value, CallError> {
+        Ok(Value::Nil)
+    }
+
+    static FILE: UserDataType = UserDataType {
+        name: "file",
+        display,
+        methods: &[("close", close)],
+    };
+
+    #[test]
+    fn userdata() {
+        let closed = Rc::new(Cell::new(false));
+        let data = ObjUserData::new(
+            &FILE,
+            File {
+                name: "foo.lox".to_owned(),
+                closed: closed.clone(),
+            },
+        );
+        let obj = Object::from(data);
+        assert_eq!(obj.typename(), "file");
+        assert_eq!(obj.to_string(), "<file foo.lox>");
+        assert_eq!(data.downcast_ref::<File>().unwrap().name, "foo.lox");
+        assert!(data.downcast_ref::<String>().is_none());
+        assert!(data.method("close").is_some());
+        assert!(data.method("open").is_none());
+
+        unsafe {
+            obj.free();
+        }
+        assert!(closed.get());
+    }
+}
@@ -0,0 +1,49 @@
+use std::fmt::Display;
+
+use super::{object::Object, valid::ValidPtr};
+
+/// A reference that doesn't keep its target alive
+/// The garbage collector clears it once the target is unreachable, before freeing the target
+#[derive(Copy, Clone, Debug)]
+pub struct ObjWeakRef {
+    target: ValidPtr<Option<Object>>,
+}
+
+impl ObjWeakRef {
+    pub fn new(target: Object) -> Self {
+        Self {
+            target: ValidPtr::new(Some(target)),
+        }
+    }
+
+    pub fn get(&self) -> Option<Object> {
+        *self.target
+    }
+
+    pub fn clear(&self) {
+        unsafe {
+            *self.target.as_ptr() = None;
+        }
+    }
+
+    pub unsafe fn free(&self) {
+        ValidPtr::free(self.target);
+    }
+}
+
+impl PartialEq for ObjWeakRef {
+    fn eq(&self, other: &Self) -> bool {
+        self.target.as_ptr() == other.target.as_ptr()
+    }
+}
+
+impl Eq for ObjWeakRef {}
+
+impl Display for ObjWeakRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.get() {
+            Some(target) => write!(f, "<weakref to {target}>"),
+            None => write!(f, "<weakref to nothing>"),
+        }
+    }
+}
@@ -1,33 +1,51 @@
+pub mod config;
+pub mod gc;
 mod stack;
+pub mod stats;
 pub mod upvalue;
 use std::{
+    borrow::Borrow,
     hint::unreachable_unchecked,
     io::Write,
     mem::{size_of, transmute},
     ops::Range,
+    slice,
+    time::Instant,
 };
 
-use ariadne::{Color, Label, Report, ReportKind, Source};
 use bytemuck::{pod_read_unaligned, AnyBitPattern};
 
 use crate::{
     bytecode::chunk::{Chunk, OpCode},
     common::{
+        codes::{self, Code},
+        diagnostic::{render_all, Diagnostic, StackFrame},
+        suggest::undefined_help,
         try_as::{TryAs, TryCast},
-        ui::{self, Span},
+        ui::{SourceMap, Span, SCRIPT_NAME},
     },
     compiler::compile,
     value::{
+        error::ObjError,
         function::{ObjClosure, ObjFunction},
-        native_function::{CallError, NativeFunction},
+        list::ObjList,
+        module::ObjModule,
+        native_function::{CallError, NativeFn},
         object::{Object, ObjectKind},
         string::UnsafeString,
+        userdata::ObjBoundMethod,
         valid::ValidPtr,
         Value,
     },
 };
 
-use self::{stack::FixedStack, upvalue::Upvalue};
+use self::{
+    config::VMConfig,
+    gc::{Finalizer, GcPhase, GrayStack},
+    stack::{Stack, FRAME_HEADROOM},
+    stats::Stats,
+    upvalue::Upvalue,
+};
 
 #[derive(Copy, Clone, Debug)]
 struct CallFrame {
@@ -36,13 +54,46 @@ struct CallFrame {
     closure: ObjClosure,
 }
 
-struct VM<'src, Stderr: Write, Stdout: Write> {
+/// Where to go when something is thrown, pushed when entering a try block
+#[derive(Copy, Clone, Debug)]
+struct Handler {
+    /// The number of callframes when this was pushed, so deeper ones can be discarded
+    frames: usize,
+    stack_len: usize,
+    catch_addr: usize,
+}
+
+/// Whatever is being thrown, while looking for a handler
+#[derive(Debug)]
+enum Exception {
+    /// A built-in runtime error, which only becomes a value if it's caught
+    Error {
+        code: Code,
+        span: Span,
+        message: String,
+        /// Only shown if the error isn't caught, since error values just hold the message
+        help: Option<String>,
+    },
+    Value {
+        span: Span,
+        value: Value,
+    },
+}
+
+struct VM<'src, Stdout: Write> {
     chunk: Chunk,
     ip: usize,
     callframe: Vec<CallFrame>,
-    stack: FixedStack,
-    source: &'src str,
-    stderr: Stderr,
+    handlers: Vec<Handler>,
+    /// Set right before a runtime error is returned, and taken while unwinding
+    exception: Option<Exception>,
+    stack: Stack,
+    config: VMConfig,
+    /// Only needed to show where each instruction came from while tracing
+    #[cfg_attr(not(feature = "verbose_vm"), allow(dead_code))]
+    sources: &'src SourceMap,
+    /// Errors that ended the script, for the host to show however it likes
+    diagnostics: Vec<Diagnostic>,
     stdout: Stdout,
     /// SAFETY INVARIANT: All objects in objects are valid, and there are no duplicate allocations
     /// This is used to look for inaccessible objects to free
@@ -50,20 +101,37 @@ struct VM<'src, Stderr: Write, Stdout: Write> {
     upvalue_storage: Vec<ValidPtr<Upvalue>>,
     /// Chunk is the source of truth for indices
     globals: Vec<Option<Value>>,
+    /// Each module that has been imported, indexed like chunk.modules
+    modules: Vec<Option<Object>>,
+    /// Whether each global was defined with const, indexed like globals
+    const_globals: Vec<bool>,
     open_upvalues: Option<ValidPtr<Upvalue>>,
+    /// A collection starts once bytes_allocated reaches this
     next_gc: usize,
+    gc_phase: GcPhase,
+    /// Bytes allocated since the last step of a collection in progress
+    gc_debt: usize,
+    gray: GrayStack,
+    /// Every weak reference that hasn't been cleared yet
+    weak_refs: Vec<Object>,
+    finalizers: Vec<(Object, Finalizer)>,
+    /// Finalizers of objects that are being swept, which run once the sweep is done
+    pending_finalizers: Vec<Finalizer>,
+    /// Objects allocated by the native function being called, which aren't reachable yet
+    native_roots: Vec<Object>,
+    /// Only the garbage collection counters are kept up to date
+    gc_stats: Stats,
+    /// The number of bytes owned by objects and upvalue_storage
+    bytes_allocated: usize,
+    peak_bytes_allocated: usize,
+    /// The number of instructions executed so far
+    instructions: u64,
+    deadline: Option<Instant>,
 }
 
-impl<'src, Stderr: Write, Stdout: Write> Drop for VM<'src, Stderr, Stdout> {
+impl<'src, Stdout: Write> Drop for VM<'src, Stdout> {
     fn drop(&mut self) {
-        // these are rare, but may happen if the stack overflows
-        self.callframe.clear();
-        self.open_upvalues = None;
-        // This is inefficient, but partly helps debugging memory leaks
-        self.globals.clear();
-        self.stack.clear();
-        self.mark_everything();
-        self.sweep();
+        self.free_everything();
     }
 }
 
@@ -71,26 +139,47 @@ impl<'src, Stderr: Write, Stdout: Write> Drop for VM<'src, Stderr, Stdout> {
 pub enum InterpretError {
     CompileError = 1,
     RuntimeError = 2,
+    /// The script ran out of fuel, time or memory
+    LimitExceeded = 3,
 }
 
 type InterpretResult = Result<(), InterpretError>;
 
-impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
-    fn new(chunk: Chunk, source: &'src str, stderr: Stderr, stdout: Stdout) -> Self {
+impl<'src, Stdout: Write> VM<'src, Stdout> {
+    fn new(chunk: Chunk, config: VMConfig, sources: &'src SourceMap, stdout: Stdout) -> Self {
         debug_assert!(!chunk.instructions.is_empty());
+        let modules = vec![None; chunk.modules.len()];
         Self {
             callframe: vec![],
+            handlers: vec![],
+            exception: None,
             ip: 0,
             chunk,
-            source,
-            stack: FixedStack::new(),
+            sources,
+            // the top level needs room for its own frame too
+            stack: Stack::new(config.stack_size.max(FRAME_HEADROOM)),
             objects: vec![],
             upvalue_storage: vec![],
-            stderr,
+            diagnostics: vec![],
             stdout,
             globals: vec![],
+            modules,
+            const_globals: vec![],
             open_upvalues: None,
-            next_gc: 1024,
+            next_gc: config.gc_min_heap,
+            gc_phase: GcPhase::Idle,
+            gc_debt: 0,
+            gray: GrayStack::default(),
+            weak_refs: vec![],
+            finalizers: vec![],
+            pending_finalizers: vec![],
+            native_roots: vec![],
+            gc_stats: Stats::default(),
+            config,
+            bytes_allocated: 0,
+            peak_bytes_allocated: 0,
+            instructions: 0,
+            deadline: None,
         }
     }
 
@@ -134,6 +223,7 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
             (a, b) => {
                 let span = self.get_span(-2..1);
                 self.runtime_error(
+                    codes::TYPE_MISMATCH,
                     span,
                     format!(
                         "Operator '{name}' takes two numbers. Got a {} ({a}) and a {} ({b}).",
@@ -147,54 +237,159 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
         Ok(())
     }
 
-    fn runtime_error(&mut self, span: Span, message: String) -> InterpretError {
-        Report::build(ReportKind::Error, (), ui::OFFSET)
-            .with_message(message)
-            .with_label(Label::new(span).with_color(Color::Red))
-            .finish()
-            // this mutable borrow infects everything it touches, hence &mut self
-            // it isn't currently presenting an issue, but perhaps DI was a mistake
-            .write(Source::from(self.source), &mut self.stderr)
-            .unwrap();
+    /// Throws a built-in error, which is only reported if nothing catches it
+    fn runtime_error(&mut self, code: Code, span: Span, message: String) -> InterpretError {
+        self.exception = Some(Exception::Error {
+            code,
+            span,
+            message,
+            help: None,
+        });
         InterpretError::RuntimeError
     }
 
-    fn define_global(&mut self, index: u8, value: Value) {
+    /// Reading or assigning a global that isn't defined, which is usually a typo
+    fn undefined_global(&mut self, index: u8) -> InterpretError {
+        let span = self.get_span(-2..0);
+        let name = self.chunk.globals.get_name(index);
+        let name: &str = name.borrow();
+        // a module's globals are prefixed by it, and only its own ones are worth suggesting
+        let (prefix, base) = name.rsplit_once("::").unwrap_or(("", name));
+        let defined: Vec<UnsafeString> = (0..self.globals.len())
+            .filter(|&i| self.globals[i].is_some())
+            .map(|i| self.chunk.globals.get_name(i as u8))
+            .collect();
+        let candidates = defined.iter().filter_map(|candidate| {
+            let candidate: &str = candidate.borrow();
+            match candidate.rsplit_once("::") {
+                Some((candidate_prefix, candidate)) if candidate_prefix == prefix => {
+                    Some(candidate)
+                }
+                Some(_) => None,
+                // natives are visible from everywhere
+                None => Some(candidate),
+            }
+        });
+        let help = undefined_help(base, candidates);
+        self.exception = Some(Exception::Error {
+            code: codes::UNDEFINED_GLOBAL,
+            span,
+            message: format!("Undefined variable: {name}"),
+            help,
+        });
+        InterpretError::RuntimeError
+    }
+
+    /// Reports an error immediately, for the ones that can't be caught
+    fn report_error(
+        &mut self,
+        code: Code,
+        span: Span,
+        message: String,
+        label: Option<String>,
+        help: Option<String>,
+    ) {
+        let call_stack = self.call_stack(span);
+        let mut error = Diagnostic::error(code, span)
+            .with_message(message)
+            .with_call_stack(call_stack);
+        if let Some(label) = label {
+            error = error.with_label(label);
+        }
+        if let Some(help) = help {
+            error = error.with_help(help);
+        }
+        self.diagnostics.push(error);
+    }
+
+    /// Where each function that's running is at, given where the innermost one is
+    fn call_stack(&self, mut span: Span) -> Vec<StackFrame> {
+        let mut stack = vec![];
+        for frame in self.callframe.iter().rev() {
+            stack.push(StackFrame {
+                function: frame.closure.function.name.to_string(),
+                span,
+            });
+            // the last byte of the call that's being returned to
+            span = self.chunk.spans[frame.return_addr - 1];
+        }
+        stack.push(StackFrame {
+            function: "<top level>".to_owned(),
+            span,
+        });
+        stack
+    }
+
+    /// How often the deadline is checked, since reading the clock is comparatively slow
+    const DEADLINE_INTERVAL: u64 = 1024;
+
+    fn check_limits(&mut self) -> InterpretResult {
+        self.instructions += 1;
+        let (code, message) =
+            if let Some(fuel) = self.config.fuel.filter(|&f| self.instructions > f) {
+                (
+                    codes::OUT_OF_FUEL,
+                    format!("Exceeded the budget of {fuel} instructions"),
+                )
+            } else if self.instructions.is_multiple_of(Self::DEADLINE_INTERVAL)
+                && self
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                (
+                    codes::TIMEOUT,
+                    format!(
+                        "Exceeded the time limit of {:?}",
+                        self.config.timeout.unwrap_or_default()
+                    ),
+                )
+            } else {
+                return Ok(());
+            };
+        // the instruction that would have run next
+        let span = self.get_span(0..1);
+        let label = "execution stopped here".to_owned();
+        self.report_error(code, span, message, Some(label), None);
+        Err(InterpretError::LimitExceeded)
+    }
+
+    fn define_global(&mut self, index: u8, value: Value, constant: bool) {
         let index = index as usize;
         while self.globals.len() <= index {
             self.globals.push(None);
+            self.const_globals.push(false);
         }
         self.globals[index] = Some(value);
+        self.const_globals[index] = constant;
     }
 
     fn get_global(&mut self, index: u8) -> Result<Value, InterpretError> {
         match self.globals.get(index as usize) {
             Some(Some(value)) => Ok(*value),
-            _ => {
-                let span = self.get_span(-2..0);
-                self.runtime_error(
-                    span,
-                    format!("Undefined variable: {}", self.chunk.globals.get_name(index)),
-                );
-                Err(InterpretError::RuntimeError)
-            }
+            _ => Err(self.undefined_global(index)),
         }
     }
 
     fn set_global(&mut self, index: u8, value: Value) -> InterpretResult {
+        // codegen catches this within a chunk, but not for globals it didn't see declared
+        if self.const_globals.get(index as usize) == Some(&true) {
+            let span = self.get_span(-2..0);
+            self.runtime_error(
+                codes::CONST_ASSIGNMENT,
+                span,
+                format!(
+                    "Cannot assign to constant {}",
+                    self.chunk.globals.get_name(index)
+                ),
+            );
+            return Err(InterpretError::RuntimeError);
+        }
         match self.globals.get_mut(index as usize) {
             Some(v) if v.is_some() => {
                 *v = Some(value);
                 Ok(())
             }
-            _ => {
-                let span = self.get_span(-2..0);
-                self.runtime_error(
-                    span,
-                    format!("Undefined variable: {}", self.chunk.globals.get_name(index)),
-                );
-                Err(InterpretError::RuntimeError)
-            }
+            _ => Err(self.undefined_global(index)),
         }
     }
 
@@ -207,6 +402,7 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
             val => {
                 let span = self.get_span(-1..0);
                 self.runtime_error(
+                    codes::TYPE_MISMATCH,
                     span,
                     format!("Tried to negate a {} ({val})", val.typename()),
                 );
@@ -217,10 +413,13 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
     }
 
     unsafe fn add(&mut self) -> InterpretResult {
-        let b = self.pop();
-        let a = self.pop();
+        // the operands stay on the stack until the result is allocated, since that may collect garbage
+        let b = self.peek(0);
+        let a = self.peek(1);
         match (a, b) {
             (Value::Num(a), Value::Num(b)) => {
+                self.pop();
+                self.pop();
                 self.push(Value::Num(a + b));
                 return Ok(());
             }
@@ -228,8 +427,10 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
                 let a = UnsafeString::try_cast(a);
                 let b = UnsafeString::try_cast(b);
                 if let (Some(a), Some(b)) = (a, b) {
-                    let concatenated = Object::from(a + b);
-                    self.objects.push(concatenated);
+                    self.reserve(Object::HEADER_SIZE + a.len() + b.len())?;
+                    self.pop();
+                    self.pop();
+                    let concatenated = self.track(Object::from(a + b));
                     self.push(Value::Object(concatenated));
                     return Ok(());
                 }
@@ -238,6 +439,7 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
         }
         let span = self.get_span(-2..1);
         self.runtime_error(
+            codes::TYPE_MISMATCH,
             span,
             format!(
                 "Operator '+' takes two numbers. Got a {} ({a}) and a {} ({b}).",
@@ -248,6 +450,61 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
         Err(InterpretError::RuntimeError)
     }
 
+    /// The object has to stay on the stack until this returns, since binding a method allocates
+    fn get_property(&mut self, object: Value, name: UnsafeString) -> Result<Value, InterpretError> {
+        let span = self.get_span(-2..0);
+        let module = match object.try_as() {
+            Some(ObjectKind::Module { module }) => module,
+            Some(ObjectKind::UserData { data }) => {
+                let Some((name, function)) = data.method(Borrow::<str>::borrow(&name)) else {
+                    return Err(self.runtime_error(
+                        codes::MISSING_METHOD,
+                        span,
+                        format!("A {} has no method named {name}", object.typename()),
+                    ));
+                };
+                self.reserve(Object::HEADER_SIZE)?;
+                let method = ObjBoundMethod {
+                    receiver: object.unwrap_as(),
+                    name,
+                    function,
+                };
+                return Ok(Value::Object(self.track(Object::from(method))));
+            }
+            _ => {
+                return Err(self.runtime_error(
+                    codes::NOT_A_MODULE,
+                    span,
+                    format!(
+                        "Only modules and userdata have properties, but got a {}",
+                        object.typename()
+                    ),
+                ))
+            }
+        };
+        match module.get(Borrow::<str>::borrow(&name)) {
+            Some(slot) => self.get_global(slot),
+            None => Err(self.runtime_error(
+                codes::MISSING_EXPORT,
+                span,
+                format!("Module {} has no export named {name}", module.name),
+            )),
+        }
+    }
+
+    /// Builds a module that reads its exports from globals, and remembers it
+    fn define_module(&mut self, index: u8) -> InterpretResult {
+        let module = ObjModule::new(&self.chunk.modules[index as usize]);
+        if let Err(err) = self.reserve(Object::HEADER_SIZE + module.size()) {
+            unsafe { module.free() };
+            return Err(err);
+        }
+        let module = self.track(Object::from(module));
+        self.modules[index as usize] = Some(module);
+        self.push(Value::from(module));
+        Ok(())
+    }
+
     fn jump(&mut self, offset: isize) {
         self.ip = (self.ip as isize + offset) as usize;
     }
@@ -277,7 +534,7 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
     #[cfg(feature = "verbose_vm")]
     fn show_debug_trace(&self) {
         self.chunk
-            .disassemble_instruction(self.ip_offset(), self.source, std::io::stdout());
+            .disassemble_instruction(self.ip_offset(), self.sources, std::io::stdout());
         eprintln!("==== STACK ====");
         for value in unsafe { self.stack.slice() } {
             eprintln!("{value}");
@@ -310,61 +567,149 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
         unsafe {
             // safety: this is, surprisingly, always sound because of
             // 1. the upper bound on the number of variables
-            // 2. limitations on functions overflowing the stack (note: not actually applicable yet)
+            // 2. function calls checking there's enough headroom for another frame
             // 3. the general inability to otherwise put a user-defined number of things on the stack
             self.stack.push(value);
         }
     }
 
-    fn function_call(&mut self, closure: ObjClosure, arg_count: u8) -> InterpretResult {
-        let function = closure.function;
-        if function.arity != arg_count {
+    fn check_arity(&mut self, function: ObjFunction, arg_count: u8) -> InterpretResult {
+        if !function.accepts(arg_count) {
             return Err(self.runtime_error(
+                codes::ARITY_MISMATCH,
                 self.get_span(-2..0),
                 format!(
                     "Function {} expects {} arguments, but got {}",
-                    function.name, function.arity, arg_count
+                    function.name,
+                    function.arity(),
+                    arg_count
                 ),
             ));
         }
+        Ok(())
+    }
+
+    fn function_call(&mut self, closure: ObjClosure, arg_count: u8) -> InterpretResult {
+        let function = closure.function;
+        self.check_arity(function, arg_count)?;
         self.callframe.push(CallFrame {
             // -1 to include function itself
             base_pointer: self.stack.len() - arg_count as usize - 1,
             return_addr: self.ip,
             closure,
         });
-        // an arbitrary number required so we don't UB with stack overflows
-        if self.callframe.len() > 512 {
-            return Err(self.runtime_error(
-                self.get_span(-2..0),
-                format!("Overflowed the stack calling {}", function.name),
-            ));
+        if self.callframe.len() > self.config.max_frames {
+            return Err(self.stack_overflow(function));
+        }
+        // required so we don't UB with stack overflows
+        if !self.stack.has_headroom() {
+            if !self.config.growable_stack {
+                return Err(self.stack_overflow(function));
+            }
+            self.grow_stack();
+        }
+        self.ip = function.entry(arg_count);
+        Ok(())
+    }
+
+    fn stack_overflow(&mut self, function: ObjFunction) -> InterpretError {
+        self.runtime_error(
+            codes::STACK_OVERFLOW,
+            self.get_span(-2..0),
+            format!("Overflowed the stack calling {}", function.name),
+        )
+    }
+
+    fn grow_stack(&mut self) {
+        // only the address is kept, since the old stack is freed by growing
+        let old_base = unsafe { self.stack.get_ptr(0) } as usize;
+        self.stack.grow(self.stack.capacity() * 2);
+        let new_base = unsafe { self.stack.get_ptr(0) };
+        // open upvalues are the only pointers into the stack that outlive an instruction
+        let mut it = self.open_upvalues;
+        while let Some(upvalue) = it {
+            let index = (upvalue.value.as_ptr() as usize - old_base) / size_of::<Value>();
+            unsafe {
+                (*upvalue.as_ptr()).value = ValidPtr::from_ptr(new_base.add(index));
+            }
+            it = upvalue.next_open;
         }
-        self.ip = function.addr;
+    }
+
+    /// Reuses the current callframe, so the callee returns directly to our caller
+    fn tail_function_call(&mut self, closure: ObjClosure, arg_count: u8) -> InterpretResult {
+        let function = closure.function;
+        self.check_arity(function, arg_count)?;
+        // codegen only emits tail calls inside of functions
+        let base_pointer = self.base_pointer();
+        // the current frame's locals are about to be overwritten
+        self.close_upvalues(base_pointer);
+        unsafe {
+            // +1 to include the function itself
+            self.stack.collapse(base_pointer, arg_count as usize + 1);
+        }
+        if let Some(frame) = self.callframe.last_mut() {
+            frame.closure = closure;
+        }
+        self.ip = function.entry(arg_count);
         Ok(())
     }
 
-    fn native_function_call(&mut self, function: NativeFunction, arg_count: u8) -> InterpretResult {
-        match function.call(unsafe { &self.stack.slice()[self.stack.len() - arg_count as usize..] })
-        {
+    /// Calls a native with the arguments on top of the stack, replacing them and the callee
+    /// A method's receiver takes the callee's place and is passed as the first argument, which
+    /// errors leave out when counting arguments
+    fn native_function_call(
+        &mut self,
+        name: &str,
+        function: NativeFn,
+        arg_count: u8,
+        receiver: bool,
+    ) -> InterpretResult {
+        let args = unsafe {
+            // SAFETY: natives can only reach the VM through Heap, which never modifies the stack
+            let args =
+                &self.stack.slice()[self.stack.len() - arg_count as usize - receiver as usize..];
+            slice::from_raw_parts(args.as_ptr(), args.len())
+        };
+        let res = function(self, args);
+        self.native_roots.clear();
+        match res {
             Ok(value) => {
+                // the arguments and the function itself
+                unsafe {
+                    for _ in 0..=arg_count {
+                        self.pop();
+                    }
+                }
                 self.push(value);
                 Ok(())
             }
             Err(CallError::ArityMismatch(arity)) => {
                 let span = self.get_span(-2..0);
                 Err(self.runtime_error(
+                    codes::ARITY_MISMATCH,
                     span,
                     format!(
                         "Function {} expects {} arguments, but got {}",
-                        function.name, arity, arg_count
+                        name,
+                        arity.saturating_sub(receiver.into()),
+                        arg_count
                     ),
                 ))
             }
             Err(CallError::TypeMismatch(index, expected)) => {
                 let span = self.get_span(-2..0);
-                Err(self.runtime_error(span, format!("Argument {} expected {}", index, expected)))
+                Err(self.runtime_error(
+                    codes::ARGUMENT_TYPE,
+                    span,
+                    format!(
+                        "Argument {} expected {}",
+                        index.saturating_sub(receiver.into()),
+                        expected
+                    ),
+                ))
             }
+            Err(CallError::Reported(err)) => Err(err),
         }
     }
 
@@ -372,10 +717,20 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
         let value = self.peek(arg_count.into());
         match value.try_as() {
             Some(ObjectKind::Closure { fun }) => self.function_call(fun, arg_count),
-            Some(ObjectKind::NativeFunction { fun }) => self.native_function_call(fun, arg_count),
+            Some(ObjectKind::NativeFunction { fun }) => {
+                self.native_function_call(fun.name.borrow(), fun.function, arg_count, false)
+            }
+            Some(ObjectKind::BoundMethod { method }) => {
+                *self
+                    .stack
+                    .get_ptr(self.stack.len() - arg_count as usize - 1) =
+                    Value::Object(method.receiver);
+                self.native_function_call(method.name, method.function, arg_count, true)
+            }
             _ => {
                 let span = self.get_span(-2..0);
                 self.runtime_error(
+                    codes::NOT_CALLABLE,
                     span,
                     format!("Canot call a value of type {}", value.typename()),
                 );
@@ -384,6 +739,15 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
         }
     }
 
+    /// A tail call to anything but a closure behaves like a regular call followed by a return
+    unsafe fn tail_call(&mut self, arg_count: u8) -> InterpretResult {
+        let value = self.peek(arg_count.into());
+        match value.try_as() {
+            Some(ObjectKind::Closure { fun }) => self.tail_function_call(fun, arg_count),
+            _ => self.call(arg_count),
+        }
+    }
+
     fn capture_upvalue(&mut self, value: ValidPtr<Value>) -> ValidPtr<Upvalue> {
         let mut prev = None;
         let mut current = self.open_upvalues;
@@ -401,7 +765,11 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
             }
         }
 
-        let new_upvalue = ValidPtr::new(Upvalue::new(value, current));
+        let mut new_upvalue = Upvalue::new(value, current);
+        new_upvalue.marked = self.new_upvalue_marked();
+        let new_upvalue = ValidPtr::new(new_upvalue);
+        // this is reserved ahead of time by the closure capturing it
+        self.bytes_allocated += size_of::<Upvalue>();
         if let Some(prev) = prev {
             unsafe {
                 (*prev.as_ptr()).next_open = Some(new_upvalue);
@@ -423,98 +791,88 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
             }
             debug_assert!(upvalue.value.as_ptr() == value.as_ptr());
             Upvalue::close(upvalue);
-            self.upvalue_storage.push(upvalue);
+            self.track_closed_upvalue(upvalue);
             self.open_upvalues = upvalue.next_open;
         }
     }
 
-    fn mark_stack(&mut self) {
-        let stack = unsafe {
-            // SAFETY: values on the stack won't be directly modified, except potentially through their interior pointers
-            self.stack.slice()
-        };
-        for value in stack {
-            value.mark();
+    /// Closes every open upvalue pointing at or above this stack index
+    fn close_upvalues(&mut self, index: usize) {
+        let last = unsafe { self.stack.get_ptr(index) };
+        while let Some(upvalue) = self.open_upvalues {
+            if upvalue.value.as_ptr() < last {
+                break;
+            }
+            Upvalue::close(upvalue);
+            self.track_closed_upvalue(upvalue);
+            self.open_upvalues = upvalue.next_open;
         }
     }
 
-    fn mark_globals(&mut self) {
-        for global in self.globals.iter().flatten() {
-            global.mark();
+    fn stats(&self) -> Stats {
+        Stats {
+            instructions: self.instructions,
+            objects: self.objects.len(),
+            bytes_allocated: self.bytes_allocated,
+            peak_bytes_allocated: self.peak_bytes_allocated,
+            ..self.gc_stats.clone()
         }
     }
 
-    fn mark_everything(&mut self) {
-        self.mark_stack();
-        self.mark_globals();
-
-        for frame in self.callframe.iter() {
-            frame.closure.mark();
-        }
-
-        let mut it = self.open_upvalues;
-        while let Some(upvalue) = it {
-            // same issue as closures
+    /// Jumps to the innermost handler with whatever was thrown, or reports it if there isn't one
+    fn unwind(&mut self) -> InterpretResult {
+        let exception = self
+            .exception
+            .take()
+            .expect("Runtime errors set the exception");
+        let Some(handler) = self.handlers.pop() else {
+            let (code, span, message, help) = match exception {
+                Exception::Error {
+                    code,
+                    span,
+                    message,
+                    help,
+                } => (code, span, message, help),
+                // rethrowing a caught error reports it like it was never caught
+                Exception::Value { value, span } => match value.try_as() {
+                    Some(ObjectKind::Error { error }) => {
+                        (error.code, error.span, error.message.to_string(), None)
+                    }
+                    _ => (
+                        codes::UNCAUGHT_EXCEPTION,
+                        span,
+                        format!("Uncaught exception: {value}"),
+                        None,
+                    ),
+                },
+            };
+            self.report_error(code, span, message, None, help);
+            return Err(InterpretError::RuntimeError);
+        };
+        self.callframe.truncate(handler.frames);
+        self.close_upvalues(handler.stack_len);
+        while self.stack.len() > handler.stack_len {
             unsafe {
-                (*upvalue.as_ptr()).marked = true;
+                self.pop();
             }
-            it = upvalue.next_open;
         }
-    }
-
-    fn sweep(&mut self) {
-        self.objects.retain(|obj| {
-            if obj.inner.marked {
-                unsafe {
-                    (*obj.inner.as_ptr()).marked = false;
-                }
-                true
-            } else {
-                unsafe {
-                    obj.free();
-                }
-                false
+        let value = match exception {
+            Exception::Error {
+                code,
+                span,
+                message,
+                ..
+            } => {
+                // nothing needs to be kept alive, since the stack has already been unwound
+                self.reserve(Object::HEADER_SIZE + message.len())?;
+                let error = self.track(Object::from(ObjError::new(code, message, span)));
+                Value::from(error)
             }
-        });
-        self.upvalue_storage.retain(|upval| {
-            if upval.marked {
-                unsafe {
-                    (*upval.as_ptr()).marked = false;
-                }
-                true
-            } else {
-                unsafe {
-                    // upvalues don't actually store anything that needs to be freed
-                    ValidPtr::free(*upval);
-                }
-                false
-            }
-        })
-    }
-
-    fn allocations(&self) -> usize {
-        self.objects.len() + self.upvalue_storage.len()
-    }
-
-    fn collect_garbage(&mut self) {
-        #[cfg(feature = "verbose_gc")]
-        {
-            eprintln!(">> GC begin");
-        }
-        let init = self.allocations();
-        #[cfg(not(feature = "stress_gc"))]
-        if init < self.next_gc {
-            return;
-        }
-        self.mark_everything();
-        self.sweep();
-        let new = self.allocations();
-        let gc_heap_grow_factor = 2;
-        self.next_gc = new * gc_heap_grow_factor;
-        #[cfg(feature = "verbose_gc")]
-        {
-            eprintln!("<< GC end {} -> {}", init, new);
-        }
+            Exception::Value { value, .. } => value,
+        };
+        self.push(value);
+        self.ip = handler.catch_addr;
+        Ok(())
     }
 
     unsafe fn run(&mut self) -> InterpretResult {
@@ -524,23 +882,23 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
 
         for i in 0..self.chunk.native_globals.len() {
             let (id, value) = self.chunk.native_globals[i];
-            self.define_global(id, value);
+            self.define_global(id, value, false);
         }
 
-        #[cfg(fuzzing)]
-        let mut iterations = 0;
+        self.deadline = self.config.timeout.map(|timeout| Instant::now() + timeout);
 
         loop {
-            #[cfg(fuzzing)]
-            {
-                iterations += 1;
-                // overly repetitive cases aren't super interesting
-                // very likely to just be simple loops, e.g. while true {}
-                // and a bigger number makes fuzzing take much longer than necessary
-                if iterations == 80_000 {
-                    return Ok(());
-                }
+            match self.execute() {
+                Err(InterpretError::RuntimeError) => self.unwind()?,
+                res => return res,
             }
+        }
+    }
+
+    /// Runs until the script finishes or something is thrown
+    unsafe fn execute(&mut self) -> InterpretResult {
+        loop {
+            self.check_limits()?;
             #[cfg(feature = "verbose_vm")]
             {
                 self.show_debug_trace();
@@ -564,6 +922,10 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
                 }
                 OpCode::Closure => {
                     let function: ObjFunction = self.read_constant().unwrap_as();
+                    // at most every upvalue needs to be newly captured
+                    let upvalues_size = function.upvalues as usize
+                        * (size_of::<ValidPtr<Upvalue>>() + size_of::<Upvalue>());
+                    self.reserve(Object::HEADER_SIZE + upvalues_size)?;
                     let mut upvalues = vec![];
                     for _ in 0..function.upvalues {
                         let local = self.next_byte() != 0;
@@ -579,10 +941,22 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
                         }
                     }
                     let upvalues = ValidPtr::from(upvalues.into_boxed_slice());
-                    let closure = Object::from(ObjClosure { function, upvalues });
-                    self.objects.push(closure);
+                    let closure = self.track(Object::from(ObjClosure { function, upvalues }));
                     self.push(Value::from(closure));
                 }
+                OpCode::CollectRest => {
+                    let parameters = self.next_byte();
+                    let start = base_pointer + 1 + parameters as usize;
+                    let count = self.stack.len() - start;
+                    // the extra arguments stay on the stack until they're in the list
+                    self.reserve(Object::HEADER_SIZE + count * size_of::<Value>())?;
+                    let items = self.stack.slice()[start..].to_vec();
+                    for _ in 0..count {
+                        self.pop();
+                    }
+                    let list = self.track(Object::from(ObjList::new(items)));
+                    self.push(Value::from(list));
+                }
                 OpCode::CloseUpvalue => {
                     self.close_top_upvalue();
                     self.pop();
@@ -591,9 +965,46 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
                     let arg_count = self.next_byte();
                     self.call(arg_count)?;
                 }
+                OpCode::TailCall => {
+                    let arg_count = self.next_byte();
+                    self.tail_call(arg_count)?;
+                }
                 OpCode::Pop => {
                     self.pop();
                 }
+                OpCode::GetModule => {
+                    let index = self.next_byte();
+                    let module = self.modules[index as usize];
+                    self.push(module.map_or(Value::Nil, Value::from));
+                }
+                OpCode::Module => {
+                    let index = self.next_byte();
+                    self.define_module(index)?;
+                }
+                OpCode::GetProperty => {
+                    let name: UnsafeString = self.read_constant().unwrap_as();
+                    let object = self.peek(0);
+                    let value = self.get_property(object, name)?;
+                    self.pop();
+                    self.push(value);
+                }
+                OpCode::PushHandler => {
+                    let offset = self.read::<u16>();
+                    self.handlers.push(Handler {
+                        frames: self.callframe.len(),
+                        stack_len: self.stack.len(),
+                        catch_addr: self.ip + offset as usize,
+                    });
+                }
+                OpCode::PopHandler => {
+                    self.handlers.pop();
+                }
+                OpCode::Throw => {
+                    let value = self.pop();
+                    let span = self.get_span(-1..0);
+                    self.exception = Some(Exception::Value { span, value });
+                    return Err(InterpretError::RuntimeError);
+                }
                 OpCode::JumpRelIfFalse => {
                     let offset = self.read::<u16>();
                     if self.peek(0).falsey() {
@@ -606,6 +1017,12 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
                         self.jump(offset as isize);
                     }
                 }
+                OpCode::JumpRelIfNotNil => {
+                    let offset = self.read::<u16>();
+                    if self.peek(0) != Value::Nil {
+                        self.jump(offset as isize);
+                    }
+                }
                 OpCode::JumpRel => {
                     let offset = self.read::<u16>();
                     self.jump(offset as isize);
@@ -617,7 +1034,13 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
                 OpCode::DefineGlobal => {
                     let index = self.next_byte();
                     let value = self.peek(0);
-                    self.define_global(index, value);
+                    self.define_global(index, value, false);
+                    self.pop();
+                }
+                OpCode::DefineConstGlobal => {
+                    let index = self.next_byte();
+                    let value = self.peek(0);
+                    self.define_global(index, value, true);
                     self.pop();
                 }
                 OpCode::GetGlobal => {
@@ -647,8 +1070,9 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
                 OpCode::SetUpvalue => {
                     let slot = self.next_byte();
                     let closure = self.callframe.last().unwrap_unchecked().closure;
-                    let upval = (*closure.upvalues.as_ptr()).get_unchecked(slot as usize);
+                    let upval = (&(*closure.upvalues.as_ptr())).get_unchecked(slot as usize);
                     (*upval.value.as_ptr()) = self.peek(0);
+                    self.upvalue_write_barrier(self.peek(0));
                 }
                 OpCode::Constant => {
                     let constant = self.read_constant();
@@ -680,7 +1104,7 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
                     self.push(Value::Bool(a == b));
                 }
                 OpCode::Invalid => {
-                    // Invalid is only reachable at a specific value (30), but any other values would be UB anyways because of the transmute
+                    // Invalid is only reachable at a specific value (the last one), but any other values would be UB anyways because of the transmute
                     unreachable_unchecked();
                 }
             }
@@ -688,16 +1112,98 @@ impl<'src, Stderr: Write, Stdout: Write> VM<'src, Stderr, Stdout> {
     }
 }
 
-pub fn interpret(source: &str, mut stderr: impl Write, mut stdout: impl Write) -> InterpretResult {
-    let Some(chunk) = compile(source, &mut stderr) else {
-        return Err(InterpretError::CompileError);
+pub fn interpret(source: &str, stderr: impl Write, stdout: impl Write) -> InterpretResult {
+    interpret_with(source, VMConfig::default(), stderr, stdout)
+}
+
+pub fn interpret_with(
+    source: &str,
+    config: VMConfig,
+    stderr: impl Write,
+    stdout: impl Write,
+) -> InterpretResult {
+    interpret_with_stats(source, config, stderr, stdout).0
+}
+
+/// Everything that interpreting a script produced, besides what it printed
+pub struct Outcome {
+    pub result: InterpretResult,
+    /// Compile errors or warnings come first, then the error that stopped the script if any
+    pub diagnostics: Vec<Diagnostic>,
+    /// Every file that was loaded, which is what the diagnostics' spans point into
+    pub sources: SourceMap,
+    pub stats: Stats,
+}
+
+fn compile_script(source: &str, config: &VMConfig) -> (SourceMap, Option<Chunk>, Vec<Diagnostic>) {
+    let path = config.script_path.as_deref();
+    let name = match path {
+        Some(path) => path.display().to_string(),
+        None => SCRIPT_NAME.to_string(),
     };
-    let mut vm = VM::new(chunk, source, &mut stderr, &mut stdout);
-    unsafe {
+    let mut sources = SourceMap::default();
+    let main = sources.add(name, source);
+    let (chunk, diagnostics) = compile(
+        &mut sources,
+        main,
+        path,
+        &config.natives,
+        config.deny_warnings,
+    );
+    (sources, chunk, diagnostics)
+}
+
+fn execute(
+    chunk: Chunk,
+    config: VMConfig,
+    sources: &SourceMap,
+    stdout: impl Write,
+) -> (InterpretResult, Stats, Vec<Diagnostic>) {
+    let mut vm = VM::new(chunk, config, sources, stdout);
+    let res = unsafe {
         // this depends on:
         // 1. there not being any bugs, which is obviously not going to happen... right?
         // 2. the codegen being correct
         // 3. all the other code being correct ;)
         vm.run()
+    };
+    (res, vm.stats(), std::mem::take(&mut vm.diagnostics))
+}
+
+/// Renders diagnostics to stderr as they come up, with compile warnings before the script runs
+pub fn interpret_with_stats(
+    source: &str,
+    config: VMConfig,
+    mut stderr: impl Write,
+    stdout: impl Write,
+) -> (InterpretResult, Stats) {
+    let (sources, chunk, diagnostics) = compile_script(source, &config);
+    render_all(&diagnostics, &sources, &mut stderr);
+    let Some(chunk) = chunk else {
+        return (Err(InterpretError::CompileError), Stats::default());
+    };
+    let (res, stats, diagnostics) = execute(chunk, config, &sources, stdout);
+    render_all(&diagnostics, &sources, &mut stderr);
+    (res, stats)
+}
+
+/// Leaves showing diagnostics up to the caller, who can render them or use them as data
+pub fn interpret_outcome(source: &str, config: VMConfig, stdout: impl Write) -> Outcome {
+    let (sources, chunk, mut diagnostics) = compile_script(source, &config);
+    let Some(chunk) = chunk else {
+        return Outcome {
+            result: Err(InterpretError::CompileError),
+            diagnostics,
+            sources,
+            stats: Stats::default(),
+        };
+    };
+    let (result, stats, runtime) = execute(chunk, config, &sources, stdout);
+    diagnostics.extend(runtime);
+    Outcome {
+        result,
+        diagnostics,
+        sources,
+        stats,
     }
 }
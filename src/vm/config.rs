@@ -0,0 +1,140 @@
+use std::{path::PathBuf, time::Duration};
+
+use crate::value::native_function::NativeFn;
+
+/// Limits for a single run of the VM
+#[derive(Clone, Debug, PartialEq)]
+pub struct VMConfig {
+    /// The maximum number of nested function calls
+    pub max_frames: usize,
+    /// The number of values the stack can initially hold
+    pub stack_size: usize,
+    /// Whether the stack may reallocate instead of overflowing
+    pub growable_stack: bool,
+    /// The maximum number of instructions to execute
+    pub fuel: Option<u64>,
+    /// The maximum wall-clock time to run for
+    pub timeout: Option<Duration>,
+    /// The maximum number of bytes the garbage collector may own at once
+    pub max_memory: Option<usize>,
+    /// How much the heap may grow after a collection before the next one
+    pub gc_growth_factor: f64,
+    /// The number of bytes the heap may grow to before collections start
+    pub gc_min_heap: usize,
+    /// Whether compile-time warnings stop the program from running
+    pub deny_warnings: bool,
+    /// Where the script was read from, which its imports are relative to
+    pub script_path: Option<PathBuf>,
+    /// Globals the host defines alongside the built in natives, replacing any with the same name
+    pub natives: Vec<(&'static str, NativeFn)>,
+}
+
+impl Default for VMConfig {
+    fn default() -> Self {
+        Self {
+            max_frames: 512,
+            stack_size: 4096,
+            growable_stack: false,
+            // overly repetitive cases aren't super interesting
+            // very likely to just be simple loops, e.g. while true {}
+            // and a bigger number makes fuzzing take much longer than necessary
+            #[cfg(fuzzing)]
+            fuel: Some(80_000),
+            #[cfg(not(fuzzing))]
+            fuel: None,
+            timeout: None,
+            max_memory: None,
+            gc_growth_factor: 2.0,
+            gc_min_heap: 1024 * 1024,
+            deny_warnings: false,
+            script_path: None,
+            natives: vec![],
+        }
+    }
+}
+
+impl VMConfig {
+    pub fn max_frames(mut self, max_frames: usize) -> Self {
+        self.max_frames = max_frames;
+        self
+    }
+
+    pub fn stack_size(mut self, stack_size: usize) -> Self {
+        self.stack_size = stack_size;
+        self
+    }
+
+    pub fn growable_stack(mut self, growable_stack: bool) -> Self {
+        self.growable_stack = growable_stack;
+        self
+    }
+
+    pub fn fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn max_memory(mut self, max_memory: usize) -> Self {
+        self.max_memory = Some(max_memory);
+        self
+    }
+
+    /// Panics if the factor isn't valid_gc_growth_factor
+    pub fn gc_growth_factor(mut self, gc_growth_factor: f64) -> Self {
+        assert!(
+            Self::valid_gc_growth_factor(gc_growth_factor),
+            "The GC growth factor must be a finite number of at least 1, but got {gc_growth_factor}"
+        );
+        self.gc_growth_factor = gc_growth_factor;
+        self
+    }
+
+    /// Whether the heap can grow by this much between collections
+    /// Anything less than 1 shrinks the threshold below what's still alive, and an infinite one
+    /// would mean never collecting again
+    pub fn valid_gc_growth_factor(gc_growth_factor: f64) -> bool {
+        gc_growth_factor.is_finite() && gc_growth_factor >= 1.0
+    }
+
+    pub fn gc_min_heap(mut self, gc_min_heap: usize) -> Self {
+        self.gc_min_heap = gc_min_heap;
+        self
+    }
+
+    pub fn deny_warnings(mut self, deny_warnings: bool) -> Self {
+        self.deny_warnings = deny_warnings;
+        self
+    }
+
+    pub fn script_path(mut self, script_path: impl Into<PathBuf>) -> Self {
+        self.script_path = Some(script_path.into());
+        self
+    }
+
+    pub fn native(mut self, name: &'static str, function: NativeFn) -> Self {
+        self.natives.push((name, function));
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::VMConfig;
+
+    #[test]
+    #[should_panic(expected = "at least 1")]
+    fn gc_growth_factor_below_one() {
+        VMConfig::default().gc_growth_factor(0.5);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least 1")]
+    fn gc_growth_factor_nan() {
+        VMConfig::default().gc_growth_factor(f64::NAN);
+    }
+}
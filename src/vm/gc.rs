@@ -0,0 +1,572 @@
+//! An incremental, tri-color mark and sweep garbage collector
+//!
+//! White objects are unmarked, gray objects are marked and waiting on the gray stack to be traced,
+//! and black objects are marked and traced. Marking and sweeping are both split into small steps
+//! interleaved with execution, so a large heap doesn't mean a long pause.
+//!
+//! The invariant keeping this sound is that a black object never points to a white one.
+//! Roots aren't objects, so they're rescanned all at once before sweeping instead of needing barriers.
+//! The write barriers are:
+//! - Setting a closed upvalue shades the new value
+//! - Closing an upvalue shades it, since it was untraced while it pointed into the stack
+//! - New objects are allocated gray while marking, and marked while sweeping so they survive
+
+use std::{collections::HashSet, io::Write, mem::size_of, time::Instant};
+
+use crate::common::codes;
+use crate::value::{
+    native_function::{CallError, Heap},
+    object::{Object, ObjectInner, ObjectKind},
+    valid::ValidPtr,
+    Value,
+};
+
+use super::{upvalue::Upvalue, InterpretError, InterpretResult, VM};
+
+/// How many instructions run between each step of a collection
+#[cfg(not(feature = "stress_gc"))]
+const STEP_INTERVAL: u64 = 128;
+/// How many objects are traced or swept in each step, which must outpace allocation
+#[cfg(not(feature = "stress_gc"))]
+const STEP_WORK: usize = 256;
+/// How many bytes may be allocated before the next step is taken early, so big allocations are paid for
+#[cfg(not(feature = "stress_gc"))]
+const STEP_BYTES: usize = 4096;
+// tiny steps on every instruction interleave collection with as much execution as possible
+#[cfg(feature = "stress_gc")]
+const STEP_INTERVAL: u64 = 1;
+#[cfg(feature = "stress_gc")]
+const STEP_WORK: usize = 4;
+#[cfg(feature = "stress_gc")]
+const STEP_BYTES: usize = 0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum GcPhase {
+    Idle,
+    Marking,
+    /// Everything before these indices in objects and upvalue_storage has been swept
+    Sweeping {
+        objects: usize,
+        upvalues: usize,
+    },
+}
+
+/// Host code that runs after an object is collected, e.g. to release what it was wrapping
+pub type Finalizer = Box<dyn FnOnce()>;
+
+#[derive(Copy, Clone, Debug)]
+pub enum Gray {
+    Object(Object),
+    Upvalue(ValidPtr<Upvalue>),
+}
+
+/// Anything the garbage collector can find more allocations through
+pub trait Trace {
+    /// Shades everything this directly refers to
+    fn trace(&self, gray: &mut GrayStack);
+}
+
+impl Trace for Gray {
+    fn trace(&self, gray: &mut GrayStack) {
+        match self {
+            Gray::Object(obj) => obj.kind().trace(gray),
+            Gray::Upvalue(upvalue) => upvalue.trace(gray),
+        }
+    }
+}
+
+/// Objects and upvalues that are marked but haven't been traced yet
+/// Tracing through this instead of recursing means deep object graphs can't overflow the native stack
+#[derive(Debug, Default)]
+pub struct GrayStack {
+    stack: Vec<Gray>,
+}
+
+impl GrayStack {
+    pub fn shade_value(&mut self, value: Value) {
+        if let Value::Object(obj) = value {
+            self.shade_object(obj);
+        }
+    }
+
+    pub fn shade_object(&mut self, obj: Object) {
+        if obj.inner.marked {
+            return;
+        }
+        unsafe {
+            (*obj.inner.as_ptr()).marked = true;
+        }
+        self.stack.push(Gray::Object(obj));
+    }
+
+    pub fn shade_upvalue(&mut self, upvalue: ValidPtr<Upvalue>) {
+        if upvalue.marked {
+            return;
+        }
+        unsafe {
+            (*upvalue.as_ptr()).marked = true;
+        }
+        self.stack.push(Gray::Upvalue(upvalue));
+    }
+
+    /// Traces up to `work` gray objects, returning whether there's nothing left to trace
+    fn trace_some(&mut self, work: usize) -> bool {
+        for _ in 0..work {
+            let Some(gray) = self.stack.pop() else {
+                return true;
+            };
+            gray.trace(self);
+        }
+        self.stack.is_empty()
+    }
+}
+
+impl<'src, Stdout: Write> VM<'src, Stdout> {
+    fn mark_roots(&mut self) {
+        let stack = unsafe {
+            // SAFETY: values on the stack won't be directly modified, except potentially through their interior pointers
+            self.stack.slice()
+        };
+        for value in stack {
+            self.gray.shade_value(*value);
+        }
+
+        for global in self.globals.iter().flatten() {
+            self.gray.shade_value(*global);
+        }
+
+        for frame in self.callframe.iter() {
+            frame.closure.trace(&mut self.gray);
+        }
+
+        for obj in self.native_roots.iter() {
+            self.gray.shade_object(*obj);
+        }
+
+        for module in self.modules.iter().flatten() {
+            self.gray.shade_object(*module);
+        }
+
+        let mut it = self.open_upvalues;
+        while let Some(upvalue) = it {
+            // these are traced through the stack while they're open
+            unsafe {
+                (*upvalue.as_ptr()).marked = true;
+            }
+            it = upvalue.next_open;
+        }
+    }
+
+    fn finish_marking(&mut self) {
+        // roots have no barriers, so they may point to anything by now
+        self.mark_roots();
+        self.gray.trace_some(usize::MAX);
+        self.clear_weak_refs();
+        self.queue_finalizers();
+        self.gc_phase = GcPhase::Sweeping {
+            objects: 0,
+            upvalues: 0,
+        };
+    }
+
+    /// Which of these unmarked objects the heap owns, and so is about to sweep
+    ///
+    /// Constants belong to the chunk, so they're never marked, but they're never swept either.
+    fn owned(&self, unmarked: impl Iterator<Item = Object>) -> HashSet<*mut ObjectInner> {
+        let unmarked: HashSet<_> = unmarked.map(|obj| obj.inner.as_ptr()).collect();
+        if unmarked.is_empty() {
+            return unmarked;
+        }
+        self.objects
+            .iter()
+            .map(|obj| obj.inner.as_ptr())
+            .filter(|ptr| unmarked.contains(ptr))
+            .collect()
+    }
+
+    /// Clears weak references to anything about to be swept
+    fn clear_weak_refs(&mut self) {
+        // the references themselves may be about to be swept
+        self.weak_refs.retain(|obj| obj.inner.marked);
+        let weak = |obj: &Object| {
+            let ObjectKind::WeakRef { weak } = obj.kind() else {
+                unreachable!("Only weak references are kept in weak_refs");
+            };
+            weak
+        };
+        let targets = self.weak_refs.iter().filter_map(|obj| weak(obj).get());
+        let dead = self.owned(targets.filter(|target| !target.inner.marked));
+        self.weak_refs.retain(|obj| {
+            let weak = weak(obj);
+            match weak.get() {
+                Some(target) if target.inner.marked => true,
+                Some(target) if dead.contains(&target.inner.as_ptr()) => {
+                    weak.clear();
+                    false
+                }
+                // a constant, which outlives every reference to it
+                _ => false,
+            }
+        });
+    }
+
+    fn queue_finalizers(&mut self) {
+        let unmarked = self.finalizers.iter().map(|(obj, _)| *obj);
+        let dead = self.owned(unmarked.filter(|obj| !obj.inner.marked));
+        let (dead, alive) = std::mem::take(&mut self.finalizers)
+            .into_iter()
+            .partition(|(obj, _)| dead.contains(&obj.inner.as_ptr()));
+        self.finalizers = alive;
+        let dead: Vec<_> = dead;
+        self.pending_finalizers
+            .extend(dead.into_iter().map(|(_, finalizer)| finalizer));
+    }
+
+    fn run_finalizers(&mut self) {
+        for finalizer in std::mem::take(&mut self.pending_finalizers) {
+            finalizer();
+        }
+    }
+
+    /// Returns whether everything has been swept
+    fn sweep_step(&mut self, mut work: usize) -> bool {
+        let GcPhase::Sweeping {
+            mut objects,
+            mut upvalues,
+        } = self.gc_phase
+        else {
+            unreachable!("Only called while sweeping");
+        };
+
+        while work > 0 && objects < self.objects.len() {
+            work -= 1;
+            let obj = self.objects[objects];
+            if obj.inner.marked {
+                unsafe {
+                    (*obj.inner.as_ptr()).marked = false;
+                }
+                objects += 1;
+            } else {
+                self.bytes_allocated -= obj.size();
+                self.gc_stats.bytes_reclaimed += obj.size();
+                self.gc_stats.objects_freed += 1;
+                // the swapped in object hasn't been swept yet either
+                self.objects.swap_remove(objects);
+                unsafe {
+                    obj.free();
+                }
+            }
+        }
+
+        while work > 0 && upvalues < self.upvalue_storage.len() {
+            work -= 1;
+            let upvalue = self.upvalue_storage[upvalues];
+            if upvalue.marked {
+                unsafe {
+                    (*upvalue.as_ptr()).marked = false;
+                }
+                upvalues += 1;
+            } else {
+                self.bytes_allocated -= size_of::<Upvalue>();
+                self.gc_stats.bytes_reclaimed += size_of::<Upvalue>();
+                self.gc_stats.objects_freed += 1;
+                self.upvalue_storage.swap_remove(upvalues);
+                unsafe {
+                    // upvalues don't actually store anything that needs to be freed
+                    ValidPtr::free(upvalue);
+                }
+            }
+        }
+
+        self.gc_phase = GcPhase::Sweeping { objects, upvalues };
+        objects == self.objects.len() && upvalues == self.upvalue_storage.len()
+    }
+
+    /// Cycles are counted when they start, so finishing one early doesn't count it twice
+    fn start_cycle(&mut self) {
+        self.gc_phase = GcPhase::Marking;
+        self.gc_stats.collections += 1;
+        self.mark_roots();
+    }
+
+    fn finish_cycle(&mut self) {
+        self.gc_phase = GcPhase::Idle;
+        let heap = self.bytes_allocated as f64 * self.config.gc_growth_factor;
+        self.next_gc = (heap as usize).max(self.config.gc_min_heap);
+        self.run_finalizers();
+    }
+
+    fn gc_step(&mut self, work: usize) {
+        match self.gc_phase {
+            GcPhase::Idle => {}
+            GcPhase::Marking => {
+                if self.gray.trace_some(work) {
+                    self.finish_marking();
+                }
+            }
+            GcPhase::Sweeping { .. } => {
+                if self.sweep_step(work) {
+                    self.finish_cycle();
+                }
+            }
+        }
+    }
+
+    fn record_pause(&mut self, start: Instant) {
+        let pause = start.elapsed();
+        self.gc_stats.total_pause += pause;
+        self.gc_stats.longest_pause = self.gc_stats.longest_pause.max(pause);
+    }
+
+    /// Runs a step of garbage collection if one is due
+    pub(super) fn collect_garbage(&mut self) {
+        if self.gc_phase == GcPhase::Idle {
+            #[cfg(not(feature = "stress_gc"))]
+            if self.bytes_allocated < self.next_gc {
+                return;
+            }
+            let start = Instant::now();
+            self.start_cycle();
+            self.record_pause(start);
+        } else if self.instructions.is_multiple_of(STEP_INTERVAL) || self.gc_debt >= STEP_BYTES {
+            self.gc_debt = 0;
+            let start = Instant::now();
+            self.gc_step(STEP_WORK);
+            self.record_pause(start);
+        }
+    }
+
+    /// Frees everything unreachable right now, finishing any collection in progress
+    pub(super) fn collect_garbage_now(&mut self) {
+        let start = Instant::now();
+        // anything that died after a collection in progress started marking would survive it
+        while self.gc_phase != GcPhase::Idle {
+            self.gc_step(usize::MAX);
+        }
+        self.start_cycle();
+        while self.gc_phase != GcPhase::Idle {
+            self.gc_step(usize::MAX);
+        }
+        self.record_pause(start);
+    }
+
+    /// Hands ownership of a new object to the garbage collector
+    pub(super) fn track(&mut self, object: Object) -> Object {
+        self.bytes_allocated += object.size();
+        if self.gc_phase != GcPhase::Idle {
+            self.gc_debt += object.size();
+        }
+        if let ObjectKind::WeakRef { .. } = object.kind() {
+            self.weak_refs.push(object);
+        }
+        self.peak_bytes_allocated = self.peak_bytes_allocated.max(self.bytes_allocated);
+        match self.gc_phase {
+            GcPhase::Idle => {}
+            // this may still be initialized with white objects
+            GcPhase::Marking => self.gray.shade_object(object),
+            GcPhase::Sweeping { .. } => unsafe {
+                (*object.inner.as_ptr()).marked = true;
+            },
+        }
+        self.objects.push(object);
+        object
+    }
+
+    /// Makes sure `size` more bytes fit within the memory limit, collecting garbage if they don't
+    /// Everything in use must be reachable, since this may collect garbage
+    pub(super) fn reserve(&mut self, size: usize) -> InterpretResult {
+        let Some(max_memory) = self.config.max_memory else {
+            return Ok(());
+        };
+        if self.bytes_allocated + size <= max_memory {
+            return Ok(());
+        }
+        self.collect_garbage_now();
+        if self.bytes_allocated + size <= max_memory {
+            return Ok(());
+        }
+        let span = self.get_span(-1..0);
+        self.report_error(
+            codes::OUT_OF_MEMORY,
+            span,
+            format!(
+                "Out of memory: allocating {size} bytes would exceed the limit of {max_memory} bytes"
+            ),
+            Some(format!("this allocation needed {size} more bytes")),
+            None,
+        );
+        Err(InterpretError::LimitExceeded)
+    }
+
+    /// The color a newly captured upvalue starts with
+    pub(super) fn new_upvalue_marked(&self) -> bool {
+        // while open, upvalues are only marked to survive sweeping
+        self.gc_phase != GcPhase::Idle
+    }
+
+    /// Hands ownership of a newly closed upvalue to the garbage collector
+    pub(super) fn track_closed_upvalue(&mut self, upvalue: ValidPtr<Upvalue>) {
+        match self.gc_phase {
+            GcPhase::Idle => unsafe {
+                (*upvalue.as_ptr()).marked = false;
+            },
+            GcPhase::Marking => {
+                // it may be black without its value ever being traced
+                unsafe {
+                    (*upvalue.as_ptr()).marked = false;
+                }
+                self.gray.shade_upvalue(upvalue);
+            }
+            GcPhase::Sweeping { .. } => unsafe {
+                (*upvalue.as_ptr()).marked = true;
+            },
+        }
+        self.upvalue_storage.push(upvalue);
+    }
+
+    /// Must be called whenever a value is written into an upvalue
+    pub(super) fn upvalue_write_barrier(&mut self, value: Value) {
+        if self.gc_phase == GcPhase::Marking {
+            self.gray.shade_value(value);
+        }
+    }
+
+    /// Frees every allocation, reachable or not
+    pub(super) fn free_everything(&mut self) {
+        for obj in std::mem::take(&mut self.objects) {
+            unsafe {
+                obj.free();
+            }
+        }
+        for upvalue in std::mem::take(&mut self.upvalue_storage) {
+            unsafe {
+                ValidPtr::free(upvalue);
+            }
+        }
+        while let Some(upvalue) = self.open_upvalues {
+            self.open_upvalues = upvalue.next_open;
+            unsafe {
+                ValidPtr::free(upvalue);
+            }
+        }
+        self.bytes_allocated = 0;
+        self.gc_phase = GcPhase::Idle;
+        self.weak_refs.clear();
+        self.pending_finalizers.extend(
+            std::mem::take(&mut self.finalizers)
+                .into_iter()
+                .map(|(_, finalizer)| finalizer),
+        );
+        self.run_finalizers();
+    }
+}
+
+impl<'src, Stdout: Write> Heap for VM<'src, Stdout> {
+    fn alloc(&mut self, kind: ObjectKind) -> Result<Object, CallError> {
+        // the native's arguments are still on the stack, so they're safe from this
+        self.reserve(Object::HEADER_SIZE + kind.size())
+            .map_err(CallError::Reported)?;
+        let obj = self.track(Object::from(kind));
+        self.native_roots.push(obj);
+        Ok(obj)
+    }
+
+    fn add_finalizer(&mut self, object: Object, finalizer: Finalizer) {
+        self.finalizers.push((object, finalizer));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use crate::{
+        common::ui::{SourceMap, SCRIPT_NAME},
+        compiler::compile,
+        value::{
+            native_function::Heap,
+            object::ObjectKind,
+            userdata::{ObjUserData, UserDataType},
+            Value,
+        },
+        vm::{config::VMConfig, VM},
+    };
+
+    #[test]
+    fn finalizers() {
+        let mut stdout = vec![];
+        let mut sources = SourceMap::default();
+        let main = sources.add(SCRIPT_NAME, "");
+        let chunk = compile(&mut sources, main, None, &[], false).0.unwrap();
+        let mut vm = VM::new(chunk, VMConfig::default(), &sources, &mut stdout);
+        let finalized = Rc::new(Cell::new(0));
+
+        let garbage = vm
+            .alloc(ObjectKind::from("garbage".to_owned()))
+            .ok()
+            .unwrap();
+        let kept = vm.alloc(ObjectKind::from("kept".to_owned())).ok().unwrap();
+        for obj in [garbage, kept] {
+            let finalized = finalized.clone();
+            vm.add_finalizer(obj, Box::new(move || finalized.set(finalized.get() + 1)));
+        }
+        // as if the native that allocated them returned kept
+        vm.native_roots.clear();
+        vm.globals.push(Some(Value::Object(kept)));
+
+        vm.collect_garbage_now();
+        assert_eq!(finalized.get(), 1);
+        drop(vm);
+        assert_eq!(finalized.get(), 2);
+    }
+
+    #[test]
+    fn userdata_dropped_by_sweep() {
+        struct Resource(Rc<Cell<bool>>);
+        impl Drop for Resource {
+            fn drop(&mut self) {
+                self.0.set(true);
+            }
+        }
+        static RESOURCE: UserDataType = UserDataType {
+            name: "resource",
+            display: |_, f| write!(f, "<resource>"),
+            methods: &[],
+        };
+
+        let mut stdout = vec![];
+        let mut sources = SourceMap::default();
+        let main = sources.add(SCRIPT_NAME, "");
+        let chunk = compile(&mut sources, main, None, &[], false).0.unwrap();
+        let mut vm = VM::new(chunk, VMConfig::default(), &sources, &mut stdout);
+        let dropped = Rc::new(Cell::new(false));
+        let data = ObjUserData::new(&RESOURCE, Resource(dropped.clone()));
+        let before = vm.bytes_allocated;
+        vm.alloc(data.into()).ok().unwrap();
+        assert!(vm.bytes_allocated > before);
+
+        vm.collect_garbage_now();
+        assert!(!dropped.get());
+        vm.native_roots.clear();
+        vm.collect_garbage_now();
+        assert!(dropped.get());
+        assert_eq!(vm.bytes_allocated, before);
+    }
+
+    #[test]
+    fn forced_collections_count_once() {
+        let mut stdout = vec![];
+        let mut sources = SourceMap::default();
+        let main = sources.add(SCRIPT_NAME, "");
+        let chunk = compile(&mut sources, main, None, &[], false).0.unwrap();
+        let mut vm = VM::new(chunk, VMConfig::default(), &sources, &mut stdout);
+
+        vm.collect_garbage_now();
+        assert_eq!(vm.gc_stats.collections, 1);
+        // finishing the cycle in progress is part of the forced one
+        vm.start_cycle();
+        assert_eq!(vm.gc_stats.collections, 2);
+        vm.collect_garbage_now();
+        assert_eq!(vm.gc_stats.collections, 3);
+    }
+}
@@ -3,31 +3,53 @@ use std::cell::{Cell, UnsafeCell};
 
 use crate::value::Value;
 
-const MAX_SIZE: usize = 4096;
+/// The most any single callframe can push before calling another function:
+/// locals are indexed by a u8, plus as many arguments and temporaries on top
+pub const FRAME_HEADROOM: usize = 2 * (u8::MAX as usize + 1);
+
 #[derive(Debug)]
-pub struct FixedStack {
+pub struct Stack {
     // Interior mutability is needed because because we have pointers into the stack that mutate it
-    stack: [UnsafeCell<Value>; MAX_SIZE],
+    stack: Box<[UnsafeCell<Value>]>,
     len: Cell<usize>,
 }
 
-impl FixedStack {
-    pub fn new() -> Self {
-        // this is just used to initialize the array, and is not actually mutated
-        #[allow(clippy::declare_interior_mutable_const)]
-        const UNINIT: UnsafeCell<Value> = UnsafeCell::new(Value::Num(f64::MAX));
+impl Stack {
+    pub fn new(capacity: usize) -> Self {
         Self {
-            stack: [UNINIT; MAX_SIZE],
+            stack: Self::allocate(capacity),
             len: Cell::new(0),
         }
     }
 
+    fn allocate(capacity: usize) -> Box<[UnsafeCell<Value>]> {
+        (0..capacity)
+            .map(|_| UnsafeCell::new(Value::Num(f64::MAX)))
+            .collect()
+    }
+
     pub fn len(&self) -> usize {
         self.len.get()
     }
 
-    pub fn clear(&self) {
-        self.len.set(0);
+    pub fn capacity(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Whether another callframe is guaranteed to fit
+    pub fn has_headroom(&self) -> bool {
+        self.len() + FRAME_HEADROOM <= self.capacity()
+    }
+
+    /// This moves the stack, so every pointer into it must be relocated afterwards
+    pub fn grow(&mut self, capacity: usize) {
+        debug_assert!(capacity >= self.capacity());
+        let mut stack = Self::allocate(capacity);
+        let len = self.len();
+        for (new, old) in stack.iter_mut().zip(self.stack[..len].iter_mut()) {
+            *new.get_mut() = *old.get_mut();
+        }
+        self.stack = stack;
     }
 
     #[inline(always)]
@@ -49,6 +71,15 @@ impl FixedStack {
         *self.get_ptr(len - 1)
     }
 
+    /// Moves the top `count` values down to start at `index`, discarding everything in between
+    pub unsafe fn collapse(&self, index: usize, count: usize) {
+        let start = self.len.get() - count;
+        for i in 0..count {
+            *self.get_ptr(index + i) = *self.get_ptr(start + i);
+        }
+        self.len.set(index + count);
+    }
+
     #[inline(always)]
     pub unsafe fn get_ptr(&self, index: usize) -> *mut Value {
         // MaybeUninit is repr(transparent)
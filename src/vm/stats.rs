@@ -0,0 +1,39 @@
+use std::{fmt::Display, time::Duration};
+
+/// Counters describing a run of the VM, for hosts to inspect afterwards
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of instructions executed
+    pub instructions: u64,
+    /// The number of live objects when the run finished
+    pub objects: usize,
+    /// The number of bytes owned by the garbage collector when the run finished
+    pub bytes_allocated: usize,
+    /// The most bytes the garbage collector owned at once
+    pub peak_bytes_allocated: usize,
+    /// The number of garbage collections started
+    pub collections: usize,
+    /// The number of objects and upvalues freed by garbage collections
+    pub objects_freed: usize,
+    /// The number of bytes freed by garbage collections
+    pub bytes_reclaimed: usize,
+    /// The time spent collecting garbage
+    pub total_pause: Duration,
+    /// The longest single garbage collection
+    pub longest_pause: Duration,
+}
+
+impl Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "==== GC STATS ====")?;
+        writeln!(f, "{:<20} {}", "instructions", self.instructions)?;
+        writeln!(f, "{:<20} {}", "collections", self.collections)?;
+        writeln!(f, "{:<20} {}", "objects freed", self.objects_freed)?;
+        writeln!(f, "{:<20} {}", "bytes reclaimed", self.bytes_reclaimed)?;
+        writeln!(f, "{:<20} {}", "live objects", self.objects)?;
+        writeln!(f, "{:<20} {}", "live bytes", self.bytes_allocated)?;
+        writeln!(f, "{:<20} {}", "peak bytes", self.peak_bytes_allocated)?;
+        writeln!(f, "{:<20} {:?}", "total pause", self.total_pause)?;
+        write!(f, "{:<20} {:?}", "longest pause", self.longest_pause)
+    }
+}
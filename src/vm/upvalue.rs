@@ -2,6 +2,8 @@ use std::fmt::Display;
 
 use crate::value::{valid::ValidPtr, Value};
 
+use super::gc::{GrayStack, Trace};
+
 #[derive(Copy, Clone, Debug)]
 pub struct Upvalue {
     pub value: ValidPtr<Value>,
@@ -34,9 +36,12 @@ impl Upvalue {
             (*my.as_ptr()).value = ValidPtr::from_ptr(&mut (*my.as_ptr()).closed);
         }
     }
+}
 
-    pub fn mark(&self) {
-        self.closed.mark();
+impl Trace for Upvalue {
+    fn trace(&self, gray: &mut GrayStack) {
+        // open upvalues point into the stack, which is fine to trace too
+        gray.shade_value(*self.value);
     }
 }
 
@@ -0,0 +1,66 @@
+//! Runs `rlox fmt` on files in a temporary directory
+
+use assert_cmd::Command;
+use assert_fs::{prelude::*, TempDir};
+
+const MESSY: &str = "var a=1;// one\nif a<2{print   a;}\n";
+const TIDY: &str = "var a = 1; // one\nif a < 2 {\n    print a;\n}\n";
+
+fn rlox() -> Command {
+    Command::cargo_bin("rlox").unwrap()
+}
+
+#[test]
+fn formats_in_place() {
+    let dir = TempDir::new().unwrap();
+    let messy = dir.child("messy.lox");
+    messy.write_str(MESSY).unwrap();
+    let tidy = dir.child("tidy.lox");
+    tidy.write_str(TIDY).unwrap();
+    rlox()
+        .args(["fmt", "messy.lox", "tidy.lox"])
+        .current_dir(&dir)
+        .assert()
+        .success()
+        .stderr("");
+    messy.assert(TIDY);
+    tidy.assert(TIDY);
+}
+
+#[test]
+fn check_lists_unformatted_files() {
+    let dir = TempDir::new().unwrap();
+    dir.child("messy.lox").write_str(MESSY).unwrap();
+    dir.child("tidy.lox").write_str(TIDY).unwrap();
+    rlox()
+        .args(["fmt", "--check", "tidy.lox"])
+        .current_dir(&dir)
+        .assert()
+        .success();
+    rlox()
+        .args(["fmt", "--check", "messy.lox", "tidy.lox"])
+        .current_dir(&dir)
+        .assert()
+        .failure()
+        .stderr("messy.lox isn't formatted\n");
+    // checking doesn't touch the file
+    dir.child("messy.lox").assert(MESSY);
+}
+
+#[test]
+fn leaves_files_that_dont_parse() {
+    let dir = TempDir::new().unwrap();
+    let broken = dir.child("broken.lox");
+    broken.write_str("print (1;\n").unwrap();
+    let output = rlox()
+        .args(["fmt", "broken.lox"])
+        .current_dir(&dir)
+        .assert()
+        .failure()
+        .get_output()
+        .stderr
+        .clone();
+    let stderr = String::from_utf8(output).unwrap();
+    assert!(stderr.contains("broken.lox"), "{stderr}");
+    broken.assert("print (1;\n");
+}
@@ -0,0 +1,329 @@
+//! Drives `rlox lsp` through scripted JSON-RPC exchanges, like an editor would
+
+use assert_cmd::Command;
+use serde_json::{json, Value};
+
+const URI: &str = "file:///script.lox";
+
+fn frame(message: &Value) -> String {
+    let body = message.to_string();
+    format!("Content-Length: {}\r\n\r\n{body}", body.len())
+}
+
+fn unframe(mut output: &str) -> Vec<Value> {
+    let mut messages = vec![];
+    while let Some((header, rest)) = output.split_once("\r\n\r\n") {
+        let length: usize = header
+            .strip_prefix("Content-Length: ")
+            .and_then(|length| length.parse().ok())
+            .unwrap_or_else(|| panic!("Unexpected header {header}"));
+        messages.push(serde_json::from_str(&rest[..length]).unwrap());
+        output = &rest[length..];
+    }
+    assert!(output.is_empty(), "Trailing output {output}");
+    messages
+}
+
+fn request(id: u64, method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+fn open(text: &str) -> Value {
+    notification(
+        "textDocument/didOpen",
+        json!({
+            "textDocument": { "uri": URI, "languageId": "lox", "version": 1, "text": text }
+        }),
+    )
+}
+
+fn at(id: u64, method: &str, line: u64, character: u64) -> Value {
+    request(
+        id,
+        method,
+        json!({
+            "textDocument": { "uri": URI },
+            "position": { "line": line, "character": character },
+            "context": { "includeDeclaration": true },
+        }),
+    )
+}
+
+/// Runs the server through initializing, the messages and shutting down, and returns its output
+fn exchange(messages: &[Value]) -> Vec<Value> {
+    let mut script = vec![
+        request(0, "initialize", json!({ "capabilities": {} })),
+        notification("initialized", json!({})),
+    ];
+    script.extend_from_slice(messages);
+    script.push(request(u64::MAX, "shutdown", Value::Null));
+    script.push(notification("exit", Value::Null));
+    let input: String = script.iter().map(frame).collect();
+    let output = Command::cargo_bin("rlox")
+        .unwrap()
+        .arg("lsp")
+        .write_stdin(input)
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    unframe(&String::from_utf8(output).unwrap())
+}
+
+fn response(messages: &[Value], id: u64) -> &Value {
+    let response = messages.iter().find(|message| message["id"] == id);
+    &response.unwrap_or_else(|| panic!("No response to {id}"))["result"]
+}
+
+fn diagnostics(messages: &[Value]) -> Vec<&Value> {
+    messages
+        .iter()
+        .filter(|message| message["method"] == "textDocument/publishDiagnostics")
+        .map(|message| &message["params"]["diagnostics"])
+        .collect()
+}
+
+fn lines(locations: &Value) -> Vec<(u64, u64)> {
+    let locations = locations.as_array().unwrap();
+    let start = |location: &Value| {
+        let start = &location["range"]["start"];
+        (
+            start["line"].as_u64().unwrap(),
+            start["character"].as_u64().unwrap(),
+        )
+    };
+    locations.iter().map(start).collect()
+}
+
+#[test]
+fn initialize() {
+    let messages = exchange(&[]);
+    let capabilities = &response(&messages, 0)["capabilities"];
+    assert_eq!(capabilities["textDocumentSync"], 1);
+    assert_eq!(capabilities["hoverProvider"], true);
+    assert_eq!(response(&messages, u64::MAX), &Value::Null);
+}
+
+#[test]
+fn diagnostics_on_open_and_change() {
+    let change = |text: &str| {
+        notification(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": URI, "version": 2 },
+                "contentChanges": [{ "text": text }],
+            }),
+        )
+    };
+    let messages = exchange(&[
+        open("var counter = 1;\nprint countr;"),
+        change("var counter = 1;\nprint counter"),
+        change("var counter = 1;\nprint counter;"),
+    ]);
+    let published = diagnostics(&messages);
+    let [typo, parse_error, fixed] = published[..] else {
+        panic!("{published:?}");
+    };
+    assert_eq!(
+        typo,
+        &json!([{
+            "range": {
+                "start": { "line": 1, "character": 6 },
+                "end": { "line": 1, "character": 12 },
+            },
+            "severity": 2,
+            "code": "L0203",
+            "source": "rlox",
+            "message": "Undefined variable\ncountr is never defined\nDid you mean 'counter'?",
+            "relatedInformation": [],
+        }])
+    );
+    assert_eq!(parse_error[0]["severity"], 1);
+    assert_eq!(parse_error[0]["code"], "L0004");
+    assert_eq!(
+        parse_error[0]["relatedInformation"][0]["message"],
+        "Expected ;"
+    );
+    assert_eq!(fixed, &json!([]));
+}
+
+#[test]
+fn closing_clears_diagnostics() {
+    let messages = exchange(&[
+        open("print nope;"),
+        notification(
+            "textDocument/didClose",
+            json!({ "textDocument": { "uri": URI } }),
+        ),
+    ]);
+    let published = diagnostics(&messages);
+    assert_eq!(published.len(), 2);
+    assert_eq!(published[1], &json!([]));
+}
+
+#[test]
+fn document_symbols() {
+    let messages = exchange(&[
+        open(
+            "var total = 0;\nconst limit = 3;\nfun add(n) {\n    var local = n;\n    fun helper() {}\n    total += local;\n}\n{ fun nested() {} }",
+        ),
+        request(1, "textDocument/documentSymbol", json!({ "textDocument": { "uri": URI } })),
+    ]);
+    let symbols = response(&messages, 1).as_array().unwrap();
+    let summary: Vec<_> = symbols
+        .iter()
+        .map(|symbol| {
+            (
+                symbol["name"].as_str().unwrap(),
+                symbol["kind"].as_u64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [("total", 13), ("limit", 14), ("add", 12), ("nested", 12)]
+    );
+    let add = &symbols[2];
+    assert_eq!(add["detail"], "(n)");
+    assert_eq!(
+        add["selectionRange"]["start"],
+        json!({ "line": 2, "character": 4 })
+    );
+    // locals aren't symbols, but nested functions are
+    let children = add["children"].as_array().unwrap();
+    assert_eq!(children.len(), 1);
+    assert_eq!(children[0]["name"], "helper");
+}
+
+#[test]
+fn definition_and_references() {
+    let text = "var x = 1;\nfun f(x) {\n    return x + 1;\n}\nprint f(x) + x;";
+    let messages = exchange(&[
+        open(text),
+        // the parameter, from its use inside the function
+        at(1, "textDocument/definition", 2, 11),
+        // the global, from its use as an argument
+        at(2, "textDocument/definition", 4, 8),
+        at(3, "textDocument/references", 0, 4),
+        at(4, "textDocument/references", 1, 6),
+        // nothing's declared at a keyword
+        at(5, "textDocument/definition", 0, 1),
+    ]);
+    let definition = response(&messages, 1);
+    assert_eq!(definition["uri"], URI);
+    assert_eq!(lines(&json!([definition])), [(1, 6)]);
+    assert_eq!(lines(&json!([response(&messages, 2)])), [(0, 4)]);
+    assert_eq!(lines(response(&messages, 3)), [(0, 4), (4, 8), (4, 13)]);
+    assert_eq!(lines(response(&messages, 4)), [(1, 6), (2, 11)]);
+    assert_eq!(response(&messages, 5), &Value::Null);
+}
+
+#[test]
+fn positions_are_utf16() {
+    let messages = exchange(&[
+        open("var s = \"💩\"; var t = s;"),
+        // 💩 takes two UTF-16 code units, so s is at 22 rather than 21
+        at(1, "textDocument/definition", 0, 22),
+    ]);
+    assert_eq!(lines(&json!([response(&messages, 1)])), [(0, 4)]);
+}
+
+#[test]
+fn hover_shows_arity() {
+    let messages = exchange(&[
+        open("fun greet(name, greeting = \"Hi\") {}\nvar twice = (x) => x * 2;\ngreet(\"a\");\ntwice(1);\nvar n = 1;"),
+        at(1, "textDocument/hover", 2, 2),
+        at(2, "textDocument/hover", 3, 2),
+        at(3, "textDocument/hover", 4, 4),
+    ]);
+    let hover = response(&messages, 1);
+    assert_eq!(
+        hover["contents"]["value"],
+        "```lox\nfun greet(name, greeting = \"Hi\")\n```\nTakes 1 to 2 arguments"
+    );
+    assert_eq!(
+        hover["range"],
+        json!({ "start": { "line": 2, "character": 0 }, "end": { "line": 2, "character": 5 } })
+    );
+    assert_eq!(
+        response(&messages, 2)["contents"]["value"],
+        "```lox\nfun twice(x)\n```\nTakes 1 argument"
+    );
+    assert_eq!(response(&messages, 3), &Value::Null);
+}
+
+#[test]
+fn completion() {
+    let labels = |messages: &[Value], id| -> Vec<String> {
+        let items = response(messages, id).as_array().unwrap();
+        let label = |item: &Value| item["label"].as_str().unwrap().to_owned();
+        items.iter().map(label).collect()
+    };
+    let messages = exchange(&[
+        open("var global = 1;\nfun f(param) {\n    var local = 2;\n    \n}\n{ var hidden = 3; }\n"),
+        at(1, "textDocument/completion", 3, 4),
+        at(2, "textDocument/completion", 6, 0),
+    ]);
+    let inside = labels(&messages, 1);
+    for name in ["local", "param", "f", "global", "clock", "while", "fun"] {
+        assert!(
+            inside.iter().any(|label| label == name),
+            "{name} in {inside:?}"
+        );
+    }
+    assert!(!inside.iter().any(|label| label == "hidden"));
+    let outside = labels(&messages, 2);
+    assert!(!outside
+        .iter()
+        .any(|label| label == "local" || label == "param"));
+    let f = &response(&messages, 1).as_array().unwrap()[..];
+    let f = f.iter().find(|item| item["label"] == "f").unwrap();
+    assert_eq!(f["kind"], 3);
+}
+
+#[test]
+fn completion_without_parsing() {
+    let messages = exchange(&[
+        open("var something = 1;\nprint some"),
+        at(1, "textDocument/completion", 1, 10),
+    ]);
+    let items = response(&messages, 1).as_array().unwrap();
+    assert!(items.iter().any(|item| item["label"] == "something"));
+    assert!(items
+        .iter()
+        .any(|item| item["label"] == "print" && item["kind"] == 14));
+}
+
+#[test]
+fn unknown_requests() {
+    let messages = exchange(&[
+        request(1, "textDocument/formatting", json!({})),
+        at(2, "textDocument/hover", 0, 0),
+    ]);
+    let error = |id| &messages.iter().find(|m| m["id"] == id).unwrap()["error"]["code"];
+    assert_eq!(error(1), -32601);
+    // no document was opened
+    assert_eq!(error(2), -32602);
+}
+
+#[test]
+fn exit_without_shutdown() {
+    let input: String = [
+        request(0, "initialize", json!({ "capabilities": {} })),
+        notification("exit", Value::Null),
+    ]
+    .iter()
+    .map(frame)
+    .collect();
+    Command::cargo_bin("rlox")
+        .unwrap()
+        .arg("lsp")
+        .write_stdin(input)
+        .assert()
+        .failure();
+}